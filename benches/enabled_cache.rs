@@ -0,0 +1,108 @@
+//! Measures the cost of `enabled` with a complex per-target filter
+//! configuration, comparing 20 recurring targets, whose results are cached,
+//! with targets that are never repeated, which always miss the cache.
+//!
+//! The baseline is the lookup used before the cache was added, which
+//! searched the sorted filter entries for each ancestor of the target. It
+//! is measured on top of `enabled` of a logger without target filters, so
+//! that both include the rest of `enabled`.
+//!
+//! ```text
+//! cargo bench --bench enabled_cache
+//! ```
+use log::{Level, LevelFilter, Log};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+use windebug_logger::{WinDebugLogger, WinDebugLoggerBuilder};
+
+const ITERATIONS: usize = 1_000_000;
+
+fn main() {
+    let mut entries = vec![("app".to_owned(), LevelFilter::Info)];
+    for i in 0..10 {
+        entries.push((format!("app::mod{}", i), LevelFilter::Debug));
+        entries.push((format!("app::mod{}::sub", i), LevelFilter::Warn));
+        entries.push((format!("dep{}", i), LevelFilter::Error));
+    }
+    let builder = entries.iter().fold(
+        WinDebugLoggerBuilder::new().level(LevelFilter::Info),
+        |builder, (target, level)| builder.target_level(target, *level),
+    );
+    let logger = WinDebugLogger::from_builder(&builder);
+
+    let plain =
+        WinDebugLogger::from_builder(&WinDebugLoggerBuilder::new().level(LevelFilter::Info));
+    let baseline = Baseline::new(entries);
+    let enabled_baseline = |metadata: &log::Metadata| {
+        // The target's entry, if any, takes precedence over the level filter
+        let enabled = plain.enabled(metadata);
+        match baseline.level_for(metadata.target()) {
+            Some(level) => metadata.level() <= level,
+            None => enabled,
+        }
+    };
+
+    let recurring: Vec<String> = (0..20)
+        .map(|i| format!("app::mod{}::sub::leaf{}", i % 12, i))
+        .collect();
+    // More than the cache can hold, so that every lookup misses
+    let distinct: Vec<String> = (0..10_000)
+        .map(|i| format!("app::mod{}::sub::leaf{}", i % 12, i))
+        .collect();
+
+    report("cached", measure(|m| logger.enabled(m), &recurring));
+    report("uncached", measure(|m| logger.enabled(m), &distinct));
+    report("old, recurring", measure(enabled_baseline, &recurring));
+    report("old, distinct", measure(enabled_baseline, &distinct));
+}
+
+/// The target filter before the cache was added.
+struct Baseline {
+    /// Sorted by target.
+    entries: Vec<(String, LevelFilter)>,
+}
+
+impl Baseline {
+    fn new(mut entries: Vec<(String, LevelFilter)>) -> Self {
+        entries.sort();
+        Self { entries }
+    }
+
+    fn level_for(&self, target: &str) -> Option<LevelFilter> {
+        std::iter::successors(Some(target), |prefix| {
+            prefix.rfind("::").map(|i| &prefix[..i])
+        })
+        .find_map(|prefix| {
+            let i = self
+                .entries
+                .binary_search_by(|(t, _)| t.as_str().cmp(prefix))
+                .ok()?;
+            Some(self.entries[i].1)
+        })
+    }
+}
+
+fn measure(enabled: impl Fn(&log::Metadata) -> bool, targets: &[String]) -> Duration {
+    let mut enabled = |i: usize| {
+        let metadata = log::Metadata::builder()
+            .target(&targets[i % targets.len()])
+            .level(Level::Debug)
+            .build();
+        black_box(enabled(&metadata));
+    };
+    // Warm up
+    (0..targets.len()).for_each(&mut enabled);
+    let start = Instant::now();
+    (0..ITERATIONS).for_each(&mut enabled);
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:<16} {:>8.1} ns/call",
+        name,
+        elapsed.as_nanos() as f64 / ITERATIONS as f64
+    );
+}
@@ -0,0 +1,71 @@
+//! Measures the per-call overhead of `log` for records that are filtered
+//! out, which `log_record` rejects before reaching the out-of-line
+//! `log_cold`, next to the cost of a record that is formatted and output.
+//!
+//! The baseline stands in for the code layout used before `log_cold` was
+//! split out, in which the filters and the output shared one function. It
+//! is rebuilt here from the public API as one out-of-line function that
+//! checks `enabled` before `log`.
+//!
+//! ```text
+//! cargo bench --bench filtered_records
+//! ```
+use log::{Level, LevelFilter, Log};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+use windebug_logger::{FnSink, Sinks, WinDebugLogger, WinDebugLoggerBuilder};
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn main() {
+    // Measure the logger, not the debugger
+    let logger = WinDebugLogger::from_builder(
+        &WinDebugLoggerBuilder::new()
+            .level(LevelFilter::Info)
+            .deny_targets(&["noisy"])
+            .timestamp_fn(|out| out.push_str("now"))
+            .sink(FnSink(|_| {}))
+            .route(LevelFilter::Off, Sinks::ODS),
+    );
+    let new = |record: &log::Record| logger.log(record);
+    let old = |record: &log::Record| log_baseline(&logger, record);
+
+    for (name, level, target) in [
+        ("below level", Level::Debug, "app"),
+        ("denied", Level::Info, "noisy::inner"),
+        ("output", Level::Info, "app"),
+    ] {
+        report(name, measure(new, level, target));
+        report(&format!("{} (old)", name), measure(old, level, target));
+    }
+}
+
+/// The filters and the output in one function, as before `log_cold`.
+#[inline(never)]
+fn log_baseline(logger: &WinDebugLogger, record: &log::Record) {
+    if logger.enabled(record.metadata()) {
+        logger.log(record);
+    }
+}
+
+fn measure(f: impl Fn(&log::Record), level: Level, target: &str) -> Duration {
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        f(&log::Record::builder()
+            .args(format_args!("iteration {}", black_box(i)))
+            .level(level)
+            .target(target)
+            .build());
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:<18} {:>8.1} ns/call",
+        name,
+        elapsed.as_nanos() as f64 / ITERATIONS as f64
+    );
+}
@@ -0,0 +1,105 @@
+//! Compares the throughput of messages that are plain string literals, which
+//! take the fast path, with that of equivalent interpolated messages. The
+//! message step is also measured against the previous implementation, which
+//! formatted every message with `write!`.
+//!
+//! ```text
+//! cargo bench --bench literal_message
+//! ```
+use log::{Level, LevelFilter, Log};
+use std::{
+    fmt::Write,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+use windebug_logger::{FnSink, Sinks, WinDebugLogger, WinDebugLoggerBuilder};
+
+const ITERATIONS: u32 = 200_000;
+
+fn main() {
+    // Measure formatting, not the debugger
+    let logger = WinDebugLogger::from_builder(
+        &WinDebugLoggerBuilder::new()
+            .timestamp_fn(|out| out.push_str("now"))
+            .sink(FnSink(|_| {}))
+            .route(LevelFilter::Off, Sinks::ODS),
+    );
+
+    let word = "up";
+    let literal = measure(|| {
+        logger.log(
+            &log::Record::builder()
+                .args(format_args!("starting up"))
+                .level(Level::Info)
+                .target("bench")
+                .build(),
+        )
+    });
+    let interpolated = measure(|| {
+        logger.log(
+            &log::Record::builder()
+                .args(format_args!("starting {}", word))
+                .level(Level::Info)
+                .target("bench")
+                .build(),
+        )
+    });
+
+    let mut out = String::with_capacity(64);
+    let mut message = |push: fn(&mut String, &log::Record)| {
+        measure(|| {
+            out.clear();
+            push(
+                &mut out,
+                &log::Record::builder()
+                    .args(format_args!("starting up"))
+                    .build(),
+            );
+            black_box(&out);
+        })
+    };
+    let message_fast = message(push_message);
+    let message_baseline = message(push_message_baseline);
+
+    report("literal", literal);
+    report("interpolated", interpolated);
+    report("message", message_fast);
+    report("message (old)", message_baseline);
+}
+
+/// A copy of the crate's private `format::push_message`. Its tests check
+/// that it matches `write!`.
+fn push_message(out: &mut String, record: &log::Record) {
+    match record.args().as_str() {
+        Some(message) => out.push_str(message),
+        None => {
+            let _ = write!(out, "{}", record.args());
+        }
+    }
+}
+
+/// The message step before the fast path was added.
+fn push_message_baseline(out: &mut String, record: &log::Record) {
+    let _ = write!(out, "{}", record.args());
+}
+
+fn measure(mut f: impl FnMut()) -> Duration {
+    // Warm up
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:<14} {:>8.1} ns/record {:>10.0} records/s",
+        name,
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS),
+        f64::from(ITERATIONS) / elapsed.as_secs_f64()
+    );
+}
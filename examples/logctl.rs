@@ -0,0 +1,49 @@
+//! Enables or disables logging in a running process that was configured with
+//! `WinDebugLoggerBuilder::enable_event`.
+//!
+//! ```text
+//! logctl <event-name> on|off
+//! ```
+use std::{env, process::exit};
+use winapi::{
+    shared::minwindef::FALSE,
+    um::{handleapi, synchapi, winnt},
+};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (name, enable) = match args.as_slice() {
+        [_, name, state] if state == "on" => (name, true),
+        [_, name, state] if state == "off" => (name, false),
+        _ => {
+            eprintln!("usage: logctl <event-name> on|off");
+            exit(2);
+        }
+    };
+
+    let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+
+    unsafe {
+        let handle = synchapi::OpenEventW(winnt::EVENT_MODIFY_STATE, FALSE, name.as_ptr());
+        if handle.is_null() {
+            eprintln!(
+                "logctl: could not open the event object (is the process running?): {}",
+                std::io::Error::last_os_error()
+            );
+            exit(1);
+        }
+
+        let ok = if enable {
+            synchapi::SetEvent(handle)
+        } else {
+            synchapi::ResetEvent(handle)
+        };
+        let error = std::io::Error::last_os_error();
+        handleapi::CloseHandle(handle);
+
+        if ok == 0 {
+            eprintln!("logctl: could not change the event state: {}", error);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,25 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+use windebug_logger::codecvt::{os_str_to_c_wstr, wvec_to_string, CodecvtError};
+
+fuzz_target!(|data: &[u8]| {
+    // Arbitrary UTF-16 code units, including unpaired surrogates
+    let wide: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    let s = OsString::from_wide(&wide);
+    let valid = String::from_utf16(&wide).is_ok();
+
+    let replaced = os_str_to_c_wstr(&s, CodecvtError::Replace).unwrap();
+    assert_eq!(replaced.last(), Some(&0));
+    assert!(wvec_to_string(&replaced, CodecvtError::Reject).is_some());
+
+    let rejected = os_str_to_c_wstr(&s, CodecvtError::Reject);
+    assert_eq!(rejected.is_some(), valid);
+    if let Some(rejected) = rejected {
+        assert_eq!(rejected, replaced);
+        assert_eq!(&rejected[..wide.len()], &wide[..]);
+    }
+});
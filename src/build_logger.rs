@@ -0,0 +1,97 @@
+//! A logger for build scripts. See [`BuildScriptLogger`].
+use log::{Level, Log, Metadata, Record, SetLoggerError};
+use std::io::{self, Write};
+
+/// A logger that writes records as `cargo:warning=` directives, which Cargo
+/// shows in its output, for use in build scripts (`build.rs`).
+///
+/// With the `build-script` feature, [`init_with_level_static!`] installs
+/// this logger instead of [`WinDebugLogger`], whose output a build script's
+/// user would never see.
+///
+/// [`init_with_level_static!`]: crate::init_with_level_static
+/// [`WinDebugLogger`]: crate::WinDebugLogger
+#[derive(Debug)]
+pub struct BuildScriptLogger {
+    level: Level,
+}
+
+impl BuildScriptLogger {
+    /// Construct a `BuildScriptLogger` logging the records at `level` or
+    /// more severe.
+    pub const fn new(level: Level) -> Self {
+        Self { level }
+    }
+}
+
+impl Log for BuildScriptLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut stdout = io::stdout().lock();
+        for line in format_directives(record) {
+            let _ = writeln!(stdout, "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Format `record` as `cargo:warning=` directives, one for each line of the
+/// message since a directive cannot span lines.
+fn format_directives(record: &Record) -> Vec<String> {
+    let message = record.args().to_string();
+    let mut lines: Vec<&str> = message.lines().collect();
+    if lines.is_empty() {
+        lines.push("");
+    }
+    lines
+        .into_iter()
+        .map(|line| {
+            format!(
+                "cargo:warning={:<5} [{}] {}",
+                record.level(),
+                record.target(),
+                line
+            )
+        })
+        .collect()
+}
+
+/// Used by `init_with_level_static!`.
+#[doc(hidden)]
+pub fn __install_build_script(logger: &'static BuildScriptLogger) -> Result<(), SetLoggerError> {
+    log::set_logger(logger)?;
+    log::set_max_level(logger.level.to_level_filter());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_directives() {
+        let directives = format_directives(
+            &Record::builder()
+                .args(format_args!("first\nsecond"))
+                .level(Level::Warn)
+                .target("build")
+                .build(),
+        );
+        assert_eq!(
+            directives,
+            [
+                "cargo:warning=WARN  [build] first",
+                "cargo:warning=WARN  [build] second",
+            ]
+        );
+    }
+}
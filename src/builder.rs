@@ -0,0 +1,1491 @@
+use crate::{
+    filter,
+    sink::{self, DebugSink, SinkEntry, Sinks},
+    CustomFormatter, CustomTimestamp, Error, FormatFn, LevelStyle, LoggerHandle, OutputFormat,
+    ThreadFilter, WinDebugLogger,
+};
+use log::{Level, LevelFilter, SetLoggerError};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+/// Configures and installs the global logger.
+///
+/// ```
+/// # use std::time::Duration;
+/// # fn main() {
+/// let handle = windebug_logger::WinDebugLoggerBuilder::new()
+///     .level(log::LevelFilter::Warn)
+///     .trace_window(Duration::from_secs(30))
+///     .init()
+///     .unwrap();
+///
+/// // Raise the level filter at runtime
+/// handle.set_level(log::LevelFilter::Info);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WinDebugLoggerBuilder {
+    pub(crate) level: LevelFilter,
+    pub(crate) target_filter: filter::TargetFilter,
+    pub(crate) allowed_targets: Option<HashSet<String>>,
+    pub(crate) denied_targets: Option<HashSet<String>>,
+    pub(crate) thread_filter: Option<ThreadFilter>,
+    pub(crate) trace_window: Option<Duration>,
+    pub(crate) trace_window_level: LevelFilter,
+    pub(crate) enable_event: Option<String>,
+    pub(crate) output_format: OutputFormat,
+    pub(crate) level_style: LevelStyle,
+    /// Indexed by `Level as usize - 1`.
+    pub(crate) level_symbols: [String; 5],
+    pub(crate) utc: bool,
+    pub(crate) locale: u32,
+    pub(crate) log_environment: bool,
+    pub(crate) suppress_duplicates: Option<Duration>,
+    pub(crate) suppress_duplicates_capacity: usize,
+    pub(crate) sampling: HashMap<String, u32>,
+    pub(crate) debug_on_error: Option<usize>,
+    pub(crate) debug_on_error_level: LevelFilter,
+    pub(crate) banner: bool,
+    pub(crate) log_on_init: bool,
+    pub(crate) app_version: Option<String>,
+    pub(crate) custom_formatter: Option<CustomFormatter>,
+    pub(crate) default_target: String,
+    #[cfg(feature = "time")]
+    pub(crate) timestamp_format: Option<time::format_description::OwnedFormatItem>,
+    pub(crate) split_level_tag: bool,
+    pub(crate) elide_repeated_targets: bool,
+    pub(crate) max_message_len: Option<usize>,
+    pub(crate) source_location: bool,
+    pub(crate) span_indent: usize,
+    pub(crate) frame_number: bool,
+    pub(crate) debugview_plus_plus: bool,
+    pub(crate) custom_timestamp: Option<CustomTimestamp>,
+    #[cfg(feature = "kv")]
+    pub(crate) timestamp_key: Option<String>,
+    pub(crate) routes: sink::Routes,
+    pub(crate) sinks: Vec<SinkEntry>,
+    pub(crate) dry_run: bool,
+    pub(crate) skip_when_unattended: bool,
+    pub(crate) flush_on_error: bool,
+    pub(crate) ansi_colors: bool,
+    pub(crate) module_name: bool,
+    pub(crate) name: Cow<'static, str>,
+    pub(crate) ring_buffer: Option<usize>,
+    pub(crate) session_id: bool,
+    pub(crate) coalesce: Option<(usize, usize, Duration)>,
+    pub(crate) flush_interval: Option<Duration>,
+    pub(crate) heartbeat: Option<Duration>,
+    pub(crate) code_page: Option<u32>,
+    pub(crate) initial_buffer_capacity: usize,
+}
+
+impl Default for WinDebugLoggerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The basic settings of [`WinDebugLoggerBuilder`], for applications that
+/// manage their configuration separately and want to populate the builder
+/// from their own configuration type. See
+/// [`WinDebugLoggerBuilder::new_from_default`].
+///
+/// Each field corresponds to the builder method of the same name, and
+/// [`Default::default`] returns the builder's defaults. This is
+/// `#[non_exhaustive]`, so start with `Default::default()` and assign the
+/// fields to change:
+///
+/// ```
+/// use windebug_logger::{LevelStyle, WinDebugLoggerBuilder, WinDebugLoggerDefaults};
+///
+/// let mut defaults = WinDebugLoggerDefaults::default();
+/// defaults.level = log::LevelFilter::Info;
+/// defaults.level_style = LevelStyle::Bracketed;
+/// let builder = WinDebugLoggerBuilder::new_from_default(defaults);
+/// ```
+///
+/// Unlike [`Config`](crate::Config), this covers only the format and filter
+/// settings. The others, such as sinks, are applied to the builder as usual.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WinDebugLoggerDefaults {
+    pub level: LevelFilter,
+    pub output_format: OutputFormat,
+    pub level_style: LevelStyle,
+    pub utc: bool,
+    pub locale: u32,
+    pub session_id: bool,
+    pub split_level_tag: bool,
+    pub elide_repeated_targets: bool,
+    /// See [`WinDebugLoggerBuilder::with_max_message_len`].
+    pub max_message_len: Option<usize>,
+    pub source_location: bool,
+    pub span_indent: usize,
+    pub frame_number: bool,
+    /// See [`WinDebugLoggerBuilder::with_default_target`].
+    pub default_target: String,
+    /// See [`WinDebugLoggerBuilder::with_module_name`].
+    pub module_name: bool,
+}
+
+impl Default for WinDebugLoggerDefaults {
+    /// Get the settings equivalent to `WinDebugLoggerBuilder::new()`.
+    fn default() -> Self {
+        let builder = WinDebugLoggerBuilder::new();
+        Self {
+            level: builder.level,
+            output_format: builder.output_format,
+            level_style: builder.level_style,
+            utc: builder.utc,
+            locale: builder.locale,
+            session_id: builder.session_id,
+            split_level_tag: builder.split_level_tag,
+            elide_repeated_targets: builder.elide_repeated_targets,
+            max_message_len: builder.max_message_len,
+            source_location: builder.source_location,
+            span_indent: builder.span_indent,
+            frame_number: builder.frame_number,
+            default_target: builder.default_target,
+            module_name: builder.module_name,
+        }
+    }
+}
+
+impl WinDebugLoggerBuilder {
+    /// Construct a `WinDebugLoggerBuilder` with the settings given by
+    /// `defaults`. The settings not covered by [`WinDebugLoggerDefaults`]
+    /// take the same defaults as [`new`](Self::new).
+    pub fn new_from_default(defaults: WinDebugLoggerDefaults) -> Self {
+        Self {
+            level: defaults.level,
+            output_format: defaults.output_format,
+            level_style: defaults.level_style,
+            utc: defaults.utc,
+            locale: defaults.locale,
+            session_id: defaults.session_id,
+            split_level_tag: defaults.split_level_tag,
+            elide_repeated_targets: defaults.elide_repeated_targets,
+            max_message_len: defaults.max_message_len,
+            source_location: defaults.source_location,
+            span_indent: defaults.span_indent,
+            frame_number: defaults.frame_number,
+            default_target: defaults.default_target,
+            module_name: defaults.module_name,
+            ..Self::new()
+        }
+    }
+
+    /// Construct a `WinDebugLoggerBuilder` with the default configuration,
+    /// which logs everything.
+    pub fn new() -> Self {
+        Self {
+            level: LevelFilter::Trace,
+            target_filter: filter::TargetFilter::new(),
+            allowed_targets: None,
+            denied_targets: None,
+            thread_filter: None,
+            trace_window: None,
+            trace_window_level: LevelFilter::Trace,
+            enable_event: None,
+            output_format: OutputFormat::Text,
+            level_style: LevelStyle::Label,
+            level_symbols: Default::default(),
+            utc: true,
+            locale: winapi::um::winnt::LOCALE_INVARIANT,
+            log_environment: false,
+            suppress_duplicates: None,
+            suppress_duplicates_capacity: 16,
+            sampling: HashMap::new(),
+            debug_on_error: None,
+            debug_on_error_level: LevelFilter::Trace,
+            banner: false,
+            log_on_init: false,
+            app_version: None,
+            custom_formatter: None,
+            default_target: String::new(),
+            #[cfg(feature = "time")]
+            timestamp_format: None,
+            split_level_tag: false,
+            elide_repeated_targets: false,
+            max_message_len: None,
+            source_location: false,
+            span_indent: 0,
+            frame_number: false,
+            debugview_plus_plus: false,
+            custom_timestamp: None,
+            #[cfg(feature = "kv")]
+            timestamp_key: None,
+            routes: sink::Routes::new(),
+            sinks: Vec::new(),
+            dry_run: false,
+            skip_when_unattended: false,
+            flush_on_error: false,
+            ansi_colors: false,
+            module_name: false,
+            name: Cow::Borrowed(""),
+            ring_buffer: None,
+            session_id: false,
+            coalesce: None,
+            flush_interval: None,
+            heartbeat: None,
+            code_page: None,
+            initial_buffer_capacity: 256,
+        }
+    }
+
+    /// Construct a `WinDebugLoggerBuilder` from a [`Config`], e.g., one
+    /// deserialized from the application's settings file with the `serde`
+    /// feature, or one populated from the application's own configuration
+    /// type.
+    ///
+    /// This fails if a sink specified by `config` cannot be opened.
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), windebug_logger::Error> {
+    /// let mut config = windebug_logger::Config::default();
+    /// config.level = log::LevelFilter::Info;
+    /// config.stderr = true;
+    /// windebug_logger::WinDebugLoggerBuilder::from_config(config)?.init()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Config`]: crate::Config
+    pub fn from_config(config: crate::Config) -> Result<Self, Error> {
+        let mut builder = Self {
+            level: config.level,
+            trace_window: config.trace_window_ms.map(Duration::from_millis),
+            trace_window_level: config.trace_window_level,
+            enable_event: config.enable_event,
+            output_format: config.output_format,
+            level_style: config.level_style,
+            utc: config.utc,
+            locale: config.locale,
+            session_id: config.session_id,
+            log_environment: config.log_environment,
+            suppress_duplicates: config.suppress_duplicates_ms.map(Duration::from_millis),
+            suppress_duplicates_capacity: config.suppress_duplicates_capacity,
+            sampling: config.sampling.into_iter().collect(),
+            debug_on_error: config.debug_on_error,
+            debug_on_error_level: config.debug_on_error_level,
+            banner: config.banner,
+            log_on_init: config.log_on_init,
+            app_version: config.app_version,
+            split_level_tag: config.split_level_tag,
+            elide_repeated_targets: config.elide_repeated_targets,
+            max_message_len: config.max_message_len,
+            source_location: config.source_location,
+            span_indent: config.span_indent,
+            frame_number: config.frame_number,
+            debugview_plus_plus: config.debugview_plus_plus,
+            heartbeat: config.heartbeat_ms.map(Duration::from_millis),
+            default_target: config.default_target,
+            code_page: config.code_page,
+            initial_buffer_capacity: config.initial_buffer_capacity,
+            ring_buffer: config.ring_buffer,
+            dry_run: config.dry_run,
+            skip_when_unattended: config.skip_when_unattended,
+            flush_on_error: config.flush_on_error,
+            ansi_colors: config.ansi_colors,
+            module_name: config.module_name,
+            name: config.name.into(),
+            ..Self::new()
+        };
+        if let Some(targets) = &config.allow_targets {
+            let targets: Vec<&str> = targets.iter().map(String::as_str).collect();
+            builder = builder.allow_targets(&targets);
+        }
+        if let Some(targets) = &config.deny_targets {
+            let targets: Vec<&str> = targets.iter().map(String::as_str).collect();
+            builder = builder.deny_targets(&targets);
+        }
+        for (target, level) in config.targets {
+            builder = builder.target_level(target, level);
+        }
+        for (level, symbol) in config.level_symbols {
+            builder = builder.level_symbol(level, symbol);
+        }
+        if let Some(path) = &config.file {
+            builder = builder.file(path).map_err(|e| Error::io(path, e))?;
+        }
+        builder = builder.stderr(config.stderr);
+        if let Some(source) = &config.event_log {
+            builder = builder.event_log(source)?;
+        }
+        if let Some(name) = &config.pipe {
+            builder = builder.pipe(name);
+        }
+        Ok(builder)
+    }
+
+    /// Construct a `WinDebugLoggerBuilder` from a [`Config`] read from the
+    /// JSON file at `path`. Requires the `json` feature.
+    ///
+    /// The builder itself cannot be serialized because it holds closures
+    /// and open sinks; [`Config`] is its serializable counterpart. Unlike
+    /// [`init_from_file`], this fails if the file does not exist.
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), windebug_logger::Error> {
+    /// use std::path::Path;
+    ///
+    /// windebug_logger::WinDebugLoggerBuilder::from_config_file(Path::new("logging.json"))?
+    ///     .init()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Config`]: crate::Config
+    /// [`init_from_file`]: crate::init_from_file
+    #[cfg(feature = "json")]
+    pub fn from_config_file(path: &Path) -> Result<Self, Error> {
+        let file = std::fs::File::open(path).map_err(|e| Error::io(path, e))?;
+        let config = serde_json::from_reader(io::BufReader::new(file))
+            .map_err(|e| crate::config::json_error(path, e))?;
+        Self::from_config(config)
+    }
+
+    /// Set the level filter. If a trace window is configured, this is the
+    /// level filter that takes effect after the window has elapsed.
+    pub fn level(self, level: LevelFilter) -> Self {
+        Self { level, ..self }
+    }
+
+    /// Set the level filter of the records whose target is `target` or one
+    /// of its descendants (e.g., `app::net` for `app`), overriding
+    /// [`level`](Self::level) and the trace window. Calling this again for
+    /// the same `target` replaces the previous level filter.
+    ///
+    /// The targets form a hierarchy: a record uses the level filter of its
+    /// most specific (longest) matching target, regardless of the order of
+    /// the calls. In the example below, `app::net::tls` is traced, and
+    /// `app::net::http` inherits `debug` from `app`.
+    ///
+    /// ```no_run
+    /// use log::LevelFilter;
+    ///
+    /// # fn main() {
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .level(LevelFilter::Info)
+    ///     .target_level("app::net::tls", LevelFilter::Trace)
+    ///     .target_level("app", LevelFilter::Debug)
+    ///     .target_level("hyper", LevelFilter::Warn)
+    ///     .init()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub fn target_level(mut self, target: impl Into<String>, level: LevelFilter) -> Self {
+        self.target_filter.insert(target.into(), level);
+        self
+    }
+
+    /// Discard all records except those whose target is one of `targets` or
+    /// their descendants (e.g., `app::db::pool` for `app::db`), regardless of
+    /// their levels. By default, records of all targets are logged.
+    ///
+    /// ```no_run
+    /// # fn main() {
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .allow_targets(&["myapp", "mylib::db"])
+    ///     .init()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    ///
+    /// Calling this again replaces the list. See [`deny_targets`] for how
+    /// this interacts with the deny-list.
+    ///
+    /// [`deny_targets`]: Self::deny_targets
+    pub fn allow_targets(self, targets: &[&str]) -> Self {
+        Self {
+            allowed_targets: Some(targets.iter().map(|&t| t.to_owned()).collect()),
+            ..self
+        }
+    }
+
+    /// Discard all records whose target is one of `targets` or their
+    /// descendants, regardless of their levels.
+    ///
+    /// ```no_run
+    /// # fn main() {
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .deny_targets(&["hyper", "myapp::noisy"])
+    ///     .init()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    ///
+    /// Records are filtered in the following order:
+    ///
+    ///  1. The deny-list. A record is discarded if its target matches an
+    ///     entry of the deny-list, unless it also matches an equally or more
+    ///     specific entry of the allow-list (e.g., `hyper::client` when
+    ///     `hyper` is denied). That is, the allow-list wins on a conflict.
+    ///  2. The allow-list set by [`allow_targets`], if any. A record is
+    ///     discarded unless its target matches one of its entries.
+    ///  3. The level filter of the target set by [`target_level`], if any.
+    ///  4. The global level filter.
+    ///
+    /// Calling this again replaces the list.
+    ///
+    /// [`allow_targets`]: Self::allow_targets
+    /// [`target_level`]: Self::target_level
+    pub fn deny_targets(self, targets: &[&str]) -> Self {
+        Self {
+            denied_targets: Some(targets.iter().map(|&t| t.to_owned()).collect()),
+            ..self
+        }
+    }
+
+    /// Discard all records logged by threads other than the ones selected
+    /// by `filter`, e.g., to follow a bug on the UI thread without the noise
+    /// of worker threads.
+    ///
+    /// ```no_run
+    /// use windebug_logger::{ThreadFilter, WinDebugLoggerBuilder};
+    ///
+    /// # fn main() {
+    /// WinDebugLoggerBuilder::new()
+    ///     .only_threads(ThreadFilter::MainThreadOnly)
+    ///     .init()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    ///
+    /// The filter is evaluated once per thread, and the result is cached
+    /// until the filter is replaced by [`LoggerHandle::set_thread_filter`]
+    /// or the main thread is changed by
+    /// [`mark_main_thread`](crate::mark_main_thread). The discarded records
+    /// are counted in the output of [`log_stats`](crate::log_stats).
+    ///
+    /// [`LoggerHandle::set_thread_filter`]: crate::LoggerHandle::set_thread_filter
+    pub fn only_threads(self, filter: ThreadFilter) -> Self {
+        Self {
+            thread_filter: Some(filter),
+            ..self
+        }
+    }
+
+    /// Apply a comma-separated list of level filter directives, e.g.,
+    /// `info,app::net=trace,hyper=warn`, as found in an environment variable
+    /// or a command-line flag.
+    ///
+    /// A directive of the form `target=level` calls
+    /// [`target_level`](Self::target_level), a bare level (see
+    /// [`parse_level`](crate::parse_level)) calls [`level`](Self::level),
+    /// and a bare target enables all of its records.
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), windebug_logger::Error> {
+    /// let spec = std::env::var("APP_LOG").unwrap_or_default();
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .filters(&spec)?
+    ///     .init()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn filters(mut self, spec: &str) -> Result<Self, Error> {
+        let (level, targets) = filter::parse_directives(spec)?;
+        if let Some(level) = level {
+            self.level = level;
+        }
+        for (target, level) in targets.iter() {
+            self.target_filter.insert(target.to_owned(), level);
+        }
+        Ok(self)
+    }
+
+    /// Use a different level filter (`LevelFilter::Trace` by default; see
+    /// [`trace_window_level`]) for the specified duration after
+    /// initialization.
+    ///
+    /// The transition happens lazily, when the first record after the window
+    /// arrives, and is announced by a single informational record. Both level
+    /// filters can be changed afterwards through [`LoggerHandle`].
+    ///
+    /// [`trace_window_level`]: Self::trace_window_level
+    pub fn trace_window(self, duration: Duration) -> Self {
+        Self {
+            trace_window: Some(duration),
+            ..self
+        }
+    }
+
+    /// Set the level filter used during the trace window.
+    pub fn trace_window_level(self, trace_window_level: LevelFilter) -> Self {
+        Self {
+            trace_window_level,
+            ..self
+        }
+    }
+
+    /// Use the signaled state of the named manual-reset event object `name`
+    /// as the master switch of the logger. The event object is created in the
+    /// non-signaled state (i.e., logging is disabled) unless it already
+    /// exists.
+    ///
+    /// This allows logging to be toggled from outside the process (e.g., by
+    /// the `logctl` example in this crate's repository). Prefix the name with
+    /// `Global\` to make it accessible from other sessions.
+    ///
+    /// The state is cached for a few hundred milliseconds, so a change may
+    /// not take effect immediately. If the event object cannot be created,
+    /// this option is ignored.
+    pub fn enable_event(self, name: impl Into<String>) -> Self {
+        Self {
+            enable_event: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Set the layout of lines.
+    ///
+    /// ```text
+    /// OutputFormat::Text:   01/02/2020 12:34:56 WARN  [app] disk is full
+    /// OutputFormat::Logfmt: ts="01/02/2020 12:34:56" level=warn target=app msg="disk is full"
+    /// ```
+    pub fn output_format(self, output_format: OutputFormat) -> Self {
+        Self {
+            output_format,
+            ..self
+        }
+    }
+
+    /// Set how the level of each record is rendered.
+    ///
+    /// ```text
+    /// LevelStyle::Label:     01/02/2020 12:34:56 WARN  [app] message
+    /// LevelStyle::Bracketed: 01/02/2020 12:34:56 <W> [app] message
+    /// ```
+    pub fn level_style(self, level_style: LevelStyle) -> Self {
+        Self {
+            level_style,
+            ..self
+        }
+    }
+
+    /// Show `symbol` before the level of each record at `level`, e.g., to
+    /// make errors stand out in a dense capture. No symbols are shown by
+    /// default.
+    ///
+    /// ```
+    /// use log::Level;
+    /// use windebug_logger::WinDebugLoggerBuilder;
+    ///
+    /// let builder = WinDebugLoggerBuilder::new()
+    ///     .level_symbol(Level::Error, "\u{2716}")
+    ///     .level_symbol(Level::Warn, "\u{26a0}")
+    ///     .level_symbol(Level::Info, "\u{2139}");
+    /// ```
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 ✖ ERROR [app] message
+    /// ```
+    pub fn level_symbol(mut self, level: Level, symbol: impl Into<String>) -> Self {
+        self.level_symbols[level as usize - 1] = symbol.into();
+        self
+    }
+
+    /// Choose between rendering timestamps in UTC (`GetSystemTime`; the
+    /// default) and in the local time zone (`GetLocalTime`).
+    pub fn utc(self, utc: bool) -> Self {
+        Self { utc, ..self }
+    }
+
+    /// Set the locale identifier used for rendering timestamps with
+    /// `GetDateFormatW` and `GetTimeFormatW`. Defaults to
+    /// `LOCALE_INVARIANT` (`0x007f`), which produces a fixed format such as
+    /// `01/02/2020 12:34:56`.
+    ///
+    /// Pass `LOCALE_USER_DEFAULT` (`0x0400`) to follow the user's regional
+    /// settings. Note that locale-sensitive formats may contain non-ASCII
+    /// characters, such as the era and AM/PM designators of some locales.
+    ///
+    /// This has no effect if the built-in timestamp is compiled out (see
+    /// the `timestamps` and `no-timestamps` features).
+    pub fn locale(self, locale: u32) -> Self {
+        Self { locale, ..self }
+    }
+
+    /// Include the session ID in each line. Disabled by default.
+    ///
+    /// The session ID is a random string of six characters generated when
+    /// the logger is installed. Unlike the process ID, which the system may
+    /// reuse, it tells apart the runs of the same program, e.g., when
+    /// correlating the output of multiple processes across restarts. It is
+    /// shown in the [`banner`] and returned by [`LoggerHandle::session_id`]
+    /// regardless of this option.
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 k3x9qa INFO  [app] message
+    /// ```
+    ///
+    /// [`banner`]: Self::banner
+    pub fn session_id(self, session_id: bool) -> Self {
+        Self { session_id, ..self }
+    }
+
+    /// Group consecutive lines into a single `OutputDebugStringW` call.
+    /// Disabled by default.
+    ///
+    /// Each `OutputDebugStringW` call raises an exception that an attached
+    /// debugger has to handle, which visibly slows down a debuggee emitting
+    /// many small records. In this mode, lines are accumulated and written
+    /// as one newline-separated string when `max_records` lines are pending,
+    /// when adding another line would make it longer than `max_bytes` (two
+    /// bytes per UTF-16 code unit, or the converted length with
+    /// [`code_page`], capped at 4000 so that it fits in the debugger's
+    /// buffer), or when a line is added `max_delay` or later
+    /// after the oldest pending line. Lines are never reordered.
+    ///
+    /// Since `max_delay` is only checked when a line is added, the last
+    /// lines may stay pending indefinitely if the application goes quiet.
+    /// Use [`flush_interval`] to write them out in the background.
+    ///
+    /// Pending lines are also written by `log::logger().flush()` and
+    /// [`LoggerHandle::shutdown`], but not when the process exits. Call one
+    /// of them before exiting. Other sinks are not affected, and neither is
+    /// [`split_level_tag`], which takes precedence.
+    ///
+    /// [`flush_interval`]: Self::flush_interval
+    /// [`split_level_tag`]: Self::split_level_tag
+    /// [`code_page`]: Self::code_page
+    pub fn coalesce(self, max_records: usize, max_bytes: usize, max_delay: Duration) -> Self {
+        Self {
+            coalesce: Some((max_records, max_bytes, max_delay)),
+            ..self
+        }
+    }
+
+    /// Write the lines pending in [`coalesce`] mode at most `interval` after
+    /// they were added, even if no more lines are logged. Disabled by
+    /// default. Has no effect unless [`coalesce`] is enabled.
+    ///
+    /// This starts a background thread when the first line is logged. The
+    /// thread sleeps without a timeout while no lines are pending, so it
+    /// does not wake up the process when it is idle.
+    ///
+    /// [`coalesce`]: Self::coalesce
+    pub fn flush_interval(self, interval: Duration) -> Self {
+        Self {
+            flush_interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Log a record every `interval` describing the state of the process,
+    /// e.g., to tell from the log of a long test run that the application
+    /// is still alive and how much it consumes. Disabled by default.
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 INFO  [windebug_logger::heartbeat] heartbeat: uptime=1:02:03 working-set=52340KiB handles=231 threads=12 logged=+120 dropped=+4
+    /// ```
+    ///
+    /// The record shows the time since the logger was installed, the working
+    /// set size, the numbers of handles and threads of the process, and the
+    /// numbers of records logged and dropped (see [`log_stats`]) since the
+    /// previous heartbeat. It has the target `windebug_logger::heartbeat`
+    /// and the level `Info`, and is subject to the filters like any other
+    /// record.
+    ///
+    /// This starts a background thread when the logger is installed, which
+    /// is stopped by [`LoggerHandle::shutdown`]. No heartbeat is logged
+    /// while logging is paused by [`enable_event`].
+    ///
+    /// [`log_stats`]: crate::log_stats
+    /// [`enable_event`]: Self::enable_event
+    pub fn heartbeat(self, interval: Duration) -> Self {
+        Self {
+            heartbeat: Some(interval),
+            ..self
+        }
+    }
+
+    /// Write to the debugger with `OutputDebugStringA` after converting each
+    /// line to the specified code page using `WideCharToMultiByte`. By
+    /// default, lines are written with `OutputDebugStringW`.
+    ///
+    /// This is intended for legacy tools that capture debug strings in a
+    /// specific code page. Pass `CP_ACP` (`0`) for the ANSI code page,
+    /// `CP_OEMCP` (`1`) for the OEM code page, or `CP_UTF8` (`65001`).
+    /// Characters that cannot be represented in the code page are replaced
+    /// with its default character, usually `?`. Other sinks are not affected.
+    ///
+    /// Long lines are split so that each part takes at most 4000 bytes in
+    /// the code page, the same limit that applies to the UTF-16 output.
+    ///
+    /// Note that `OutputDebugStringW` itself converts the string to the ANSI
+    /// code page for debuggers that do not request wide strings, so
+    /// `CP_ACP` is mostly useful for comparison.
+    pub fn code_page(self, code_page: u32) -> Self {
+        Self {
+            code_page: Some(code_page),
+            ..self
+        }
+    }
+
+    /// Set the initial capacity of the buffers each line is formatted into
+    /// (`256` by default), in bytes for the UTF-8 text and in code units for
+    /// the UTF-16 line it's converted to. Set it to the length of a typical
+    /// line to avoid reallocating the buffers while the line is formatted.
+    /// Longer lines are still formatted in full.
+    pub fn initial_buffer_capacity(self, initial_buffer_capacity: usize) -> Self {
+        Self {
+            initial_buffer_capacity,
+            ..self
+        }
+    }
+
+    /// Log the current directory, the executable path, and the OS version
+    /// when the logger is initialized. Disabled by default.
+    ///
+    /// These records are logged at `Info` level with the target
+    /// `windebug_logger`, regardless of the level filter.
+    pub fn log_environment(self, log_environment: bool) -> Self {
+        Self {
+            log_environment,
+            ..self
+        }
+    }
+
+    /// Suppress a message if an identical message (i.e., one with the same
+    /// target, level, and text) was logged less than `window` ago.
+    ///
+    /// Once a suppressed message stops repeating for `window`, a single
+    /// record `[suppressed N duplicate messages for "..."]` is logged in place
+    /// of the suppressed occurrences when the next record arrives. Only the
+    /// most recent messages are remembered (see
+    /// [`suppress_duplicates_capacity`]).
+    ///
+    /// Note that this requires every message to be formatted twice.
+    ///
+    /// [`suppress_duplicates_capacity`]: Self::suppress_duplicates_capacity
+    pub fn suppress_duplicates(self, window: Duration) -> Self {
+        Self {
+            suppress_duplicates: Some(window),
+            ..self
+        }
+    }
+
+    /// Set the number of distinct messages remembered for
+    /// [`suppress_duplicates`]. Defaults to 16.
+    ///
+    /// [`suppress_duplicates`]: Self::suppress_duplicates
+    pub fn suppress_duplicates_capacity(self, suppress_duplicates_capacity: usize) -> Self {
+        Self {
+            suppress_duplicates_capacity,
+            ..self
+        }
+    }
+
+    /// Log only one in every `n` records of `target` and its descendants,
+    /// appending `(sampled 1/N)` to their messages, e.g., to keep a
+    /// representative part of the output of a chatty target. Disabled by
+    /// default.
+    ///
+    /// ```no_run
+    /// # fn main() {
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .with_sampling("app::render", 100)
+    ///     .init()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    ///
+    /// Unlike [`suppress_duplicates`], this applies to distinct messages as
+    /// well. Each target has its own counter, and the most specific target
+    /// configured by this method decides, so `n` of `0` or `1` can be used
+    /// to exempt a descendant. The records dropped by sampling are counted
+    /// in the output of [`log_stats`](crate::log_stats).
+    ///
+    /// [`suppress_duplicates`]: Self::suppress_duplicates
+    pub fn with_sampling(mut self, target: impl Into<String>, n: u32) -> Self {
+        self.sampling.insert(target.into(), n);
+        self
+    }
+
+    /// Retain up to `capacity` most recent records rejected by the level
+    /// filter, and write them right before the next `Error` record. Disabled
+    /// by default.
+    ///
+    /// This lets an application run at a terse level (e.g., `Info`) while
+    /// still providing detailed context when something goes wrong. The
+    /// retained records keep the timestamps of when they were logged. Only
+    /// records up to [`debug_on_error_level`] are retained.
+    ///
+    /// Note that this requires every retained record to be formatted even if
+    /// it is never written.
+    ///
+    /// [`debug_on_error_level`]: Self::debug_on_error_level
+    pub fn debug_on_error(self, capacity: usize) -> Self {
+        Self {
+            debug_on_error: Some(capacity),
+            ..self
+        }
+    }
+
+    /// Set the least severe level retained by [`debug_on_error`].
+    /// Defaults to `LevelFilter::Trace`.
+    ///
+    /// [`debug_on_error`]: Self::debug_on_error
+    pub fn debug_on_error_level(self, debug_on_error_level: LevelFilter) -> Self {
+        Self {
+            debug_on_error_level,
+            ..self
+        }
+    }
+
+    /// Log a banner describing the process and the logger's configuration
+    /// when the logger is initialized. Disabled by default.
+    ///
+    /// The banner is a single multi-line record logged at `Info` level with
+    /// the target `windebug_logger`, regardless of the level filter. It
+    /// includes the time in UTC (so that the banner is dated even if
+    /// timestamps are disabled by [`timestamp_fn`]; it's omitted if the
+    /// built-in timestamp is compiled out), the executable path, the process
+    /// ID, the session ID (see [`session_id`]), the OS version, the processor
+    /// architecture, the version of this crate, the application version (see
+    /// [`app_version`]), and the logger's configuration. Fields that cannot be
+    /// queried are shown as `unknown`.
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 INFO  [windebug_logger] Logging started
+    ///   time: 2020-01-02T12:34:56.789Z
+    ///   executable: C:\Program Files\App\app.exe
+    ///   process id: 1234
+    ///   session id: k3x9qa
+    ///   ...
+    /// ```
+    ///
+    /// [`app_version`]: Self::app_version
+    /// [`session_id`]: Self::session_id
+    /// [`timestamp_fn`]: Self::timestamp_fn
+    pub fn banner(self, banner: bool) -> Self {
+        Self { banner, ..self }
+    }
+
+    /// Log a single line marking the initialization, with the version of
+    /// this crate and the level filter, right after the logger is installed
+    /// (`false` by default). The line is written like an `Info` record with
+    /// the target `windebug_logger`, but regardless of the level filter.
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 INFO  [windebug_logger] windebug_logger 0.1.4 initialized with level=Debug
+    /// ```
+    ///
+    /// Unlike the [`banner`], this is a single line that is easy to search
+    /// for in DebugView to find the start of a session.
+    ///
+    /// [`banner`]: Self::banner
+    pub fn log_on_init(self, log_on_init: bool) -> Self {
+        Self {
+            log_on_init,
+            ..self
+        }
+    }
+
+    /// Set the application version string shown in the [`banner`], e.g.,
+    /// `concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"))`.
+    ///
+    /// [`banner`]: Self::banner
+    pub fn app_version(self, app_version: impl Into<String>) -> Self {
+        Self {
+            app_version: Some(app_version.into()),
+            ..self
+        }
+    }
+
+    /// Render timestamps with the [`time`] crate using the specified
+    /// [format description] instead of `GetDateFormatW` and
+    /// `GetTimeFormatW`. Requires the `time` feature.
+    ///
+    /// The description is parsed here, and an error is returned if it is
+    /// invalid. The time zone is chosen by [`utc`].
+    ///
+    /// ```
+    /// # fn main() {
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .timestamp_format_description(
+    ///         "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]",
+    ///     )
+    ///     .unwrap()
+    ///     .init()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`time`]: https://crates.io/crates/time
+    /// [format description]: https://time-rs.github.io/book/api/format-description.html
+    /// [`utc`]: Self::utc
+    #[cfg(feature = "time")]
+    pub fn timestamp_format_description(
+        self,
+        description: &str,
+    ) -> Result<Self, time::error::InvalidFormatDescription> {
+        Ok(Self {
+            timestamp_format: Some(time::format_description::parse_owned::<1>(description)?),
+            ..self
+        })
+    }
+
+    /// Write the level tag of each record with its own `OutputDebugStringW`
+    /// call, separately from the text before and after it. Disabled by
+    /// default. **Experimental.**
+    ///
+    /// This is intended for viewers that color output per call, allowing
+    /// them to color the level tag distinctly. Whether the three calls are
+    /// displayed as a single line depends on the viewer, and calls from other
+    /// threads may be interleaved between them. This has no effect on
+    /// records formatted by [`custom_formatter`].
+    ///
+    /// [`custom_formatter`]: Self::custom_formatter
+    pub fn split_level_tag(self, split_level_tag: bool) -> Self {
+        Self {
+            split_level_tag,
+            ..self
+        }
+    }
+
+    /// Truncate each message longer than `max_len` UTF-16 code units
+    /// (excluding the trailing newline) and append `…[truncated]` to it.
+    /// Disabled by default.
+    ///
+    /// The message is everything after the target, including the key-values
+    /// and the source location, so the timestamp, the level, and the target
+    /// are always kept. With [`custom_formatter`] or
+    /// [`OutputFormat::Logfmt`], whose layouts are not known, the whole line
+    /// is truncated instead.
+    ///
+    /// This keeps the lines short in viewers, whereas lines that are too long
+    /// for a single `OutputDebugStringW` call are otherwise split into
+    /// multiple parts (see [Long messages]). Truncation comes first, so
+    /// lines are only split if they are still longer than the limit for a
+    /// single call. Surrogate pairs are never split. Unlike splitting, this
+    /// applies to every sink.
+    ///
+    /// [`custom_formatter`]: Self::custom_formatter
+    /// [Long messages]: crate#long-messages
+    pub fn with_max_message_len(self, max_len: usize) -> Self {
+        Self {
+            max_message_len: Some(max_len),
+            ..self
+        }
+    }
+
+    /// Append the source location of each record, if known, to the
+    /// message. Disabled by default.
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 INFO  [app] message @ src/main.rs:42
+    /// ```
+    ///
+    /// See also [`debugview_plus_plus`].
+    ///
+    /// [`debugview_plus_plus`]: Self::debugview_plus_plus
+    pub fn source_location(self, source_location: bool) -> Self {
+        Self {
+            source_location,
+            ..self
+        }
+    }
+
+    /// Indent the message of each record by `width` spaces per
+    /// [`time_scope`] it is logged in on the same thread, so that nested
+    /// operations are visible as a hierarchy. `0` (the default) disables
+    /// indentation.
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 INFO  [app] Loading the project
+    /// 01/02/2020 12:34:56 DEBUG [app::io]   Reading project.toml
+    /// 01/02/2020 12:34:56 DEBUG [app::io]     Parsed 12 entries
+    /// 01/02/2020 12:34:56 DEBUG [windebug_logger]   parsing took 0.120 ms
+    /// 01/02/2020 12:34:56 DEBUG [windebug_logger] loading took 3.456 ms
+    /// ```
+    ///
+    /// Only scopes that were enabled when created count. This has no effect
+    /// on [`custom_formatter`] and [`OutputFormat::Logfmt`].
+    ///
+    /// [`time_scope`]: crate::time_scope
+    /// [`custom_formatter`]: Self::custom_formatter
+    pub fn span_indent(self, width: usize) -> Self {
+        Self {
+            span_indent: width,
+            ..self
+        }
+    }
+
+    /// Show the frame number set by [`set_frame`](crate::set_frame) in each
+    /// line (`false` by default), e.g., to correlate the lines with the
+    /// frames rendered by a game.
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 [f:12345] INFO  [app::render] Uploading textures
+    /// ```
+    ///
+    /// [`OutputFormat::Logfmt`] writes it as `frame=12345`.
+    pub fn frame_number(self, frame_number: bool) -> Self {
+        Self {
+            frame_number,
+            ..self
+        }
+    }
+
+    /// Write the source location enabled by [`source_location`] in the
+    /// syntax [DebugView++] recognizes as a link to the file, instead of the
+    /// human-readable one. Disabled by default.
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 INFO  [app] message Source:src/main.rs@42
+    /// ```
+    ///
+    /// This has no effect without [`source_location`], in which case a
+    /// warning is logged when the logger is initialized.
+    ///
+    /// [`source_location`]: Self::source_location
+    /// [DebugView++]: https://github.com/CobaltFusion/DebugViewPP
+    pub fn debugview_plus_plus(self, debugview_plus_plus: bool) -> Self {
+        Self {
+            debugview_plus_plus,
+            ..self
+        }
+    }
+
+    /// Show a placeholder (`[ " ]`) instead of the target if it is
+    /// identical to the target of the previous line. Disabled by default.
+    ///
+    /// The previous line is tracked per thread, so a placeholder always
+    /// refers to a line logged by the same thread, which may not be the line
+    /// immediately above it if multiple threads are logging.
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 INFO  [app::network::http] Connecting
+    /// 01/02/2020 12:34:56 DEBUG [ " ] Resolved the host name
+    /// 01/02/2020 12:34:56 INFO  [ " ] Connected
+    /// ```
+    pub fn elide_repeated_targets(self, elide_repeated_targets: bool) -> Self {
+        Self {
+            elide_repeated_targets,
+            ..self
+        }
+    }
+
+    /// Render timestamps with the specified function, which appends the
+    /// timestamp text to the given `String`.
+    ///
+    /// The function is called once for every record that is written. It
+    /// replaces the built-in timestamp rendering entirely; the Win32 date and
+    /// time functions are not called in this mode, and [`utc`] has no effect.
+    /// If the function writes nothing, the line starts without a timestamp or
+    /// the separating space. If the function panics, the panic is caught and
+    /// the line is written without a timestamp.
+    ///
+    /// ```
+    /// # fn main() {
+    /// use std::{fmt::Write, time::Instant};
+    ///
+    /// let start = Instant::now();
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .timestamp_fn(move |out| {
+    ///         let _ = write!(out, "{:>10.3}", start.elapsed().as_secs_f64());
+    ///     })
+    ///     .init()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`utc`]: Self::utc
+    pub fn timestamp_fn(self, timestamp_fn: impl Fn(&mut String) + Send + Sync + 'static) -> Self {
+        Self {
+            custom_timestamp: Some(CustomTimestamp::new(timestamp_fn)),
+            ..self
+        }
+    }
+
+    /// Render the timestamp of a record from its key-value named `key`
+    /// instead of the current time, e.g., for records forwarded from another
+    /// process or replayed from a file. Requires the `kv` feature.
+    ///
+    /// The value is the number of seconds since the Unix epoch, given as an
+    /// integer or a floating-point number. Records without the key-value, or
+    /// with one that is not a number, are timestamped with the current time.
+    /// Timestamps that cannot be rendered (e.g., ones before the year 1601)
+    /// are left empty. This has no effect if [`timestamp_fn`] is used.
+    ///
+    /// ```
+    /// # fn main() {
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .timestamp_key("ts")
+    ///     .init()
+    ///     .unwrap();
+    ///
+    /// log::info!(ts = 1_600_000_000; "replayed");
+    /// # }
+    /// ```
+    ///
+    /// See also [`log_message_at`](crate::log_message_at).
+    ///
+    /// [`timestamp_fn`]: Self::timestamp_fn
+    #[cfg(feature = "kv")]
+    pub fn timestamp_key(self, key: impl Into<String>) -> Self {
+        Self {
+            timestamp_key: Some(key.into()),
+            ..self
+        }
+    }
+
+    /// Set the target shown for records that have neither a target nor a
+    /// module path. Defaults to an empty string, which renders as `[]`.
+    pub fn with_default_target(self, default_target: impl Into<String>) -> Self {
+        Self {
+            default_target: default_target.into(),
+            ..self
+        }
+    }
+
+    /// Show the name of the module (DLL) containing this crate in each line
+    /// (`false` by default), e.g., to tell apart the output of plugins that
+    /// are loaded into the same process and log through their own copies of
+    /// this crate. If this crate is linked into the executable, its name is
+    /// shown instead.
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 (plugin) INFO  [app] message
+    /// ```
+    ///
+    /// The name is the file stem of the module, looked up once by
+    /// `GetModuleHandleExW` when the logger is constructed. It is omitted if
+    /// the lookup fails.
+    pub fn with_module_name(self, module_name: bool) -> Self {
+        Self {
+            module_name,
+            ..self
+        }
+    }
+
+    /// Name the logger (empty by default) to tell apart the output of
+    /// multiple loggers that receive the same records, e.g., to compare two
+    /// format configurations side by side. A non-empty name is shown in each
+    /// line:
+    ///
+    /// ```text
+    /// 01/02/2020 12:34:56 [logger:compact] INFO  [app] message
+    /// ```
+    ///
+    /// The name is fixed when the logger is constructed and is not changed
+    /// by [`LoggerHandle::reconfigure`].
+    pub fn name(self, name: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            name: name.into(),
+            ..self
+        }
+    }
+
+    /// Format records using the specified function instead of the built-in
+    /// format.
+    ///
+    /// The function replaces the entire output, including the timestamp and
+    /// the trailing newline, and the other format options such as
+    /// [`level_style`] and [`utc`] have no effect on it. If it returns an
+    /// error, the record is counted as a write failure.
+    ///
+    /// A panic in the function does not propagate to the logging call site.
+    /// Instead, the line `<windebug_logger: formatter panicked>` is written
+    /// in place of the record.
+    ///
+    /// ```
+    /// # fn main() {
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .custom_formatter(Box::new(|record, out| {
+    ///         writeln!(out, "{}: {}", record.level(), record.args())
+    ///     }))
+    ///     .init()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`level_style`]: Self::level_style
+    /// [`utc`]: Self::utc
+    pub fn custom_formatter(self, formatter: Box<FormatFn>) -> Self {
+        Self {
+            custom_formatter: Some(CustomFormatter::new(formatter)),
+            ..self
+        }
+    }
+
+    /// Route the records up to `level` to `sinks`, and the records above
+    /// `level` away from `sinks`. By default, every record is routed to all
+    /// attached sinks.
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// use log::LevelFilter;
+    /// use windebug_logger::Sinks;
+    ///
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .file("app.log")?
+    ///     .event_log("MyApp")?
+    ///     // `Info` and above are also written to the file
+    ///     .route(LevelFilter::Info, Sinks::FILE)
+    ///     // Only `Error` is reported to the Event Log
+    ///     .route(LevelFilter::Error, Sinks::EVENT_LOG)
+    ///     .init()
+    ///     .unwrap();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// The routing decision is made once per record, after the level filter.
+    /// A record routed to no attached sink is still counted as logged.
+    pub fn route(mut self, level: LevelFilter, sinks: Sinks) -> Self {
+        self.routes.set(level, sinks);
+        self
+    }
+
+    /// Also write every line to the file at `path` (as [`Sinks::FILE`]),
+    /// encoded in UTF-8. The file is created if it does not exist and opened
+    /// for appending.
+    pub fn file(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let sink = sink::WriterSink::open(path)?;
+        let name = format!("file ({})", path.display());
+        Ok(self.push_sink(Sinks::FILE, name, Arc::new(sink)))
+    }
+
+    /// Also write every line to `writer` (as [`Sinks::FILE`]), encoded in
+    /// UTF-8.
+    pub fn file_writer(self, writer: impl Write + Send + 'static) -> Self {
+        let sink = sink::WriterSink::new(writer);
+        self.push_sink(Sinks::FILE, "file".to_owned(), Arc::new(sink))
+    }
+
+    /// Also write every line to the standard error stream (as
+    /// [`Sinks::STDERR`]). Disabled by default.
+    pub fn stderr(mut self, stderr: bool) -> Self {
+        self.sinks.retain(|entry| entry.kind != Sinks::STDERR);
+        if stderr {
+            let sink = sink::StderrSink::new(self.ansi_colors);
+            self = self.push_sink(Sinks::STDERR, "stderr".to_owned(), Arc::new(sink));
+        }
+        self
+    }
+
+    /// Color the lines written to the standard error stream (see
+    /// [`stderr`](Self::stderr)) by level with ANSI escape sequences, e.g.,
+    /// red for `Error` and yellow for `Warn` (`false` by default).
+    ///
+    /// The colors are only used if the standard error stream is a console
+    /// that supports ANSI escape sequences (Windows 10 and later), which is
+    /// detected when the sink is attached. The debugger and the other sinks
+    /// never receive escape sequences.
+    pub fn ansi_colors(mut self, ansi_colors: bool) -> Self {
+        self.ansi_colors = ansi_colors;
+        // Reattach the sink to apply the option
+        let stderr = self.sinks.iter().any(|entry| entry.kind == Sinks::STDERR);
+        self.stderr(stderr)
+    }
+
+    /// Also report every line to the Windows Event Log (as
+    /// [`Sinks::EVENT_LOG`]) using the event source named `source`.
+    ///
+    /// The event type is chosen by the level. Since the event source is not
+    /// registered with a message file, Event Viewer shows a note about a
+    /// missing event description along with each line. This is usually
+    /// combined with [`route`] to report errors only.
+    ///
+    /// [`route`]: Self::route
+    pub fn event_log(self, source: &str) -> io::Result<Self> {
+        let sink = sink::EventLogSink::register(source)?;
+        let name = format!("event log ({})", source);
+        Ok(self.push_sink(Sinks::EVENT_LOG, name, Arc::new(sink)))
+    }
+
+    /// Also write every line to the named pipe `\\.\pipe\{name}` (as
+    /// [`Sinks::PIPE`]), encoded in UTF-8.
+    ///
+    /// The pipe is connected when a line is written and reconnected after a
+    /// failure, so the server can be started and restarted at any time.
+    /// Lines written while the server is not running are counted as write
+    /// failures.
+    pub fn pipe(self, name: &str) -> Self {
+        let sink = sink::PipeSink::new(name);
+        let name = format!("pipe ({})", sink.path());
+        self.push_sink(Sinks::PIPE, name, Arc::new(sink))
+    }
+
+    /// Also write every line (as [`Sinks::SHARED_MEMORY`]) to a ring buffer
+    /// of `size` bytes in the file mapping named `name`, which other
+    /// processes can read at any time without going through the debug
+    /// output. Requires the `shared-memory` feature.
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .with_shared_memory_ring(r"Local\MyAppLog", 1 << 20)?
+    ///     .init()
+    ///     .unwrap();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// The mapping is created with `CreateFileMappingW`, or opened if it
+    /// already exists, e.g., because a reader created it first. The records
+    /// left by a previous writer are kept if the layout matches. Only one
+    /// logger should write to a mapping at a time.
+    ///
+    /// # Format
+    ///
+    /// All integers are little-endian. The mapping starts with a 32-byte
+    /// header:
+    ///
+    /// | Offset | Type  | Content                                          |
+    /// |--------|-------|--------------------------------------------------|
+    /// | 0      | `u32` | The magic number, the bytes `WDLR`               |
+    /// | 4      | `u32` | The version of the layout, `1`                   |
+    /// | 8      | `u32` | The capacity of the data area in bytes (`C`)     |
+    /// | 12     | `u32` | Reserved                                         |
+    /// | 16     | `u64` | The head: the end of the newest record           |
+    /// | 24     | `u64` | The tail: the start of the oldest record         |
+    ///
+    /// The data area of `C` bytes follows. `size` is rounded down to a
+    /// multiple of 4 to give `C`, and must be at least 256. The head and the
+    /// tail are positions that only increase; the position `p` refers to
+    /// the byte at `32 + p % C` in the mapping.
+    ///
+    /// Each record consists of a `u32` length `n` and a line of `n` bytes of
+    /// UTF-8 without the trailing newline, padded with zeros to a multiple
+    /// of 4 bytes. A record never wraps around the end of the data area;
+    /// instead, a length of `0xFFFFFFFF` marks the rest of the data area as
+    /// unused, and the next record starts at the beginning of the data area.
+    /// Lines that do not fit in the data area are dropped and counted as
+    /// write failures.
+    ///
+    /// When the data area is full, the oldest records are dropped by
+    /// advancing the tail before they are overwritten. A reader keeps its
+    /// own position `p` (starting at the tail) and repeats the following:
+    ///
+    ///  1. Read the head (with acquire semantics). Stop if `p` equals it.
+    ///  2. If `p` is behind the tail, records were lost; set `p` to the tail.
+    ///  3. Read the record at `p`, and then read the tail again. If it has
+    ///     moved past `p`, the record was overwritten while being read;
+    ///     discard it and go back to step 2.
+    ///  4. Advance `p` past the record (or to the next multiple of `C` for
+    ///     the marker).
+    #[cfg(feature = "shared-memory")]
+    pub fn with_shared_memory_ring(self, name: &str, size: usize) -> io::Result<Self> {
+        let sink = crate::shm::SharedMemorySink::open(name, size)?;
+        let name = format!("shared memory ({})", name);
+        Ok(self.push_sink(Sinks::SHARED_MEMORY, name, Arc::new(sink)))
+    }
+
+    /// Keep the last `capacity` lines in memory (as
+    /// [`Sinks::RING_BUFFER`]). They can be retrieved by
+    /// [`LoggerHandle::history`], e.g., to include them in a crash report.
+    pub fn ring_buffer(self, capacity: usize) -> Self {
+        Self {
+            ring_buffer: Some(capacity),
+            ..self
+        }
+    }
+
+    /// Also write every line to `sink` (as [`Sinks::CUSTOM`]).
+    pub fn sink(self, sink: impl DebugSink + 'static) -> Self {
+        let name = sink.name();
+        self.push_sink(Sinks::CUSTOM, name, Arc::new(sink))
+    }
+
+    /// Detach the sinks of the specified kinds attached so far, e.g., in
+    /// [`LoggerHandle::reconfigure`]. [`Sinks::ODS`] and
+    /// [`Sinks::RING_BUFFER`] cannot be detached; use [`route`] to stop
+    /// writing to them.
+    ///
+    /// [`route`]: Self::route
+    pub fn remove_sinks(mut self, kinds: Sinks) -> Self {
+        self.sinks.retain(|entry| !kinds.contains(entry.kind));
+        self
+    }
+
+    /// Format and route every record as usual, but skip the
+    /// `OutputDebugStringW` call (`false` by default).
+    ///
+    /// The other sinks, such as [`sink`](Self::sink) and
+    /// [`ring_buffer`](Self::ring_buffer), still receive the lines, so this
+    /// can be used to check the output of a configuration, e.g., in CI,
+    /// without polluting the debug output.
+    pub fn dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
+
+    /// Skip the `OutputDebugStringW` call while neither a debugger nor a
+    /// debug output monitor is receiving the output (`false` by default), as
+    /// determined by [`debug_listener_present`](crate::debug_listener_present).
+    ///
+    /// The state is re-evaluated at most once a second, so the first lines
+    /// after a debugger is attached might be lost. Call
+    /// [`refresh_listener_state`](crate::refresh_listener_state) to pick up
+    /// the change immediately. The other sinks are not affected.
+    pub fn skip_when_unattended(self, skip_when_unattended: bool) -> Self {
+        Self {
+            skip_when_unattended,
+            ..self
+        }
+    }
+
+    /// Flush the lines buffered by [`coalesce`](Self::coalesce) and the sinks
+    /// right after writing each `Error` record (`false` by default), so that
+    /// it reaches its destinations even if the process crashes shortly
+    /// afterwards. The other records are still buffered as usual.
+    pub fn flush_on_error(self, flush_on_error: bool) -> Self {
+        Self {
+            flush_on_error,
+            ..self
+        }
+    }
+
+    fn push_sink(mut self, kind: Sinks, name: String, sink: Arc<dyn DebugSink>) -> Self {
+        self.sinks.push(SinkEntry::new(kind, name, sink));
+        self
+    }
+
+    /// Initialize the global logger with this configuration.
+    pub fn init(self) -> Result<LoggerHandle, Error> {
+        Ok(self.install()?)
+    }
+
+    /// Like [`init`](Self::init), but returns `SetLoggerError` for the
+    /// initialization functions that predate [`Error`].
+    pub(crate) fn install(self) -> Result<LoggerHandle, SetLoggerError> {
+        let logger = WinDebugLogger::from_builder(&self);
+        let logger = crate::install_boxed(Box::new(logger))?;
+        self.announce(logger);
+
+        Ok(LoggerHandle { logger })
+    }
+
+    /// Log the messages enabled by the builder for a logger that has just
+    /// been installed.
+    pub(crate) fn announce(&self, logger: &WinDebugLogger) {
+        if self.log_on_init {
+            logger.log_init_marker(self.level);
+        }
+
+        if self.banner {
+            logger.log_banner(self.app_version.as_deref());
+        }
+
+        if self.debugview_plus_plus && !self.source_location {
+            logger.log_config_warning(format_args!(
+                "`debugview_plus_plus` has no effect without `source_location`"
+            ));
+        }
+
+        if self.log_environment {
+            logger.log_environment();
+        }
+    }
+}
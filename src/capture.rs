@@ -0,0 +1,147 @@
+//! A sink collecting lines in memory, for asserting on the output in tests.
+//! Requires the `test-util` feature.
+//!
+//! ```
+//! # fn main() {
+//! use windebug_logger::{capture::CaptureSink, WinDebugLogger, WinDebugLoggerBuilder};
+//!
+//! let (sink, lines) = CaptureSink::new();
+//! let logger = WinDebugLogger::from_builder(
+//!     &WinDebugLoggerBuilder::new()
+//!         .timestamp_fn(|out| out.push_str("now"))
+//!         .sink(sink),
+//! );
+//! log::Log::log(
+//!     &logger,
+//!     &log::Record::builder()
+//!         .args(format_args!("hello"))
+//!         .level(log::Level::Info)
+//!         .target("app")
+//!         .build(),
+//! );
+//! assert_eq!(lines.lines(), ["now INFO  [app] hello\n"]);
+//! # }
+//! ```
+use log::Level;
+use std::{
+    io,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::DebugSink;
+
+/// A [`DebugSink`] collecting the lines it receives, which can be read
+/// through the [`CaptureHandle`] returned by [`CaptureSink::new`].
+#[derive(Debug)]
+pub struct CaptureSink {
+    captured: Arc<Mutex<Captured>>,
+}
+
+/// Reads the lines collected by a [`CaptureSink`].
+#[derive(Debug, Clone)]
+pub struct CaptureHandle {
+    captured: Arc<Mutex<Captured>>,
+}
+
+#[derive(Debug, Default)]
+struct Captured {
+    lines: Vec<(Level, Vec<u16>)>,
+    flushes: usize,
+}
+
+impl CaptureSink {
+    /// Construct a `CaptureSink` and a handle to read its lines.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> (CaptureSink, CaptureHandle) {
+        let captured = Arc::new(Mutex::new(Captured::default()));
+        (
+            CaptureSink {
+                captured: Arc::clone(&captured),
+            },
+            CaptureHandle { captured },
+        )
+    }
+}
+
+impl DebugSink for CaptureSink {
+    fn write(&self, level: Level, line: &[u16]) -> io::Result<()> {
+        lock(&self.captured).lines.push((level, line.to_vec()));
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        lock(&self.captured).flushes += 1;
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        "capture".to_owned()
+    }
+}
+
+impl CaptureHandle {
+    /// Get the lines collected so far, including the trailing newlines.
+    /// Unpaired surrogates are replaced with U+FFFD.
+    pub fn lines(&self) -> Vec<String> {
+        (lock(&self.captured).lines.iter())
+            .map(|(_, line)| String::from_utf16_lossy(line))
+            .collect()
+    }
+
+    /// Get the lines collected so far with their levels.
+    pub fn records(&self) -> Vec<(Level, String)> {
+        (lock(&self.captured).lines.iter())
+            .map(|(level, line)| (*level, String::from_utf16_lossy(line)))
+            .collect()
+    }
+
+    /// Get the lines collected so far as they were received, e.g., to check
+    /// how unpaired surrogates are passed through.
+    pub fn wide_lines(&self) -> Vec<Vec<u16>> {
+        (lock(&self.captured).lines.iter())
+            .map(|(_, line)| line.clone())
+            .collect()
+    }
+
+    /// Get the number of times the sink has been flushed.
+    pub fn flushes(&self) -> usize {
+        lock(&self.captured).flushes
+    }
+
+    /// Discard the lines collected so far.
+    pub fn clear(&self) {
+        lock(&self.captured).lines.clear();
+    }
+}
+
+fn lock(captured: &Mutex<Captured>) -> MutexGuard<'_, Captured> {
+    captured.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{WinDebugLogger, WinDebugLoggerBuilder};
+
+    #[test]
+    fn test_capture() {
+        let (sink, lines) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|out| out.push_str("now"))
+                .sink(sink),
+        );
+        let wide: Vec<u16> = "a\u{1F984}".encode_utf16().chain(Some(0xD800)).collect();
+        logger.log_wide(Level::Warn, "app", &wide);
+        assert_eq!(lines.lines(), ["now WARN  [app] a\u{1F984}\u{FFFD}\n"]);
+        assert_eq!(lines.records()[0].0, Level::Warn);
+        assert!(lines.wide_lines()[0].ends_with(&[0xD800, 0x0A]));
+        assert_eq!(lines.flushes(), 0);
+        log::Log::flush(&logger);
+        assert_eq!(lines.flushes(), 1);
+        assert!(logger.sink_status().contains(&("capture".to_owned(), true)));
+
+        lines.clear();
+        assert!(lines.lines().is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+//! Splitting of long lines into multiple `OutputDebugStringW` calls.
+use std::sync::Mutex;
+
+use winapi::um::winnls::CP_UTF8;
+
+use crate::coalesce::MAX_BATCH_LEN;
+
+/// The maximum length of a chunk in UTF-16 code units, excluding the null
+/// terminator. See [`MAX_BATCH_LEN`] for the rationale.
+pub const MAX_CHUNK_LEN: usize = MAX_BATCH_LEN;
+
+/// The maximum length of a chunk in bytes after conversion to a code page
+/// for `OutputDebugStringA`, excluding the null terminator. This is the
+/// size that a chunk of [`MAX_CHUNK_LEN`] code units is allowed to take.
+pub const MAX_CHUNK_BYTES: usize = 2 * MAX_CHUNK_LEN;
+
+/// How the length of a line is measured against the limits, depending on
+/// how it is written to the debugger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Measure {
+    /// In UTF-16 code units, for `OutputDebugStringW`.
+    Units,
+    /// In bytes after conversion to the code page, for `OutputDebugStringA`.
+    Bytes(u32),
+}
+
+impl Measure {
+    pub fn new(code_page: Option<u32>) -> Self {
+        match code_page {
+            Some(code_page) => Self::Bytes(code_page),
+            None => Self::Units,
+        }
+    }
+
+    /// Get the maximum length of a chunk.
+    pub fn max_chunk_len(self) -> usize {
+        match self {
+            Self::Units => MAX_CHUNK_LEN,
+            Self::Bytes(_) => MAX_CHUNK_BYTES,
+        }
+    }
+
+    /// Get the length of a UTF-16 code unit, which is an upper bound if the
+    /// code page's encoding is not known here. Each half of a surrogate pair
+    /// counts for half of the pair.
+    pub fn unit_len(self, unit: u16) -> usize {
+        match self {
+            Self::Units => 1,
+            _ if unit < 0x80 => 1,
+            Self::Bytes(CP_UTF8) => match unit {
+                0x80..=0x7ff | 0xd800..=0xdfff => 2,
+                _ => 3,
+            },
+            // GB18030 encodes some BMP characters in four bytes
+            Self::Bytes(54936) => 4,
+            // UTF-7 encodes a lone non-ASCII character as `+xxx-`
+            Self::Bytes(65000) => 5,
+            // The other code pages take at most two bytes per character
+            Self::Bytes(_) => 2,
+        }
+    }
+
+    /// Get the length of `text`.
+    pub fn len(self, text: &[u16]) -> usize {
+        match self {
+            Self::Units => text.len(),
+            _ => text.iter().map(|&unit| self.unit_len(unit)).sum(),
+        }
+    }
+}
+
+/// Held while the parts of a line are written so that they are not
+/// interleaved with the parts of another line.
+static PARTS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Call `output` with the null-terminated `line` if it is at most `max_len`
+/// long as measured by `measure`, or with each part of it otherwise.
+///
+/// Every part starts with a marker like `(part 2/5 k3f9) `, where `k3f9` is
+/// a token returned by `token`, shared by the parts of the line so that they
+/// can be reassembled even if they are interleaved with other lines. Every
+/// part ends with a newline, except for the last one if `line` did not.
+///
+/// The parts of a line are never interleaved with those of another line
+/// split by this function, though a line written in one piece may come in
+/// between. Lines that are not split take no lock.
+pub(crate) fn split(
+    line: &[u16],
+    max_len: usize,
+    measure: Measure,
+    token: impl FnOnce() -> String,
+    mut output: impl FnMut(&[u16]),
+) {
+    debug_assert_eq!(line.last(), Some(&0));
+    let text = &line[..line.len() - 1];
+    if measure.len(text) <= max_len {
+        output(line);
+        return;
+    }
+
+    let (body, newline) = match text.split_last() {
+        Some((&c, rest)) if c == u16::from(b'\n') => (rest, true),
+        _ => (text, false),
+    };
+    let token = token();
+
+    // The marker's length depends on the number of parts, which in turn
+    // depends on the marker's length
+    let mut max_parts = 9;
+    let parts = loop {
+        let marker_len = marker(max_parts, max_parts, &token).len();
+        // Leave room for the newline and at least a surrogate pair. Markers
+        // are ASCII, so their length is the same in every measure.
+        let payload = max_len
+            .saturating_sub(marker_len + 1)
+            .max(2 * measure.unit_len(0xd800));
+        let parts = part_ranges(body, payload, measure);
+        if parts.len() <= max_parts {
+            break parts;
+        }
+        max_parts = max_parts * 10 + 9;
+    };
+
+    let mut buf = Vec::new();
+    let _guard = PARTS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    for (i, range) in parts.iter().enumerate() {
+        buf.clear();
+        buf.extend(marker(i + 1, parts.len(), &token).encode_utf16());
+        buf.extend_from_slice(&body[range.clone()]);
+        if i + 1 < parts.len() || newline {
+            buf.push(u16::from(b'\n'));
+        }
+        buf.push(0);
+        output(&buf);
+    }
+}
+
+fn marker(part: usize, parts: usize, token: &str) -> String {
+    format!("(part {}/{} {}) ", part, parts, token)
+}
+
+/// Split `text` into ranges at most `max_len` long as measured by `measure`
+/// without splitting surrogate pairs. `max_len` must leave room for a
+/// surrogate pair.
+fn part_ranges(text: &[u16], max_len: usize, measure: Measure) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = start;
+        let mut len = 0;
+        while end < text.len() && len + measure.unit_len(text[end]) <= max_len {
+            len += measure.unit_len(text[end]);
+            end += 1;
+        }
+        if end < text.len() && (0xd800..0xdc00).contains(&text[end - 1]) {
+            end -= 1;
+        }
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    fn split_to_strings(line: &str, max_len: usize) -> Vec<String> {
+        let line: Vec<u16> = line.encode_utf16().chain(Some(0)).collect();
+        let mut parts = Vec::new();
+        split(
+            &line,
+            max_len,
+            Measure::Units,
+            || "tok".to_owned(),
+            |part| {
+                assert!(part.len() - 1 <= max_len, "{:?}", part);
+                parts.push(String::from_utf16(part).unwrap());
+            },
+        );
+        parts
+    }
+
+    #[test]
+    fn test_split_short() {
+        assert_eq!(split_to_strings("hello\n", 6), ["hello\n\0"]);
+    }
+
+    #[test]
+    fn test_split() {
+        // "(part 1/6 tok) " is 15 long, leaving 4 for the text
+        assert_eq!(
+            split_to_strings("0123456789abcdefghijk\n", 20),
+            [
+                "(part 1/6 tok) 0123\n\0",
+                "(part 2/6 tok) 4567\n\0",
+                "(part 3/6 tok) 89ab\n\0",
+                "(part 4/6 tok) cdef\n\0",
+                "(part 5/6 tok) ghij\n\0",
+                "(part 6/6 tok) k\n\0",
+            ]
+        );
+        // Without a trailing newline
+        let parts = split_to_strings("0123456789abcdefghijk", 20);
+        assert_eq!(parts.len(), 6);
+        assert_eq!(parts[5], "(part 6/6 tok) k\0");
+    }
+
+    #[test]
+    fn test_split_many_parts() {
+        // Ten or more parts need longer markers
+        let line = "x".repeat(50);
+        let parts = split_to_strings(&line, 20);
+        assert_eq!(parts.len(), 25);
+        assert_eq!(parts[0], "(part 1/25 tok) xx\n\0");
+        assert_eq!(parts[24], "(part 25/25 tok) xx\0");
+    }
+
+    #[test]
+    fn test_split_surrogate_pairs() {
+        assert_eq!(
+            split_to_strings("012🦄456789abcdefghij\n", 20),
+            [
+                "(part 1/6 tok) 012\n\0",
+                "(part 2/6 tok) 🦄45\n\0",
+                "(part 3/6 tok) 6789\n\0",
+                "(part 4/6 tok) abcd\n\0",
+                "(part 5/6 tok) efgh\n\0",
+                "(part 6/6 tok) ij\n\0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_utf8() {
+        let measure = Measure::Bytes(CP_UTF8);
+        // 2 + 3 + 4 bytes
+        let text: Vec<u16> = "é€🦄".encode_utf16().collect();
+        assert_eq!(measure.len(&text), 9);
+        assert_eq!(Measure::Units.len(&text), 4);
+
+        // Twenty code units, but 60 bytes
+        let line: Vec<u16> = "€".repeat(20).encode_utf16().chain(Some(0)).collect();
+        let mut parts = Vec::new();
+        split(
+            &line,
+            40,
+            measure,
+            || "tok".to_owned(),
+            |part| {
+                let part = String::from_utf16(part).unwrap();
+                assert!(part.len() - 1 <= 40, "{:?}", part);
+                parts.push(part);
+            },
+        );
+        // "(part 1/3 tok) " and the newline leave 24 bytes
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], format!("(part 1/3 tok) {}\n\0", "€".repeat(8)));
+        assert_eq!(parts[2], format!("(part 3/3 tok) {}\0", "€".repeat(4)));
+    }
+
+    #[test]
+    fn test_split_contiguous() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let line: Vec<u16> = "x".repeat(100).encode_utf16().chain(Some(0)).collect();
+
+        let threads: Vec<_> = (0..2)
+            .map(|thread_i| {
+                let output = Arc::clone(&output);
+                let line = line.clone();
+                thread::spawn(move || {
+                    for record_i in 0..100 {
+                        split(
+                            &line,
+                            30,
+                            Measure::Units,
+                            || format!("{}-{}", thread_i, record_i),
+                            |part| {
+                                output
+                                    .lock()
+                                    .unwrap()
+                                    .push(String::from_utf16(part).unwrap());
+                                thread::yield_now();
+                            },
+                        );
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // Every record is written as "(part 1/n token)", ..., "(part n/n token)"
+        let output = output.lock().unwrap();
+        assert_eq!(output.len() % 200, 0);
+        let parts = output.len() / 200;
+        for record in output.chunks(parts) {
+            let token = record[0].split(' ').nth(2).unwrap();
+            for (i, part) in record.iter().enumerate() {
+                let marker = format!("(part {}/{} {}", i + 1, parts, token);
+                assert!(part.starts_with(&marker), "{:?}", record);
+            }
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Grouping of lines into fewer `OutputDebugStringW` calls. See
+//! [`WinDebugLoggerBuilder::coalesce`].
+//!
+//! [`WinDebugLoggerBuilder::coalesce`]: crate::WinDebugLoggerBuilder::coalesce
+use crate::chunk::{Measure, MAX_CHUNK_BYTES};
+use std::{
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard, Once},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The maximum length of a batch in UTF-16 code units, excluding the null
+/// terminator.
+///
+/// The debugger receives the output through a 4096-byte buffer (`DBWIN`),
+/// which also holds the process ID, after it is converted to the ANSI code
+/// page. Anything longer is truncated or split by the viewer, so batches are
+/// kept well below that even if every character takes two bytes.
+pub const MAX_BATCH_LEN: usize = 2000;
+
+/// Writes a null-terminated batch.
+pub(crate) type OutputFn = dyn Fn(&[u16]) + Send + Sync;
+
+pub(crate) struct Coalescer {
+    max_records: usize,
+    /// Measured by `measure`.
+    max_len: usize,
+    measure: Measure,
+    max_delay: Duration,
+    flush_interval: Option<Duration>,
+    shared: Arc<Shared>,
+    worker: Once,
+    /// The worker thread, taken by [`Coalescer::close`] to join it.
+    worker_handle: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+struct Shared {
+    /// Locked while the batch is written out so that lines are never
+    /// reordered.
+    batch: Mutex<Batch>,
+    /// Notified when the batch becomes non-empty or the coalescer is dropped.
+    condvar: Condvar,
+    output: Box<OutputFn>,
+}
+
+#[derive(Debug, Default)]
+struct Batch {
+    /// Lines without null terminators.
+    buf: Vec<u16>,
+    /// The length of `buf` as measured by `Coalescer::measure`.
+    len: usize,
+    records: usize,
+    /// The time when the first pending line was added.
+    since: Option<Instant>,
+    /// Set when the coalescer is dropped to stop the worker thread.
+    closed: bool,
+}
+
+impl fmt::Debug for Coalescer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Coalescer")
+            .field("max_records", &self.max_records)
+            .field("max_len", &self.max_len)
+            .field("max_delay", &self.max_delay)
+            .field("flush_interval", &self.flush_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Coalescer {
+    pub fn new(
+        max_records: usize,
+        max_bytes: usize,
+        max_delay: Duration,
+        flush_interval: Option<Duration>,
+        measure: Measure,
+        output: Box<OutputFn>,
+    ) -> Self {
+        Self {
+            max_records: max_records.max(1),
+            max_len: match measure {
+                Measure::Units => (max_bytes / 2).clamp(1, MAX_BATCH_LEN),
+                Measure::Bytes(_) => max_bytes.clamp(1, MAX_CHUNK_BYTES),
+            },
+            measure,
+            max_delay,
+            flush_interval,
+            shared: Arc::new(Shared {
+                batch: Mutex::new(Batch::default()),
+                condvar: Condvar::new(),
+                output,
+            }),
+            worker: Once::new(),
+            worker_handle: Mutex::new(None),
+        }
+    }
+
+    pub fn max_records(&self) -> usize {
+        self.max_records
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+
+    pub fn flush_interval(&self) -> Option<Duration> {
+        self.flush_interval
+    }
+
+    /// Add a null-terminated line to the batch, writing batches as they
+    /// become full or overdue.
+    pub fn push(&self, line: &[u16]) {
+        if let Some(interval) = self.flush_interval {
+            self.worker.call_once(|| self.spawn_worker(interval));
+        }
+
+        let text = &line[..line.len() - 1];
+        let len = self.measure.len(text);
+        let shared = &*self.shared;
+        let mut batch = shared.lock();
+
+        if !batch.buf.is_empty() && batch.len + len > self.max_len {
+            batch.write(&shared.output);
+        }
+
+        if len >= self.max_len {
+            // Too long to be grouped with anything
+            (shared.output)(line);
+            return;
+        }
+
+        batch.buf.extend_from_slice(text);
+        batch.len += len;
+        batch.records += 1;
+        let since = match batch.since {
+            Some(since) => since,
+            None => {
+                // Start the worker's timer
+                shared.condvar.notify_one();
+                *batch.since.insert(Instant::now())
+            }
+        };
+
+        if batch.records >= self.max_records || since.elapsed() >= self.max_delay {
+            batch.write(&shared.output);
+        }
+    }
+
+    /// Write the pending lines, if any.
+    pub fn flush(&self) {
+        let shared = &*self.shared;
+        shared.lock().write(&shared.output);
+    }
+
+    /// Write the pending lines and stop the worker thread, waiting for it to
+    /// exit. Lines pushed afterwards are only written by `push` and `flush`.
+    pub fn close(&self) {
+        let shared = &*self.shared;
+        {
+            let mut batch = shared.lock();
+            batch.write(&shared.output);
+            batch.closed = true;
+        }
+        shared.condvar.notify_one();
+
+        let handle = self
+            .worker_handle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        if let Some(handle) = handle {
+            let _ = handle.join();
+        }
+    }
+
+    /// Start a thread that writes the pending lines `interval` after the
+    /// oldest one was added. It only wakes up while there are pending lines.
+    fn spawn_worker(&self, interval: Duration) {
+        let shared = Arc::clone(&self.shared);
+        // If this fails, pending lines are only written by `push` and `flush`
+        let handle = thread::Builder::new()
+            .name("windebug_logger flush".to_owned())
+            .spawn(move || {
+                let mut batch = shared.lock();
+                while !batch.closed {
+                    batch = match batch.since {
+                        None => shared
+                            .condvar
+                            .wait(batch)
+                            .unwrap_or_else(|e| e.into_inner()),
+                        Some(since) => {
+                            let elapsed = since.elapsed();
+                            if elapsed >= interval {
+                                batch.write(&shared.output);
+                                continue;
+                            }
+                            shared
+                                .condvar
+                                .wait_timeout(batch, interval - elapsed)
+                                .unwrap_or_else(|e| e.into_inner())
+                                .0
+                        }
+                    };
+                }
+            });
+        *self.worker_handle.lock().unwrap_or_else(|e| e.into_inner()) = handle.ok();
+    }
+}
+
+impl Drop for Coalescer {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.condvar.notify_one();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Batch> {
+        self.batch.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Batch {
+    fn write(&mut self, output: &OutputFn) {
+        if self.buf.is_empty() {
+            return;
+        }
+        self.buf.push(0);
+        output(&self.buf);
+        self.buf.clear();
+        self.len = 0;
+        self.records = 0;
+        self.since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn line(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    /// Create a coalescer recording the batches it writes.
+    fn coalescer(
+        max_records: usize,
+        max_bytes: usize,
+        max_delay: Duration,
+        flush_interval: Option<Duration>,
+        measure: Measure,
+    ) -> (Coalescer, Arc<Mutex<Vec<String>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let calls2 = Arc::clone(&calls);
+        let coalescer = Coalescer::new(
+            max_records,
+            max_bytes,
+            max_delay,
+            flush_interval,
+            measure,
+            Box::new(move |buf| {
+                calls2
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf16(buf).unwrap())
+            }),
+        );
+        (coalescer, calls)
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let (coalescer, calls) = coalescer(3, 40, Duration::from_secs(3600), None, Measure::Units);
+
+        coalescer.push(&line("a\n"));
+        coalescer.push(&line("b\n"));
+        coalescer.push(&line("c\n"));
+        coalescer.push(&line("d\n"));
+        // `max_len` is 20; flushes "d\n" first
+        coalescer.push(&line("0123456789abcdefgh\n"));
+        // Longer than `max_len`; written by itself
+        coalescer.push(&line("0123456789abcdefghijk\n"));
+        coalescer.push(&line("e\n"));
+        coalescer.flush();
+        coalescer.flush();
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "a\nb\nc\n\0",
+                "d\n\0",
+                "0123456789abcdefgh\n\0",
+                "0123456789abcdefghijk\n\0",
+                "e\n\0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_code_page() {
+        let measure = Measure::Bytes(winapi::um::winnls::CP_UTF8);
+        let (coalescer, calls) = coalescer(100, 10, Duration::from_secs(3600), None, measure);
+
+        // Five code units in total, which would fit in 10 bytes of UTF-16,
+        // but eleven bytes of UTF-8
+        coalescer.push(&line("€€\n"));
+        coalescer.push(&line("€\n"));
+        coalescer.push(&line("a\n"));
+        coalescer.flush();
+
+        assert_eq!(*calls.lock().unwrap(), ["€€\n\0", "€\na\n\0"]);
+    }
+
+    #[test]
+    fn test_coalesce_delay() {
+        let (coalescer, calls) = coalescer(100, 4000, Duration::ZERO, None, Measure::Units);
+        coalescer.push(&line("a\n"));
+        coalescer.push(&line("b\n"));
+        assert_eq!(calls.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_flush_interval() {
+        // Waits for each batch of the worker instead of sleeping, and checks
+        // when it was written
+        let interval = Duration::from_millis(20);
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let coalescer = Coalescer::new(
+            100,
+            4000,
+            Duration::from_secs(3600),
+            Some(interval),
+            Measure::Units,
+            Box::new(move |buf| {
+                let batch = String::from_utf16(buf).unwrap();
+                let _ = sender.lock().unwrap().send((batch, Instant::now()));
+            }),
+        );
+        let next_batch = || receiver.recv_timeout(Duration::from_secs(30)).unwrap();
+
+        let start = Instant::now();
+        coalescer.push(&line("a\n"));
+        let (batch, written) = next_batch();
+        assert_eq!(batch, "a\n\0");
+        assert!(written - start >= interval);
+
+        // An explicit flush does not wait for the worker
+        coalescer.push(&line("b\n"));
+        coalescer.flush();
+        assert_eq!(receiver.try_recv().unwrap().0, "b\n\0");
+
+        // The timer restarts with the next line
+        let start = Instant::now();
+        coalescer.push(&line("c\n"));
+        let (batch, written) = next_batch();
+        assert_eq!(batch, "c\n\0");
+        assert!(written - start >= interval);
+    }
+
+    #[test]
+    fn test_close() {
+        let hour = Duration::from_secs(3600);
+        let (coalescer, calls) = coalescer(100, 4000, hour, Some(hour), Measure::Units);
+        coalescer.push(&line("a\n"));
+        assert!(coalescer.worker_handle.lock().unwrap().is_some());
+
+        coalescer.close();
+        assert_eq!(*calls.lock().unwrap(), ["a\n\0"]);
+        assert!(coalescer.worker_handle.lock().unwrap().is_none());
+    }
+}
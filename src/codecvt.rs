@@ -1,12 +1,35 @@
-use std::{convert::TryInto, ptr::null_mut};
-use winapi::um::{stringapiset::MultiByteToWideChar, winnls::CP_UTF8};
+//! Conversion between Rust strings and the wide character strings passed to
+//! `OutputDebugStringW`.
+use std::os::windows::ffi::OsStrExt;
+use std::{
+    convert::TryInto,
+    ffi::OsStr,
+    fmt,
+    ptr::{null, null_mut},
+};
+use winapi::um::{
+    stringapiset::{MultiByteToWideChar, WideCharToMultiByte},
+    winnls::CP_UTF8,
+};
+
+/// Specifies how a conversion handles input that is not a valid Unicode
+/// string, such as an unpaired surrogate in a wide character string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CodecvtError {
+    /// Replace each invalid sequence with U+FFFD REPLACEMENT CHARACTER.
+    Replace,
+    /// Fail the conversion by returning `None`.
+    Reject,
+}
 
 /// Convert a given `str` into a null-terminated wide character string. Does not
 /// check for unexpected null characters.
 ///
-/// Returns `None` if the input string is too long or anything goes wrong.
+/// Since `str` is always valid UTF-8, this never produces an unpaired
+/// surrogate and needs no [`CodecvtError`] policy. Returns `None` if the input
+/// string is too long or anything goes wrong.
 pub fn str_to_c_wstr(s: &str) -> Option<Box<[u16]>> {
-    if s.len() == 0 {
+    if s.is_empty() {
         Some(Box::new([0]))
     } else {
         unsafe {
@@ -40,9 +63,162 @@ pub fn str_to_c_wstr(s: &str) -> Option<Box<[u16]>> {
     }
 }
 
+/// Convert a given `OsStr` into a null-terminated wide character string. Does
+/// not check for unexpected null characters.
+///
+/// `on_error` specifies how unpaired surrogates are handled. Returns `None` if
+/// one is found and `on_error` is [`CodecvtError::Reject`].
+pub fn os_str_to_c_wstr(s: &OsStr, on_error: CodecvtError) -> Option<Box<[u16]>> {
+    let mut out = Vec::with_capacity(s.len() + 1);
+    for c in std::char::decode_utf16(s.encode_wide()) {
+        match (c, on_error) {
+            (Ok(c), _) => out.extend_from_slice(c.encode_utf16(&mut [0; 2])),
+            (Err(_), CodecvtError::Replace) => out.push(std::char::REPLACEMENT_CHARACTER as u16),
+            (Err(_), CodecvtError::Reject) => return None,
+        }
+    }
+    out.push(0); // null termination
+    Some(out.into())
+}
+
+/// Convert a null-terminated wide character string into a `String`.
+///
+/// `on_error` specifies how unpaired surrogates are handled. Returns `None` if
+/// `ptr` is null, or if one is found and `on_error` is
+/// [`CodecvtError::Reject`].
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a null-terminated wide character string.
+pub unsafe fn wstr_to_string(ptr: *const u16, on_error: CodecvtError) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    wvec_to_string(std::slice::from_raw_parts(ptr, len), on_error)
+}
+
+/// Convert a wide character string into a `String`. The string ends at the
+/// first null character, if any.
+///
+/// `on_error` specifies how unpaired surrogates are handled. Returns `None` if
+/// one is found and `on_error` is [`CodecvtError::Reject`].
+#[inline]
+pub fn wvec_to_string(v: &[u16], on_error: CodecvtError) -> Option<String> {
+    let len = v.iter().position(|&c| c == 0).unwrap_or(v.len());
+    match on_error {
+        CodecvtError::Replace => Some(String::from_utf16_lossy(&v[..len])),
+        CodecvtError::Reject => String::from_utf16(&v[..len]).ok(),
+    }
+}
+
+/// Convert a wide character string into a multibyte string in the code page
+/// `code_page` (e.g., `CP_ACP`, `CP_OEMCP`, or `CP_UTF8`) using
+/// `WideCharToMultiByte`. A null terminator, if any, is converted like any
+/// other character.
+///
+/// Characters that cannot be represented in the code page are replaced with
+/// its default character (usually `?`), and unpaired surrogates with U+FFFD
+/// REPLACEMENT CHARACTER in UTF-8. Returns `None` if the input string is too
+/// long or anything goes wrong.
+pub fn wstr_to_multibyte(code_page: u32, s: &[u16]) -> Option<Vec<u8>> {
+    if s.is_empty() {
+        return Some(Vec::new());
+    }
+    unsafe {
+        let in_len = s.len().try_into().ok()?;
+        let num_bytes = WideCharToMultiByte(
+            code_page,
+            0,
+            s.as_ptr(),
+            in_len,
+            null_mut(),
+            0,
+            null(),
+            null_mut(),
+        );
+        if num_bytes <= 0 {
+            return None;
+        }
+
+        let mut out = Vec::<u8>::with_capacity(num_bytes as usize);
+        let out_num_bytes = WideCharToMultiByte(
+            code_page,
+            0,
+            s.as_ptr(),
+            in_len,
+            out.as_mut_ptr() as *mut i8,
+            num_bytes,
+            null(),
+            null_mut(),
+        );
+        if out_num_bytes != num_bytes {
+            return None;
+        }
+        out.set_len(num_bytes as usize);
+
+        Some(out)
+    }
+}
+
+/// Convert a multibyte string in the code page `code_page` into a wide
+/// character string using `MultiByteToWideChar`. The reverse of
+/// [`wstr_to_multibyte`].
+///
+/// Invalid sequences are replaced with U+FFFD REPLACEMENT CHARACTER or the
+/// code page's default character. Returns `None` if the input string is too
+/// long or anything goes wrong.
+pub fn multibyte_to_wstr(code_page: u32, s: &[u8]) -> Option<Vec<u16>> {
+    if s.is_empty() {
+        return Some(Vec::new());
+    }
+    unsafe {
+        let in_len = s.len().try_into().ok()?;
+        let num_wchars =
+            MultiByteToWideChar(code_page, 0, s.as_ptr() as *const i8, in_len, null_mut(), 0);
+        if num_wchars <= 0 {
+            return None;
+        }
+
+        let mut out = Vec::<u16>::with_capacity(num_wchars as usize);
+        let out_num_wchars = MultiByteToWideChar(
+            code_page,
+            0,
+            s.as_ptr() as *const i8,
+            in_len,
+            out.as_mut_ptr(),
+            num_wchars,
+        );
+        if out_num_wchars != num_wchars {
+            return None;
+        }
+        out.set_len(num_wchars as usize);
+
+        Some(out)
+    }
+}
+
+/// Displays a wide character string, replacing unpaired surrogates with
+/// U+FFFD REPLACEMENT CHARACTER.
+pub struct WideDisplay<'a>(pub &'a [u16]);
+
+impl fmt::Display for WideDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use fmt::Write;
+        for c in std::char::decode_utf16(self.0.iter().cloned()) {
+            f.write_char(c.unwrap_or(std::char::REPLACEMENT_CHARACTER))?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::os::windows::ffi::OsStringExt;
     use wchar::wch_c;
 
     #[test]
@@ -51,4 +227,76 @@ mod tests {
         assert_eq!(*str_to_c_wstr("книга").unwrap(), *wch_c!("книга"));
         assert_eq!(*str_to_c_wstr("🦄✨").unwrap(), *wch_c!("🦄✨"));
     }
+
+    #[test]
+    fn test_str_to_c_wstr_surrogate_pairs() {
+        // U+1F984 UNICORN FACE, U+2728 SPARKLES, U+10348 GOTHIC LETTER HWAIR
+        assert_eq!(
+            *str_to_c_wstr("🦄✨𐍈").unwrap(),
+            [0xd83e, 0xdd84, 0x2728, 0xd800, 0xdf48, 0][..]
+        );
+        // Non-BMP characters at both ends
+        assert_eq!(
+            *str_to_c_wstr("𐍈a𐍈").unwrap(),
+            [0xd800, 0xdf48, 0x61, 0xd800, 0xdf48, 0][..]
+        );
+        // Exactly one null terminator, regardless of the length
+        for n in 0..64 {
+            let s = "🦄".repeat(n);
+            let wide = str_to_c_wstr(&s).unwrap();
+            assert_eq!(wide.len(), n * 2 + 1);
+            assert_eq!(wide.iter().filter(|&&c| c == 0).count(), 1);
+            assert_eq!(wide.last(), Some(&0));
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &s in &["", "книга", "🦄✨", "line 1\nline 2"] {
+            let wide = str_to_c_wstr(s).unwrap();
+            for &on_error in &[CodecvtError::Replace, CodecvtError::Reject] {
+                assert_eq!(wvec_to_string(&wide, on_error).as_deref(), Some(s));
+                assert_eq!(
+                    unsafe { wstr_to_string(wide.as_ptr(), on_error) }.as_deref(),
+                    Some(s)
+                );
+                assert_eq!(*os_str_to_c_wstr(OsStr::new(s), on_error).unwrap(), *wide);
+            }
+        }
+        assert_eq!(
+            unsafe { wstr_to_string(std::ptr::null(), CodecvtError::Replace) },
+            None
+        );
+    }
+
+    #[test]
+    fn test_unpaired_surrogate() {
+        let wide = [0x61, 0xd83e, 0x62];
+        assert_eq!(
+            wvec_to_string(&wide, CodecvtError::Replace).as_deref(),
+            Some("a\u{fffd}b")
+        );
+        assert_eq!(wvec_to_string(&wide, CodecvtError::Reject), None);
+        let os_string = std::ffi::OsString::from_wide(&wide);
+        assert_eq!(
+            *os_str_to_c_wstr(&os_string, CodecvtError::Replace).unwrap(),
+            [0x61, 0xfffd, 0x62, 0][..]
+        );
+        assert_eq!(os_str_to_c_wstr(&os_string, CodecvtError::Reject), None);
+    }
+
+    #[test]
+    fn test_wstr_to_multibyte() {
+        assert_eq!(wstr_to_multibyte(CP_UTF8, &[]).unwrap(), b"");
+        assert_eq!(
+            wstr_to_multibyte(CP_UTF8, wch_c!("книга")).unwrap(),
+            "книга\0".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_wide_display() {
+        assert_eq!(WideDisplay(&[0x61, 0xd83e, 0xdd84]).to_string(), "a🦄");
+        assert_eq!(WideDisplay(&[0x61, 0xd83e, 0x62]).to_string(), "a\u{fffd}b");
+    }
 }
@@ -0,0 +1,3 @@
+//! Drop-in replacements for the APIs of other logging crates, to ease
+//! migrating to this crate.
+pub mod simple;
@@ -0,0 +1,202 @@
+//! A [`SimpleLogger`] with the same methods as `simple_logger`'s, which
+//! configures this crate's logger instead.
+//!
+//! Switching a project from `simple_logger` only requires changing the
+//! `use` declaration:
+//!
+//! ```diff
+//! -use simple_logger::SimpleLogger;
+//! +use windebug_logger::compat::simple::SimpleLogger;
+//! ```
+//!
+//! ```
+//! # fn main() {
+//! use windebug_logger::compat::simple::SimpleLogger;
+//!
+//! SimpleLogger::new()
+//!     .with_level(log::LevelFilter::Info)
+//!     .with_module_level("hyper", log::LevelFilter::Warn)
+//!     .env()
+//!     .init()
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! Settings that have no counterpart in this crate, such as
+//! [`SimpleLogger::with_colors`], are accepted and ignored, and each of them
+//! is reported by a `Debug` record with the target `windebug_logger` after
+//! initialization. Use [`SimpleLogger::into_builder`] to reach the settings
+//! specific to this crate.
+use log::{Level, LevelFilter, SetLoggerError};
+
+use crate::{parse_level, WinDebugLoggerBuilder};
+
+/// A builder with the same methods as `simple_logger::SimpleLogger`. See
+/// the [module-level documentation](self).
+#[derive(Debug, Clone)]
+#[must_use = "a `SimpleLogger` does nothing until `init` is called"]
+pub struct SimpleLogger {
+    builder: WinDebugLoggerBuilder,
+    /// The names of the methods called that have no effect.
+    ignored: Vec<&'static str>,
+}
+
+impl Default for SimpleLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimpleLogger {
+    /// Construct a `SimpleLogger` logging everything with local timestamps,
+    /// like `simple_logger`.
+    pub fn new() -> Self {
+        Self {
+            builder: WinDebugLoggerBuilder::new().utc(false),
+            ignored: Vec::new(),
+        }
+    }
+
+    /// Set the level filter of the records not covered by
+    /// [`with_module_level`](Self::with_module_level).
+    pub fn with_level(self, level: LevelFilter) -> Self {
+        Self {
+            builder: self.builder.level(level),
+            ..self
+        }
+    }
+
+    /// Set the level filter of the records from `target` and its
+    /// descendants. See [`WinDebugLoggerBuilder::target_level`].
+    pub fn with_module_level(self, target: &str, level: LevelFilter) -> Self {
+        Self {
+            builder: self.builder.target_level(target, level),
+            ..self
+        }
+    }
+
+    /// Take the level filter from the `RUST_LOG` environment variable, which
+    /// is parsed by [`parse_level`]. The level filter set by
+    /// [`with_level`](Self::with_level) is kept if the variable is missing
+    /// or invalid.
+    pub fn env(self) -> Self {
+        match std::env::var("RUST_LOG").ok().map(|s| parse_level(&s)) {
+            Some(Ok(level)) => self.with_level(level),
+            _ => self,
+        }
+    }
+
+    /// Render timestamps in the local time zone (the default).
+    pub fn with_local_timestamps(self) -> Self {
+        Self {
+            builder: self.builder.utc(false),
+            ..self
+        }
+    }
+
+    /// Render timestamps in UTC.
+    pub fn with_utc_timestamps(self) -> Self {
+        Self {
+            builder: self.builder.utc(true),
+            ..self
+        }
+    }
+
+    /// Omit timestamps.
+    pub fn without_timestamps(self) -> Self {
+        Self {
+            builder: self.builder.timestamp_fn(|_| {}),
+            ..self
+        }
+    }
+
+    /// Has no effect; debuggers show lines without colors.
+    pub fn with_colors(self, _colors: bool) -> Self {
+        self.ignore("with_colors")
+    }
+
+    /// Has no effect; this crate does not show thread names.
+    pub fn with_threads(self, _threads: bool) -> Self {
+        self.ignore("with_threads")
+    }
+
+    fn ignore(mut self, method: &'static str) -> Self {
+        if !self.ignored.contains(&method) {
+            self.ignored.push(method);
+        }
+        self
+    }
+
+    /// Get the maximum level filter this `SimpleLogger` would enable.
+    pub fn max_level(&self) -> LevelFilter {
+        self.builder
+            .level
+            .max(self.builder.target_filter.max_level())
+    }
+
+    /// Get the equivalent [`WinDebugLoggerBuilder`], e.g., to apply the
+    /// settings specific to this crate before initialization.
+    pub fn into_builder(self) -> WinDebugLoggerBuilder {
+        self.builder
+    }
+
+    /// Initialize the global logger with this configuration.
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        self.builder.install()?;
+        for method in self.ignored {
+            log::debug!(
+                target: "windebug_logger",
+                "`SimpleLogger::{}` has no effect with windebug_logger",
+                method
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Initialize the global logger, logging everything. Equivalent to
+/// `simple_logger::init`.
+pub fn init() -> Result<(), SetLoggerError> {
+    SimpleLogger::new().init()
+}
+
+/// Initialize the global logger with a specific log level. Equivalent to
+/// `simple_logger::init_with_level`.
+pub fn init_with_level(level: Level) -> Result<(), SetLoggerError> {
+    SimpleLogger::new()
+        .with_level(level.to_level_filter())
+        .init()
+}
+
+/// Initialize the global logger with the level filter given by the
+/// `RUST_LOG` environment variable. Equivalent to
+/// `simple_logger::init_with_env`.
+pub fn init_with_env() -> Result<(), SetLoggerError> {
+    SimpleLogger::new().env().init()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let logger = SimpleLogger::new()
+            .with_level(LevelFilter::Warn)
+            .with_module_level("app::net", LevelFilter::Trace)
+            .with_utc_timestamps()
+            .with_colors(true)
+            .with_colors(false)
+            .with_threads(true);
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+        assert_eq!(logger.ignored, ["with_colors", "with_threads"]);
+
+        let builder = logger.into_builder();
+        assert_eq!(builder.level, LevelFilter::Warn);
+        assert_eq!(
+            builder.target_filter.level_for("app::net::http"),
+            Some(LevelFilter::Trace)
+        );
+        assert!(builder.utc);
+    }
+}
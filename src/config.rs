@@ -0,0 +1,557 @@
+//! A plain configuration, optionally serializable. See [`Config`].
+use log::{Level, LevelFilter};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "toml", feature = "json"))]
+use std::path::Path;
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+    time::Duration,
+};
+
+#[cfg(any(feature = "toml", feature = "json"))]
+use crate::Error;
+use crate::{LevelStyle, OutputFormat, WinDebugLoggerBuilder};
+
+/// A configuration of the logger as plain data, applied by
+/// [`WinDebugLoggerBuilder::from_config`].
+///
+/// Each field corresponds to the builder method of the same name, and
+/// [`Default::default`] returns the builder's defaults. Durations are given
+/// in milliseconds. Start with `Config::default()` and assign the fields to
+/// change, or fill in the rest of a struct literal with
+/// `..Config::default()`:
+///
+/// ```
+/// let mut config = windebug_logger::Config {
+///     level: log::LevelFilter::Info,
+///     ..windebug_logger::Config::default()
+/// };
+/// config.targets.insert("hyper".to_owned(), log::LevelFilter::Warn);
+/// let builder = windebug_logger::WinDebugLoggerBuilder::from_config(config).unwrap();
+/// ```
+///
+/// With the `serde` feature, this implements `Deserialize` and `Serialize`,
+/// e.g., to read it from a section of an application's settings file.
+/// Missing fields take the builder's defaults, and unknown fields are
+/// rejected. Levels are serialized as lowercase names, e.g., `"info"`, and
+/// deserialized by [`parse_level`](crate::parse_level), which also accepts
+/// other cases and numbers given as strings.
+///
+/// ```json
+/// {
+///     "level": "info",
+///     "targets": { "app::net": "trace", "hyper": "warn" },
+///     "trace_window_ms": 30000,
+///     "level_style": "bracketed",
+///     "level_symbols": { "error": "✖" },
+///     "file": "C:\\ProgramData\\App\\app.log",
+///     "stderr": true
+/// }
+/// ```
+///
+/// Settings that take Rust values, such as
+/// [`WinDebugLoggerBuilder::custom_formatter`] and
+/// [`WinDebugLoggerBuilder::sink`], can be applied to the builder returned
+/// by `from_config`.
+///
+/// [`WinDebugLoggerBuilder::from_config`]: crate::WinDebugLoggerBuilder::from_config
+/// [`WinDebugLoggerBuilder::custom_formatter`]: crate::WinDebugLoggerBuilder::custom_formatter
+/// [`WinDebugLoggerBuilder::sink`]: crate::WinDebugLoggerBuilder::sink
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct Config {
+    /// The level filter. See
+    /// [`WinDebugLoggerBuilder::level`](crate::WinDebugLoggerBuilder::level).
+    #[cfg_attr(feature = "serde", serde(with = "lowercase::level_filter"))]
+    pub level: LevelFilter,
+    /// The level filters of targets. See
+    /// [`WinDebugLoggerBuilder::target_level`](crate::WinDebugLoggerBuilder::target_level).
+    #[cfg_attr(feature = "serde", serde(with = "lowercase::level_filters"))]
+    pub targets: BTreeMap<String, LevelFilter>,
+    /// The only targets whose records are kept. See
+    /// [`WinDebugLoggerBuilder::allow_targets`](crate::WinDebugLoggerBuilder::allow_targets).
+    pub allow_targets: Option<Vec<String>>,
+    /// The targets whose records are discarded. See
+    /// [`WinDebugLoggerBuilder::deny_targets`](crate::WinDebugLoggerBuilder::deny_targets).
+    pub deny_targets: Option<Vec<String>>,
+    /// The duration of the trace window in milliseconds. See
+    /// [`WinDebugLoggerBuilder::trace_window`](crate::WinDebugLoggerBuilder::trace_window).
+    pub trace_window_ms: Option<u64>,
+    /// The level filter used during the trace window. See
+    /// [`WinDebugLoggerBuilder::trace_window_level`](crate::WinDebugLoggerBuilder::trace_window_level).
+    #[cfg_attr(feature = "serde", serde(with = "lowercase::level_filter"))]
+    pub trace_window_level: LevelFilter,
+    /// The name of the event object used as the master switch. See
+    /// [`WinDebugLoggerBuilder::enable_event`](crate::WinDebugLoggerBuilder::enable_event).
+    pub enable_event: Option<String>,
+    /// The layout of lines. See
+    /// [`WinDebugLoggerBuilder::output_format`](crate::WinDebugLoggerBuilder::output_format).
+    pub output_format: OutputFormat,
+    /// How the level of each record is rendered. See
+    /// [`WinDebugLoggerBuilder::level_style`](crate::WinDebugLoggerBuilder::level_style).
+    pub level_style: LevelStyle,
+    /// The symbols shown before the levels. See
+    /// [`WinDebugLoggerBuilder::level_symbol`](crate::WinDebugLoggerBuilder::level_symbol).
+    #[cfg_attr(feature = "serde", serde(with = "lowercase::level_keys"))]
+    pub level_symbols: BTreeMap<Level, String>,
+    /// Whether timestamps are rendered in UTC. See
+    /// [`WinDebugLoggerBuilder::utc`](crate::WinDebugLoggerBuilder::utc).
+    pub utc: bool,
+    /// The locale identifier used for rendering timestamps. See
+    /// [`WinDebugLoggerBuilder::locale`](crate::WinDebugLoggerBuilder::locale).
+    pub locale: u32,
+    /// Whether each line includes the session ID. See
+    /// [`WinDebugLoggerBuilder::session_id`](crate::WinDebugLoggerBuilder::session_id).
+    pub session_id: bool,
+    /// Whether the environment is logged on initialization. See
+    /// [`WinDebugLoggerBuilder::log_environment`](crate::WinDebugLoggerBuilder::log_environment).
+    pub log_environment: bool,
+    /// The window in milliseconds in which duplicate messages are suppressed.
+    /// See
+    /// [`WinDebugLoggerBuilder::suppress_duplicates`](crate::WinDebugLoggerBuilder::suppress_duplicates).
+    pub suppress_duplicates_ms: Option<u64>,
+    /// The number of distinct messages remembered for suppressing duplicates.
+    /// See
+    /// [`WinDebugLoggerBuilder::suppress_duplicates_capacity`](crate::WinDebugLoggerBuilder::suppress_duplicates_capacity).
+    pub suppress_duplicates_capacity: usize,
+    /// The sampling rates of targets. See
+    /// [`WinDebugLoggerBuilder::with_sampling`](crate::WinDebugLoggerBuilder::with_sampling).
+    pub sampling: BTreeMap<String, u32>,
+    /// The number of rejected records retained for the next error. See
+    /// [`WinDebugLoggerBuilder::debug_on_error`](crate::WinDebugLoggerBuilder::debug_on_error).
+    pub debug_on_error: Option<usize>,
+    /// The least severe level retained for the next error. See
+    /// [`WinDebugLoggerBuilder::debug_on_error_level`](crate::WinDebugLoggerBuilder::debug_on_error_level).
+    #[cfg_attr(feature = "serde", serde(with = "lowercase::level_filter"))]
+    pub debug_on_error_level: LevelFilter,
+    /// Whether a banner is logged on initialization. See
+    /// [`WinDebugLoggerBuilder::banner`](crate::WinDebugLoggerBuilder::banner).
+    pub banner: bool,
+    /// Whether a line marking the initialization is logged. See
+    /// [`WinDebugLoggerBuilder::log_on_init`](crate::WinDebugLoggerBuilder::log_on_init).
+    pub log_on_init: bool,
+    /// The application version shown in the banner. See
+    /// [`WinDebugLoggerBuilder::app_version`](crate::WinDebugLoggerBuilder::app_version).
+    pub app_version: Option<String>,
+    /// Whether the level tag is written by its own call. See
+    /// [`WinDebugLoggerBuilder::split_level_tag`](crate::WinDebugLoggerBuilder::split_level_tag).
+    pub split_level_tag: bool,
+    /// Whether repeated targets are replaced with a placeholder. See
+    /// [`WinDebugLoggerBuilder::elide_repeated_targets`](crate::WinDebugLoggerBuilder::elide_repeated_targets).
+    pub elide_repeated_targets: bool,
+    /// The length in UTF-16 code units beyond which messages are truncated. See
+    /// [`WinDebugLoggerBuilder::with_max_message_len`](crate::WinDebugLoggerBuilder::with_max_message_len).
+    pub max_message_len: Option<usize>,
+    /// Whether the source location is appended to messages. See
+    /// [`WinDebugLoggerBuilder::source_location`](crate::WinDebugLoggerBuilder::source_location).
+    pub source_location: bool,
+    /// The indentation width per nested time scope. See
+    /// [`WinDebugLoggerBuilder::span_indent`](crate::WinDebugLoggerBuilder::span_indent).
+    pub span_indent: usize,
+    /// Whether each line shows the frame number. See
+    /// [`WinDebugLoggerBuilder::frame_number`](crate::WinDebugLoggerBuilder::frame_number).
+    pub frame_number: bool,
+    /// Whether the source location uses the DebugView++ syntax. See
+    /// [`WinDebugLoggerBuilder::debugview_plus_plus`](crate::WinDebugLoggerBuilder::debugview_plus_plus).
+    pub debugview_plus_plus: bool,
+    /// The interval of heartbeat records in milliseconds. See
+    /// [`WinDebugLoggerBuilder::heartbeat`](crate::WinDebugLoggerBuilder::heartbeat).
+    pub heartbeat_ms: Option<u64>,
+    /// The target shown for records without one. See
+    /// [`WinDebugLoggerBuilder::with_default_target`](crate::WinDebugLoggerBuilder::with_default_target).
+    pub default_target: String,
+    /// Whether each line shows the name of the module containing this crate.
+    /// See
+    /// [`WinDebugLoggerBuilder::with_module_name`](crate::WinDebugLoggerBuilder::with_module_name).
+    pub module_name: bool,
+    /// The name of the logger. See
+    /// [`WinDebugLoggerBuilder::name`](crate::WinDebugLoggerBuilder::name).
+    pub name: String,
+    /// The code page lines are converted to for `OutputDebugStringA`. See
+    /// [`WinDebugLoggerBuilder::code_page`](crate::WinDebugLoggerBuilder::code_page).
+    pub code_page: Option<u32>,
+    /// The initial capacity of the buffers each line is formatted into. See
+    /// [`WinDebugLoggerBuilder::initial_buffer_capacity`](crate::WinDebugLoggerBuilder::initial_buffer_capacity).
+    pub initial_buffer_capacity: usize,
+    /// The path of the log file. See
+    /// [`WinDebugLoggerBuilder::file`](crate::WinDebugLoggerBuilder::file).
+    pub file: Option<PathBuf>,
+    /// Whether lines are also written to the standard error stream. See
+    /// [`WinDebugLoggerBuilder::stderr`](crate::WinDebugLoggerBuilder::stderr).
+    pub stderr: bool,
+    /// Whether the lines written to the standard error stream are colored. See
+    /// [`WinDebugLoggerBuilder::ansi_colors`](crate::WinDebugLoggerBuilder::ansi_colors).
+    pub ansi_colors: bool,
+    /// The source name for
+    /// [`WinDebugLoggerBuilder::event_log`](crate::WinDebugLoggerBuilder::event_log).
+    pub event_log: Option<String>,
+    /// The pipe name for
+    /// [`WinDebugLoggerBuilder::pipe`](crate::WinDebugLoggerBuilder::pipe).
+    pub pipe: Option<String>,
+    /// The number of lines kept in memory. See
+    /// [`WinDebugLoggerBuilder::ring_buffer`](crate::WinDebugLoggerBuilder::ring_buffer).
+    pub ring_buffer: Option<usize>,
+    /// Whether the `OutputDebugStringW` call is skipped. See
+    /// [`WinDebugLoggerBuilder::dry_run`](crate::WinDebugLoggerBuilder::dry_run).
+    pub dry_run: bool,
+    /// Whether the `OutputDebugStringW` call is skipped while nobody receives
+    /// the output. See
+    /// [`WinDebugLoggerBuilder::skip_when_unattended`](crate::WinDebugLoggerBuilder::skip_when_unattended).
+    pub skip_when_unattended: bool,
+    /// Whether buffered lines are flushed after each error. See
+    /// [`WinDebugLoggerBuilder::flush_on_error`](crate::WinDebugLoggerBuilder::flush_on_error).
+    pub flush_on_error: bool,
+}
+
+impl Default for Config {
+    /// Get the configuration equivalent to `WinDebugLoggerBuilder::new()`.
+    fn default() -> Self {
+        let builder = WinDebugLoggerBuilder::new();
+        let millis = |duration: Duration| duration.as_millis() as u64;
+        let sorted = |targets: HashSet<String>| {
+            let mut targets: Vec<String> = targets.into_iter().collect();
+            targets.sort();
+            targets
+        };
+        Self {
+            level: builder.level,
+            targets: (builder.target_filter.iter())
+                .map(|(target, level)| (target.to_owned(), level))
+                .collect(),
+            allow_targets: builder.allowed_targets.map(sorted),
+            deny_targets: builder.denied_targets.map(sorted),
+            trace_window_ms: builder.trace_window.map(millis),
+            trace_window_level: builder.trace_window_level,
+            enable_event: builder.enable_event,
+            output_format: builder.output_format,
+            level_style: builder.level_style,
+            level_symbols: Level::iter()
+                .zip(builder.level_symbols.iter())
+                .filter(|(_, symbol)| !symbol.is_empty())
+                .map(|(level, symbol)| (level, symbol.clone()))
+                .collect(),
+            utc: builder.utc,
+            locale: builder.locale,
+            session_id: builder.session_id,
+            log_environment: builder.log_environment,
+            suppress_duplicates_ms: builder.suppress_duplicates.map(millis),
+            suppress_duplicates_capacity: builder.suppress_duplicates_capacity,
+            sampling: builder.sampling.into_iter().collect(),
+            debug_on_error: builder.debug_on_error,
+            debug_on_error_level: builder.debug_on_error_level,
+            banner: builder.banner,
+            log_on_init: builder.log_on_init,
+            app_version: builder.app_version,
+            split_level_tag: builder.split_level_tag,
+            elide_repeated_targets: builder.elide_repeated_targets,
+            max_message_len: builder.max_message_len,
+            source_location: builder.source_location,
+            span_indent: builder.span_indent,
+            frame_number: builder.frame_number,
+            debugview_plus_plus: builder.debugview_plus_plus,
+            heartbeat_ms: builder.heartbeat.map(millis),
+            default_target: builder.default_target,
+            module_name: builder.module_name,
+            name: builder.name.into_owned(),
+            code_page: builder.code_page,
+            initial_buffer_capacity: builder.initial_buffer_capacity,
+            // `new` attaches no sinks
+            file: None,
+            stderr: false,
+            event_log: None,
+            pipe: None,
+            ansi_colors: builder.ansi_colors,
+            ring_buffer: builder.ring_buffer,
+            dry_run: builder.dry_run,
+            skip_when_unattended: builder.skip_when_unattended,
+            flush_on_error: builder.flush_on_error,
+        }
+    }
+}
+
+impl Config {
+    /// Read a `Config` from the file at `path`, or return `Ok(None)` if the
+    /// file does not exist.
+    ///
+    /// Files whose extension is `.json` are parsed as JSON, which requires
+    /// the `json` feature, and the others as TOML, which requires the `toml`
+    /// feature. Malformed files are reported as [`Error::Config`] with the
+    /// location of the error.
+    #[cfg(any(feature = "toml", feature = "json"))]
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let path = path.as_ref();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::io(path, e)),
+        };
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        if is_json {
+            parse_json(path, &text).map(Some)
+        } else {
+            parse_toml(path, &text).map(Some)
+        }
+    }
+}
+
+/// Serialization of levels as lowercase names, used with `#[serde(with)]`.
+#[cfg(feature = "serde")]
+mod lowercase {
+    use log::{Level, LevelFilter};
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::collections::BTreeMap;
+
+    fn name(level: impl std::fmt::Display) -> String {
+        level.to_string().to_ascii_lowercase()
+    }
+
+    fn parse<E: serde::de::Error>(s: &str) -> Result<LevelFilter, E> {
+        crate::parse_level(s).map_err(E::custom)
+    }
+
+    pub mod level_filter {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            level: &LevelFilter,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&name(level))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<LevelFilter, D::Error> {
+            parse(&String::deserialize(deserializer)?)
+        }
+    }
+
+    /// Maps from targets to level filters.
+    pub mod level_filters {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            levels: &BTreeMap<String, LevelFilter>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(levels.iter().map(|(target, level)| (target, name(level))))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<BTreeMap<String, LevelFilter>, D::Error> {
+            BTreeMap::<String, String>::deserialize(deserializer)?
+                .into_iter()
+                .map(|(target, level)| Ok((target, parse::<D::Error>(&level)?)))
+                .collect()
+        }
+    }
+
+    /// Maps keyed by levels.
+    pub mod level_keys {
+        use super::*;
+        use serde::de::Error as _;
+
+        pub fn serialize<S: Serializer>(
+            map: &BTreeMap<Level, String>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(map.iter().map(|(level, value)| (name(level), value)))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<BTreeMap<Level, String>, D::Error> {
+            BTreeMap::<String, String>::deserialize(deserializer)?
+                .into_iter()
+                .map(|(level, value)| {
+                    let level = parse::<D::Error>(&level)?
+                        .to_level()
+                        .ok_or_else(|| D::Error::custom("expected a level other than off"))?;
+                    Ok((level, value))
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(any(feature = "toml", feature = "json"))]
+fn config_error(path: &Path, line: Option<usize>, column: Option<usize>, message: String) -> Error {
+    Error::Config {
+        path: path.to_owned(),
+        line,
+        column,
+        message,
+    }
+}
+
+#[cfg(feature = "json")]
+fn parse_json(path: &Path, text: &str) -> Result<Config, Error> {
+    serde_json::from_str(text).map_err(|e| json_error(path, e))
+}
+
+/// Convert an error from `serde_json` to [`Error::Config`].
+#[cfg(feature = "json")]
+pub(crate) fn json_error(path: &Path, e: serde_json::Error) -> Error {
+    if e.is_io() {
+        return Error::io(path, e.into());
+    }
+    // `serde_json` appends the location to the message
+    let message = e.to_string();
+    let suffix = format!(" at line {} column {}", e.line(), e.column());
+    let message = message.strip_suffix(&suffix).unwrap_or(&message).to_owned();
+    match e.line() {
+        0 => config_error(path, None, None, message),
+        line => config_error(path, Some(line), Some(e.column()), message),
+    }
+}
+
+#[cfg(all(feature = "toml", not(feature = "json")))]
+fn parse_json(path: &Path, _text: &str) -> Result<Config, Error> {
+    Err(config_error(
+        path,
+        None,
+        None,
+        "reading JSON requires the `json` feature".to_owned(),
+    ))
+}
+
+#[cfg(feature = "toml")]
+fn parse_toml(path: &Path, text: &str) -> Result<Config, Error> {
+    toml::from_str(text).map_err(|e| {
+        let message = e.message().to_owned();
+        match e.span() {
+            Some(span) => {
+                let (line, column) = line_column(text, span.start);
+                config_error(path, Some(line), Some(column), message)
+            }
+            None => config_error(path, None, None, message),
+        }
+    })
+}
+
+#[cfg(all(feature = "json", not(feature = "toml")))]
+fn parse_toml(path: &Path, _text: &str) -> Result<Config, Error> {
+    Err(config_error(
+        path,
+        None,
+        None,
+        "reading TOML requires the `toml` feature".to_owned(),
+    ))
+}
+
+/// Convert a byte offset in `text` to a one-based line and column, counting
+/// columns in characters.
+#[cfg(feature = "toml")]
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default() {
+        let builder = WinDebugLoggerBuilder::from_config(Config::default()).unwrap();
+        assert_eq!(
+            format!("{:?}", builder),
+            format!("{:?}", WinDebugLoggerBuilder::new())
+        );
+
+        let config = Config {
+            level: LevelFilter::Info,
+            targets: BTreeMap::from([("hyper".to_owned(), LevelFilter::Warn)]),
+            ..Config::default()
+        };
+        let builder = WinDebugLoggerBuilder::from_config(config).unwrap();
+        assert_eq!(builder.level, LevelFilter::Info);
+        assert_eq!(
+            builder.target_filter.level_for("hyper::client"),
+            Some(LevelFilter::Warn)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "level": "info",
+                "trace_window_ms": 30000,
+                "level_style": "bracketed",
+                "level_symbols": { "error": "x" },
+                "stderr": true
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.level, LevelFilter::Info);
+        assert_eq!(config.trace_window_ms, Some(30000));
+        assert_eq!(config.level_style, LevelStyle::Bracketed);
+        assert_eq!(config.level_symbols[&Level::Error], "x");
+        assert!(config.stderr);
+        assert!(config.utc);
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains(r#""level":"info""#), "{}", json);
+        assert!(
+            json.contains(r#""level_symbols":{"error":"x"}"#),
+            "{}",
+            json
+        );
+        let round_trip: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_trip, config);
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_line_column() {
+        let text = "level = \"info\"\nstderr = \"yes\"\n";
+        assert_eq!(line_column(text, 0), (1, 1));
+        assert_eq!(line_column(text, text.find("\"yes").unwrap()), (2, 10));
+    }
+
+    #[cfg(any(feature = "toml", feature = "json"))]
+    #[test]
+    fn test_from_file_missing() {
+        let config = Config::from_file("this/file/does/not/exist.toml").unwrap();
+        assert_eq!(config, None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_levels() {
+        let config: Config = serde_json::from_str(
+            r#"{ "level": "Warning", "targets": { "hyper": "2" }, "level_symbols": { "INFO": "i" } }"#,
+        )
+        .unwrap();
+        assert_eq!(config.level, LevelFilter::Warn);
+        assert_eq!(config.targets["hyper"], LevelFilter::Warn);
+        assert_eq!(config.level_symbols[&Level::Info], "i");
+
+        let result = serde_json::from_str::<Config>(r#"{ "level_symbols": { "off": "-" } }"#);
+        assert!(result.unwrap_err().to_string().contains("other than off"));
+        let result = serde_json::from_str::<Config>(r#"{ "level": "loud" }"#);
+        assert!(result.unwrap_err().to_string().contains("\"loud\""));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_unknown_field() {
+        let result = serde_json::from_str::<Config>(r#"{ "levle": "info" }"#);
+        assert!(result.unwrap_err().to_string().contains("levle"));
+    }
+}
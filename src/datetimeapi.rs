@@ -0,0 +1,199 @@
+//! Safe wrappers around the date and time formatting functions of Win32.
+use std::{
+    convert::TryInto,
+    ptr::{null, null_mut},
+};
+use winapi::{
+    ctypes::c_int,
+    shared::{minwindef::DWORD, winerror::ERROR_INSUFFICIENT_BUFFER},
+    um::{
+        datetimeapi::{GetDateFormatW, GetTimeFormatW},
+        errhandlingapi::GetLastError,
+        minwinbase::SYSTEMTIME,
+        winnt::{LCID, LPCWSTR, LPWSTR},
+    },
+};
+
+type FormatFn =
+    unsafe extern "system" fn(LCID, DWORD, *const SYSTEMTIME, LPCWSTR, LPWSTR, c_int) -> c_int;
+
+/// The size of the stack buffer tried first. Enough for the date and time
+/// formats of all locales in practice.
+const STACK_BUF_LEN: usize = 64;
+
+/// Format the date part of `system_time` using `GetDateFormatW` and append
+/// it to `buf`.
+///
+/// `format`, if given, must be a null-terminated picture string such as
+/// `yyyy-MM-dd`. The null terminator is not appended. Returns `None` and
+/// leaves `buf` unchanged if the call fails.
+pub fn format_date_into(
+    buf: &mut Vec<u16>,
+    system_time: &SYSTEMTIME,
+    locale: u32,
+    flags: u32,
+    format: Option<&[u16]>,
+) -> Option<()> {
+    format_into(GetDateFormatW, buf, system_time, locale, flags, format)
+}
+
+/// Format the time part of `system_time` using `GetTimeFormatW` and append
+/// it to `buf`.
+///
+/// `format`, if given, must be a null-terminated picture string such as
+/// `HH:mm:ss`. The null terminator is not appended. Returns `None` and leaves
+/// `buf` unchanged if the call fails.
+pub fn format_time_into(
+    buf: &mut Vec<u16>,
+    system_time: &SYSTEMTIME,
+    locale: u32,
+    flags: u32,
+    format: Option<&[u16]>,
+) -> Option<()> {
+    format_into(GetTimeFormatW, buf, system_time, locale, flags, format)
+}
+
+fn format_into(
+    api: FormatFn,
+    buf: &mut Vec<u16>,
+    system_time: &SYSTEMTIME,
+    locale: u32,
+    flags: u32,
+    format: Option<&[u16]>,
+) -> Option<()> {
+    let format = match format {
+        Some(format) => {
+            assert_eq!(format.last(), Some(&0), "format must be null-terminated");
+            format.as_ptr()
+        }
+        None => null(),
+    };
+
+    // Try the stack buffer first to avoid a heap allocation
+    let mut stack_buf = [0u16; STACK_BUF_LEN];
+    let len = unsafe {
+        api(
+            locale,
+            flags,
+            system_time,
+            format,
+            stack_buf.as_mut_ptr(),
+            STACK_BUF_LEN as c_int,
+        )
+    };
+    if len > 0 {
+        // Exclude the null terminator
+        buf.extend_from_slice(&stack_buf[..len as usize - 1]);
+        return Some(());
+    }
+    if unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER {
+        return None;
+    }
+
+    // Query the required buffer size, including the null terminator
+    let len = unsafe { api(locale, flags, system_time, format, null_mut(), 0) };
+    if len <= 0 {
+        return None;
+    }
+
+    buf.reserve(len.try_into().ok()?);
+    let spare = buf.spare_capacity_mut();
+    let len = unsafe {
+        api(
+            locale,
+            flags,
+            system_time,
+            format,
+            spare.as_mut_ptr() as LPWSTR,
+            spare.len().try_into().unwrap_or(c_int::MAX),
+        )
+    };
+    if len <= 0 {
+        return None;
+    }
+
+    // Exclude the null terminator
+    unsafe { buf.set_len(buf.len() + len as usize - 1) };
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wchar::{wch, wch_c};
+    use winapi::um::winnt::LOCALE_INVARIANT;
+
+    fn format_date(
+        time: &SYSTEMTIME,
+        locale: u32,
+        flags: u32,
+        format: Option<&[u16]>,
+    ) -> Option<Vec<u16>> {
+        let mut buf = Vec::new();
+        format_date_into(&mut buf, time, locale, flags, format)?;
+        Some(buf)
+    }
+
+    fn format_time(
+        time: &SYSTEMTIME,
+        locale: u32,
+        flags: u32,
+        format: Option<&[u16]>,
+    ) -> Option<Vec<u16>> {
+        let mut buf = Vec::new();
+        format_time_into(&mut buf, time, locale, flags, format)?;
+        Some(buf)
+    }
+
+    fn system_time() -> SYSTEMTIME {
+        SYSTEMTIME {
+            wYear: 2020,
+            wMonth: 1,
+            wDayOfWeek: 4,
+            wDay: 2,
+            wHour: 12,
+            wMinute: 34,
+            wSecond: 56,
+            wMilliseconds: 789,
+        }
+    }
+
+    #[test]
+    fn test_format_date() {
+        let time = system_time();
+        assert_eq!(
+            format_date(&time, LOCALE_INVARIANT, 0, None).unwrap(),
+            wch!("01/02/2020")
+        );
+        assert_eq!(
+            format_date(&time, LOCALE_INVARIANT, 0, Some(wch_c!("yyyy-MM-dd"))).unwrap(),
+            wch!("2020-01-02")
+        );
+    }
+
+    #[test]
+    fn test_format_time() {
+        let time = system_time();
+        assert_eq!(
+            format_time(&time, LOCALE_INVARIANT, 0, None).unwrap(),
+            wch!("12:34:56")
+        );
+        assert_eq!(
+            format_time(&time, LOCALE_INVARIANT, 0, Some(wch_c!("HH'h'mm"))).unwrap(),
+            wch!("12h34")
+        );
+    }
+
+    #[test]
+    fn test_format_into_long() {
+        let time = system_time();
+        let format =
+            wch_c!("yyyy'-----------------------------------------------------------------'MM");
+        let mut buf = wch!("prefix ").to_vec();
+        format_date_into(&mut buf, &time, LOCALE_INVARIANT, 0, Some(format)).unwrap();
+        assert_eq!(
+            buf,
+            wch!("prefix 2020-----------------------------------------------------------------01")
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use log::Level;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Suppresses identical messages. See
+/// [`WinDebugLoggerBuilder::suppress_duplicates`].
+///
+/// The table keeps the target and message of each entry rather than just a
+/// count and a time because the `[suppressed N duplicate messages ...]`
+/// report has to name the message after its last occurrence is gone.
+///
+/// [`WinDebugLoggerBuilder::suppress_duplicates`]: crate::WinDebugLoggerBuilder::suppress_duplicates
+#[derive(Debug)]
+pub(crate) struct DuplicateFilter {
+    window: Duration,
+    capacity: usize,
+    /// Indexed by the SipHash of `(target, level, message)`.
+    table: Mutex<HashMap<u64, Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    level: Level,
+    target: String,
+    message: String,
+    /// The number of suppressed occurrences since the message was last
+    /// logged or reported.
+    count: u32,
+    last_seen: Instant,
+}
+
+/// A message that has been suppressed one or more times and is no longer
+/// being repeated.
+#[derive(Debug)]
+pub(crate) struct Suppressed {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    pub count: u32,
+}
+
+impl DuplicateFilter {
+    pub fn new(window: Duration, capacity: usize) -> Self {
+        Self {
+            window,
+            capacity: capacity.max(1),
+            table: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Check if `record` duplicates a recently logged message. Returns `true`
+    /// if it should be suppressed.
+    ///
+    /// The messages whose windows have expired with one or more suppressed
+    /// occurrences are appended to `expired`.
+    pub fn filter(&self, record: &log::Record, expired: &mut Vec<Suppressed>) -> bool {
+        self.filter_at(record, Instant::now(), expired)
+    }
+
+    /// [`filter`](Self::filter) with the current time given by `now`.
+    fn filter_at(&self, record: &log::Record, now: Instant, expired: &mut Vec<Suppressed>) -> bool {
+        let message = crate::format::message(record);
+
+        let mut hasher = DefaultHasher::new();
+        (record.target(), record.level(), &message).hash(&mut hasher);
+        let key = hasher.finish();
+
+        let mut table = self.table.lock().unwrap_or_else(|e| e.into_inner());
+
+        let suppress = if let Some(entry) = table.get_mut(&key) {
+            let is_duplicate = now.duration_since(entry.last_seen) < self.window;
+            entry.last_seen = now;
+            if is_duplicate {
+                entry.count += 1;
+            }
+            is_duplicate
+        } else {
+            if table.len() >= self.capacity {
+                // Evict the least recently seen message
+                let oldest = table
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_seen)
+                    .map(|(&key, _)| key);
+                if let Some(entry) = oldest.and_then(|key| table.remove(&key)) {
+                    expired.extend(entry.into_suppressed());
+                }
+            }
+
+            table.insert(
+                key,
+                Entry {
+                    level: record.level(),
+                    target: record.target().to_owned(),
+                    message: message.into_owned(),
+                    count: 0,
+                    last_seen: now,
+                },
+            );
+            false
+        };
+
+        // Report the messages that stopped repeating
+        for entry in table.values_mut() {
+            if entry.count > 0 && now.duration_since(entry.last_seen) >= self.window {
+                expired.push(Suppressed {
+                    level: entry.level,
+                    target: entry.target.clone(),
+                    message: entry.message.clone(),
+                    count: entry.count,
+                });
+                entry.count = 0;
+            }
+        }
+
+        suppress
+    }
+}
+
+impl Entry {
+    fn into_suppressed(self) -> Option<Suppressed> {
+        if self.count > 0 {
+            Some(Suppressed {
+                level: self.level,
+                target: self.target,
+                message: self.message,
+                count: self.count,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(filter: &DuplicateFilter, message: &str, expired: &mut Vec<Suppressed>) -> bool {
+        filter_at(filter, message, Instant::now(), expired)
+    }
+
+    fn filter_at(
+        filter: &DuplicateFilter,
+        message: &str,
+        now: Instant,
+        expired: &mut Vec<Suppressed>,
+    ) -> bool {
+        filter.filter_at(
+            &log::Record::builder()
+                .args(format_args!("{}", message))
+                .level(Level::Error)
+                .target("app")
+                .build(),
+            now,
+            expired,
+        )
+    }
+
+    #[test]
+    fn test_suppress_within_window() {
+        let duplicates = DuplicateFilter::new(Duration::from_secs(3600), 16);
+        let mut expired = Vec::new();
+        assert!(!filter(&duplicates, "a", &mut expired));
+        assert!(filter(&duplicates, "a", &mut expired));
+        assert!(filter(&duplicates, "a", &mut expired));
+        assert!(!filter(&duplicates, "b", &mut expired));
+        assert!(expired.is_empty());
+    }
+
+    #[test]
+    fn test_report_after_window() {
+        let duplicates = DuplicateFilter::new(Duration::from_millis(50), 16);
+        let mut expired = Vec::new();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert!(!filter_at(&duplicates, "a", at(0), &mut expired));
+        assert!(filter_at(&duplicates, "a", at(10), &mut expired));
+        // The window starts again with each occurrence
+        assert!(filter_at(&duplicates, "a", at(55), &mut expired));
+        assert!(expired.is_empty());
+
+        assert!(!filter_at(&duplicates, "b", at(105), &mut expired));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].message, "a");
+        assert_eq!(expired[0].count, 2);
+    }
+
+    #[test]
+    fn test_evict() {
+        let duplicates = DuplicateFilter::new(Duration::from_secs(3600), 2);
+        let mut expired = Vec::new();
+        assert!(!filter(&duplicates, "a", &mut expired));
+        assert!(filter(&duplicates, "a", &mut expired));
+        assert!(!filter(&duplicates, "b", &mut expired));
+        assert!(!filter(&duplicates, "c", &mut expired));
+
+        // "a" was evicted
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].message, "a");
+        assert_eq!(expired[0].count, 1);
+        assert!(!filter(&duplicates, "a", &mut expired));
+    }
+}
@@ -0,0 +1,154 @@
+//! The error type returned by [`WinDebugLoggerBuilder::init`].
+//!
+//! [`WinDebugLoggerBuilder::init`]: crate::WinDebugLoggerBuilder::init
+use crate::ParseLevelError;
+use log::SetLoggerError;
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// An error that occurred while initializing the logger.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Another logger (possibly one of this crate) is already installed as
+    /// the global logger.
+    AlreadyInitialized(SetLoggerError),
+    /// An I/O operation, such as opening a log file, failed.
+    Io {
+        /// The file the operation was performed on, if any.
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// A level filter could not be parsed.
+    InvalidLevel(ParseLevelError),
+    /// A level filter directive could not be parsed.
+    InvalidFilterDirective(String),
+    /// A configuration file is malformed.
+    Config {
+        path: PathBuf,
+        /// The one-based line number of the error, if known.
+        line: Option<usize>,
+        /// The one-based column number of the error, if known.
+        column: Option<usize>,
+        message: String,
+    },
+    /// A Win32 API function failed.
+    Win32 {
+        function: &'static str,
+        /// The value returned by `GetLastError`.
+        code: u32,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyInitialized(_) => {
+                f.write_str("a logger is already installed as the global logger")
+            }
+            Self::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            Self::Io { path: None, source } => write!(f, "I/O error: {}", source),
+            Self::InvalidLevel(e) => e.fmt(f),
+            Self::InvalidFilterDirective(directive) => {
+                write!(f, "invalid filter directive {:?}", directive)
+            }
+            Self::Config {
+                path,
+                line,
+                column,
+                message,
+            } => {
+                write!(f, "{}", path.display())?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                if let Some(column) = column {
+                    write!(f, ":{}", column)?;
+                }
+                write!(f, ": {}", message)
+            }
+            Self::Win32 { function, code } => write!(
+                f,
+                "{} failed: {}",
+                function,
+                io::Error::from_raw_os_error(*code as i32)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::AlreadyInitialized(e) => Some(e),
+            Self::Io { source, .. } => Some(source),
+            Self::InvalidLevel(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// Construct [`Error::Io`] for an operation on the file at `path`.
+    pub(crate) fn io(path: &Path, source: io::Error) -> Self {
+        Self::Io {
+            path: Some(path.to_owned()),
+            source,
+        }
+    }
+}
+
+impl From<SetLoggerError> for Error {
+    fn from(e: SetLoggerError) -> Self {
+        Self::AlreadyInitialized(e)
+    }
+}
+
+impl From<ParseLevelError> for Error {
+    fn from(e: ParseLevelError) -> Self {
+        Self::InvalidLevel(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io {
+            path: None,
+            source: e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let source = io::Error::new(io::ErrorKind::PermissionDenied, "access denied");
+        let e = Error::io(Path::new("windebug_logger.toml"), source);
+        assert_eq!(e.to_string(), "windebug_logger.toml: access denied");
+
+        let e = Error::from(io::Error::new(io::ErrorKind::BrokenPipe, "pipe closed"));
+        assert_eq!(e.to_string(), "I/O error: pipe closed");
+
+        let e = Error::InvalidFilterDirective("app=loud".to_owned());
+        assert_eq!(e.to_string(), "invalid filter directive \"app=loud\"");
+
+        let e = Error::Config {
+            path: "windebug_logger.toml".into(),
+            line: Some(3),
+            column: Some(9),
+            message: "unknown variant `loud`".to_owned(),
+        };
+        assert_eq!(
+            e.to_string(),
+            "windebug_logger.toml:3:9: unknown variant `loud`"
+        );
+    }
+}
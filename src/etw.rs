@@ -0,0 +1,361 @@
+//! A sink writing records as TraceLogging events with typed fields. See
+//! [`WinEtwStructuredSink`].
+use log::Level;
+use std::{io, mem::size_of, sync::OnceLock};
+use winapi::{
+    shared::{
+        evntprov::{self, EVENT_DATA_DESCRIPTOR, EVENT_DESCRIPTOR, EVENT_INFO_CLASS, REGHANDLE},
+        guiddef::GUID,
+        minwindef::ULONG,
+        ntdef::PVOID,
+    },
+    um::libloaderapi,
+};
+
+use crate::{codecvt, DebugSink};
+
+/// The name of the events.
+const EVENT_NAME: &str = "LogRecord";
+
+/// The channel of TraceLogging events (`WINEVENT_CHANNEL_TRACELOGGING`).
+const CHANNEL_TRACELOGGING: u8 = 11;
+
+/// TraceLogging field types (`TlgIn*` and `TlgOut*`).
+const IN_ANSISTRING: u8 = 2;
+const IN_UINT32: u8 = 8;
+const IN_CHAIN: u8 = 0x80;
+const OUT_TID: u8 = 6;
+const OUT_UTF8: u8 = 35;
+
+/// Writes records to Event Tracing for Windows (ETW) as TraceLogging events
+/// with the fields `Level`, `Target`, `Message`, `ThreadId`, `File`, and
+/// `Line`, which consumers such as Windows Performance Analyzer can query
+/// individually. Requires the `etw-structured` feature.
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use windebug_logger::{WinDebugLoggerBuilder, WinEtwStructuredSink};
+///
+/// let sink = WinEtwStructuredSink::register(
+///     "MyCompany.MyApp",
+///     "3970f9cf-2c0c-4f11-b1cc-e3a1e9958833",
+/// )?;
+/// WinDebugLoggerBuilder::new().sink(sink).init()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Start a session with the provider's GUID, e.g., with
+/// `wpr -start profile.wprp` or `tracelog -start app -guid #3970f9cf-...`,
+/// to collect the events. The events are named `LogRecord` and have the ETW
+/// level corresponding to the record's (`Trace` and `Debug` map to
+/// verbose), so sessions can filter them by level. Nothing is written while
+/// no session is collecting the events at the record's level.
+///
+/// The fields are UTF-8 strings, except for `ThreadId` and `Line`, which
+/// are 32-bit integers. `File` is empty and `Line` is `0` if unknown.
+/// `Message` is the message of the record, without the timestamp and the
+/// other decorations of the formatted line. The lines replayed by
+/// [`WinDebugLoggerBuilder::debug_on_error`] have no record at hand, so
+/// they are written with the formatted line as `Message` and an empty
+/// `Target`.
+///
+/// [`WinDebugLoggerBuilder::debug_on_error`]: crate::WinDebugLoggerBuilder::debug_on_error
+#[derive(Debug)]
+pub struct WinEtwStructuredSink {
+    handle: REGHANDLE,
+    name: String,
+    /// The provider traits, passed with every event.
+    provider_metadata: Vec<u8>,
+    /// The name and the fields of the events.
+    event_metadata: Vec<u8>,
+}
+
+impl WinEtwStructuredSink {
+    /// Register a provider named `name` (e.g., `MyCompany.MyApp`) with the
+    /// GUID `guid`, given in the registry format with or without braces,
+    /// e.g., `3970f9cf-2c0c-4f11-b1cc-e3a1e9958833`.
+    pub fn register(name: &str, guid: &str) -> io::Result<Self> {
+        let guid = parse_guid(guid)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid GUID"))?;
+        if name.contains('\0') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid provider name",
+            ));
+        }
+
+        let mut handle = 0;
+        let error =
+            unsafe { evntprov::EventRegister(&guid, None, std::ptr::null_mut(), &mut handle) };
+        if error != 0 {
+            return Err(io::Error::from_raw_os_error(error as i32));
+        }
+
+        let mut provider_metadata = with_size(name.as_bytes(), 0);
+        provider_metadata.push(0);
+        set_size(&mut provider_metadata);
+        // Unavailable before Windows 8 and fails before Windows 10, which
+        // still accept the provider traits passed with every event
+        if let Some(event_set_information) = event_set_information() {
+            unsafe {
+                event_set_information(
+                    handle,
+                    evntprov::EventProviderSetTraits,
+                    provider_metadata.as_mut_ptr() as _,
+                    provider_metadata.len() as u32,
+                )
+            };
+        }
+
+        Ok(Self {
+            handle,
+            name: name.to_owned(),
+            provider_metadata,
+            event_metadata: event_metadata(),
+        })
+    }
+
+    fn write_event(&self, fields: &Fields<'_>) -> io::Result<()> {
+        let level = etw_level(fields.level);
+        if unsafe { evntprov::EventProviderEnabled(self.handle, level, 0) } == 0 {
+            return Ok(());
+        }
+
+        let descriptor = EVENT_DESCRIPTOR {
+            Id: 0,
+            Version: 0,
+            Channel: CHANNEL_TRACELOGGING,
+            Level: level,
+            Opcode: 0,
+            Task: 0,
+            Keyword: 0,
+        };
+        let level_name = c_string(fields.level.as_str());
+        let target = c_string(fields.target);
+        let message = c_string(fields.message);
+        let file = c_string(fields.file);
+        let mut data = [
+            data_descriptor(
+                &self.provider_metadata,
+                evntprov::EVENT_DATA_DESCRIPTOR_TYPE_PROVIDER_METADATA,
+            ),
+            data_descriptor(
+                &self.event_metadata,
+                evntprov::EVENT_DATA_DESCRIPTOR_TYPE_EVENT_METADATA,
+            ),
+            data_descriptor(&level_name, 0),
+            data_descriptor(&target, 0),
+            data_descriptor(&message, 0),
+            data_descriptor(&fields.thread_id.to_ne_bytes(), 0),
+            data_descriptor(&file, 0),
+            data_descriptor(&fields.line.to_ne_bytes(), 0),
+        ];
+
+        let error = unsafe {
+            evntprov::EventWrite(
+                self.handle,
+                &descriptor,
+                data.len() as u32,
+                data.as_mut_ptr(),
+            )
+        };
+        if error != 0 {
+            return Err(io::Error::from_raw_os_error(error as i32));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WinEtwStructuredSink {
+    fn drop(&mut self) {
+        unsafe { evntprov::EventUnregister(self.handle) };
+    }
+}
+
+/// The values of the fields of an event.
+struct Fields<'a> {
+    level: Level,
+    target: &'a str,
+    message: &'a str,
+    thread_id: u32,
+    file: &'a str,
+    line: u32,
+}
+
+impl DebugSink for WinEtwStructuredSink {
+    fn write(&self, level: Level, line: &[u16]) -> io::Result<()> {
+        let line = codecvt::WideDisplay(line).to_string();
+        self.write_event(&Fields {
+            level,
+            target: "",
+            message: line.trim_end_matches('\n'),
+            thread_id: current_thread_id(),
+            file: "",
+            line: 0,
+        })
+    }
+
+    fn write_record(&self, record: &log::Record<'_>, _line: &[u16]) -> io::Result<()> {
+        let message = record.args().to_string();
+        self.write_event(&Fields {
+            level: record.level(),
+            target: record.target(),
+            message: &message,
+            thread_id: current_thread_id(),
+            file: record.file().unwrap_or(""),
+            line: record.line().unwrap_or(0),
+        })
+    }
+
+    fn name(&self) -> String {
+        format!("etw({})", self.name)
+    }
+}
+
+type EventSetInformationFn =
+    unsafe extern "system" fn(REGHANDLE, EVENT_INFO_CLASS, PVOID, ULONG) -> ULONG;
+
+/// Look up `EventSetInformation` (Windows 8 and later), as
+/// `TraceLoggingProvider.h` does, so that the sink still works on older
+/// versions.
+fn event_set_information() -> Option<EventSetInformationFn> {
+    static PROC: OnceLock<Option<EventSetInformationFn>> = OnceLock::new();
+
+    *PROC.get_or_init(|| unsafe {
+        let advapi32 = libloaderapi::GetModuleHandleW(wchar::wch_c!("advapi32.dll").as_ptr());
+        if advapi32.is_null() {
+            return None;
+        }
+        let proc = libloaderapi::GetProcAddress(advapi32, b"EventSetInformation\0".as_ptr() as _);
+        if proc.is_null() {
+            return None;
+        }
+        let event_set_information: EventSetInformationFn = std::mem::transmute(proc);
+        Some(event_set_information)
+    })
+}
+
+fn current_thread_id() -> u32 {
+    unsafe { winapi::um::processthreadsapi::GetCurrentThreadId() }
+}
+
+/// Map a level to the corresponding ETW level.
+fn etw_level(level: Level) -> u8 {
+    match level {
+        Level::Error => 2,                // TRACE_LEVEL_ERROR
+        Level::Warn => 3,                 // TRACE_LEVEL_WARNING
+        Level::Info => 4,                 // TRACE_LEVEL_INFORMATION
+        Level::Debug | Level::Trace => 5, // TRACE_LEVEL_VERBOSE
+    }
+}
+
+/// Convert `s` to a null-terminated UTF-8 string, replacing null characters,
+/// which would end the field early.
+fn c_string(s: &str) -> Vec<u8> {
+    let mut out = s.replace('\0', "\u{fffd}").into_bytes();
+    out.push(0);
+    out
+}
+
+fn data_descriptor(data: &[u8], ty: u8) -> EVENT_DATA_DESCRIPTOR {
+    let mut descriptor = EVENT_DATA_DESCRIPTOR {
+        Ptr: data.as_ptr() as u64,
+        Size: data.len() as u32,
+        u: unsafe { std::mem::zeroed() },
+    };
+    unsafe { descriptor.u.s_mut().Type = ty };
+    descriptor
+}
+
+/// Start a metadata blob with a placeholder for its size, followed by
+/// `bytes`, with room for `extra` more bytes.
+fn with_size(bytes: &[u8], extra: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(size_of::<u16>() + bytes.len() + extra);
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(bytes);
+    out
+}
+
+/// Fill in the size of a metadata blob started by `with_size`.
+fn set_size(blob: &mut [u8]) {
+    let size = blob.len() as u16;
+    blob[..2].copy_from_slice(&size.to_le_bytes());
+}
+
+/// Build the event metadata: the size, the tags, the event name, and the
+/// name and the types of each field.
+fn event_metadata() -> Vec<u8> {
+    const FIELDS: [(&str, &[u8]); 6] = [
+        ("Level", &[IN_ANSISTRING | IN_CHAIN, OUT_UTF8]),
+        ("Target", &[IN_ANSISTRING | IN_CHAIN, OUT_UTF8]),
+        ("Message", &[IN_ANSISTRING | IN_CHAIN, OUT_UTF8]),
+        ("ThreadId", &[IN_UINT32 | IN_CHAIN, OUT_TID]),
+        ("File", &[IN_ANSISTRING | IN_CHAIN, OUT_UTF8]),
+        ("Line", &[IN_UINT32]),
+    ];
+
+    // No tags
+    let mut out = with_size(&[0], 64);
+    out.extend_from_slice(EVENT_NAME.as_bytes());
+    out.push(0);
+    for (name, types) in FIELDS.iter() {
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        out.extend_from_slice(types);
+    }
+    set_size(&mut out);
+    out
+}
+
+/// Parse a GUID in the registry format, e.g.,
+/// `{3970f9cf-2c0c-4f11-b1cc-e3a1e9958833}`, with or without braces.
+fn parse_guid(s: &str) -> Option<GUID> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix('{')
+        .and_then(|s| s.strip_suffix('}'))
+        .unwrap_or(s);
+    let parts: Vec<&str> = s.split('-').collect();
+    let lens: Vec<usize> = parts.iter().map(|part| part.len()).collect();
+    if lens != [8, 4, 4, 4, 12] || !s.bytes().all(|b| b == b'-' || b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let tail = u64::from_str_radix(&format!("{}{}", parts[3], parts[4]), 16).ok()?;
+    Some(GUID {
+        Data1: u32::from_str_radix(parts[0], 16).ok()?,
+        Data2: u16::from_str_radix(parts[1], 16).ok()?,
+        Data3: u16::from_str_radix(parts[2], 16).ok()?,
+        Data4: tail.to_be_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_guid() {
+        let guid = parse_guid("{3970f9cf-2c0c-4f11-b1cc-e3a1e9958833}").unwrap();
+        assert_eq!(guid.Data1, 0x3970f9cf);
+        assert_eq!(guid.Data2, 0x2c0c);
+        assert_eq!(guid.Data3, 0x4f11);
+        assert_eq!(guid.Data4, [0xb1, 0xcc, 0xe3, 0xa1, 0xe9, 0x95, 0x88, 0x33]);
+        assert!(parse_guid("3970F9CF-2C0C-4F11-B1CC-E3A1E9958833").is_some());
+
+        assert!(parse_guid("3970f9cf-2c0c-4f11-b1cc").is_none());
+        assert!(parse_guid("3970f9cf-2c0c-4f11-b1cc-e3a1e995883g").is_none());
+        assert!(parse_guid("+970f9cf-2c0c-4f11-b1cc-e3a1e9958833").is_none());
+    }
+
+    #[test]
+    fn test_event_metadata() {
+        let metadata = event_metadata();
+        assert_eq!(
+            u16::from_le_bytes([metadata[0], metadata[1]]) as usize,
+            metadata.len()
+        );
+        assert!(metadata[2..].starts_with(b"\0LogRecord\0Level\0\x82\x23Target\0"));
+        assert!(metadata.ends_with(b"Line\0\x08"));
+    }
+}
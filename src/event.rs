@@ -0,0 +1,104 @@
+use std::{
+    ptr::{null_mut, NonNull},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        RwLock,
+    },
+};
+use winapi::{
+    shared::minwindef::TRUE,
+    um::{handleapi, synchapi, sysinfoapi, winbase, winnt::HANDLE},
+};
+
+/// How long (in milliseconds) the cached state of the event object remains
+/// valid.
+const CHECK_INTERVAL: u32 = 250;
+
+/// A named manual-reset event object whose signaled state enables or disables
+/// the logger. See [`WinDebugLoggerBuilder::enable_event`].
+///
+/// [`WinDebugLoggerBuilder::enable_event`]: crate::WinDebugLoggerBuilder::enable_event
+#[derive(Debug)]
+pub(crate) struct EnableEvent {
+    /// The event handle. `None` after `close`. `refresh` holds the read lock
+    /// while waiting on the handle so that `close` cannot close it in the
+    /// meantime.
+    handle: RwLock<Option<Handle>>,
+    signaled: AtomicBool,
+    /// The tick count (`GetTickCount`) at which `signaled` was last updated.
+    last_check: AtomicU32,
+}
+
+impl EnableEvent {
+    /// Create or open a named event object. The event is created in the
+    /// non-signaled state.
+    pub fn open(name: &str) -> Option<Self> {
+        let name = crate::codecvt::str_to_c_wstr(name)?;
+        let handle = unsafe {
+            synchapi::CreateEventW(
+                null_mut(), // default security attributes
+                TRUE,       // manual-reset
+                0,          // initially non-signaled
+                name.as_ptr(),
+            )
+        };
+        let handle = NonNull::new(handle)?;
+
+        let this = Self {
+            handle: RwLock::new(Some(Handle(handle))),
+            signaled: AtomicBool::new(false),
+            last_check: AtomicU32::new(0),
+        };
+        this.refresh(unsafe { sysinfoapi::GetTickCount() });
+        Some(this)
+    }
+
+    /// Get the (possibly slightly stale) signaled state of the event object.
+    pub fn is_signaled(&self) -> bool {
+        let now = unsafe { sysinfoapi::GetTickCount() };
+        if now.wrapping_sub(self.last_check.load(Ordering::Relaxed)) >= CHECK_INTERVAL {
+            self.refresh(now);
+        }
+        self.signaled.load(Ordering::Relaxed)
+    }
+
+    fn refresh(&self, now: u32) {
+        let handle = self.handle.read().unwrap_or_else(|e| e.into_inner());
+        let signaled = match &*handle {
+            Some(handle) => unsafe {
+                synchapi::WaitForSingleObject(handle.as_raw(), 0) == winbase::WAIT_OBJECT_0
+            },
+            None => false,
+        };
+        self.signaled.store(signaled, Ordering::Relaxed);
+        self.last_check.store(now, Ordering::Relaxed);
+    }
+
+    /// Close the event handle. The event is treated as non-signaled from now
+    /// on.
+    pub fn close(&self) {
+        // Waits for in-flight `refresh` calls to finish with the handle
+        let handle = self
+            .handle
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        self.signaled.store(false, Ordering::Relaxed);
+        if let Some(handle) = handle {
+            unsafe { handleapi::CloseHandle(handle.as_raw()) };
+        }
+    }
+}
+
+/// An event handle, which can be used from any thread.
+#[derive(Debug)]
+struct Handle(NonNull<winapi::ctypes::c_void>);
+
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Handle {
+    fn as_raw(&self) -> HANDLE {
+        self.0.as_ptr()
+    }
+}
@@ -0,0 +1,481 @@
+//! Per-target level filters. See [`WinDebugLoggerBuilder::target_level`].
+//!
+//! [`WinDebugLoggerBuilder::target_level`]: crate::WinDebugLoggerBuilder::target_level
+use log::LevelFilter;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        RwLock,
+    },
+};
+
+use crate::{level::parse_level, Error};
+
+/// The maximum number of targets remembered by [`LevelCache`].
+const CACHE_CAPACITY: usize = 256;
+
+/// The level filters of targets and their descendants, forming a hierarchy
+/// in which the entry of the longest matching target wins.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct TargetFilter {
+    /// Sorted by target.
+    entries: Vec<(String, LevelFilter)>,
+}
+
+impl TargetFilter {
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Set the level filter of `target`, replacing the existing one.
+    pub fn insert(&mut self, target: String, level: LevelFilter) {
+        match self.find(&target) {
+            Ok(i) => self.entries[i].1 = level,
+            Err(i) => self.entries.insert(i, (target, level)),
+        }
+    }
+
+    /// Get the level filter of the most specific entry matching `target`,
+    /// i.e., `target` itself or its closest ancestor (e.g., `app::net` and
+    /// then `app` for `app::net::tls`).
+    pub fn level_for(&self, target: &str) -> Option<LevelFilter> {
+        ancestors(target).find_map(|prefix| Some(self.entries[self.find(prefix).ok()?].1))
+    }
+
+    fn find(&self, target: &str) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|(t, _)| t.as_str().cmp(target))
+    }
+
+    /// Get the most verbose level filter of the entries.
+    pub fn max_level(&self) -> LevelFilter {
+        self.entries
+            .iter()
+            .map(|&(_, level)| level)
+            .max()
+            .unwrap_or(LevelFilter::Off)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, LevelFilter)> {
+        self.entries.iter().map(|(t, level)| (&**t, *level))
+    }
+}
+
+/// A [`TargetFilter`] that can be changed at runtime, with its lookups
+/// cached.
+#[derive(Debug)]
+pub(crate) struct TargetLevels {
+    filter: RwLock<TargetFilter>,
+    /// `false` if `filter` is empty, in which case lookups are skipped.
+    active: AtomicBool,
+    cache: LevelCache,
+}
+
+impl TargetLevels {
+    pub const fn empty() -> Self {
+        Self {
+            filter: RwLock::new(TargetFilter::new()),
+            active: AtomicBool::new(false),
+            cache: LevelCache::new(),
+        }
+    }
+
+    pub fn new(filter: TargetFilter) -> Self {
+        Self {
+            active: AtomicBool::new(!filter.is_empty()),
+            filter: RwLock::new(filter),
+            cache: LevelCache::new(),
+        }
+    }
+
+    /// Get the level filter of the most specific entry matching `target`.
+    #[inline]
+    pub fn level_for(&self, target: &str) -> Option<LevelFilter> {
+        if !self.active.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.cache
+            .get_or_insert_with(target, || self.read().level_for(target))
+    }
+
+    /// Set the level filter of `target`, replacing the existing one.
+    pub fn insert(&self, target: String, level: LevelFilter) {
+        let mut filter = self.filter.write().unwrap_or_else(|e| e.into_inner());
+        filter.insert(target, level);
+        self.active.store(true, Ordering::Relaxed);
+        self.cache.invalidate();
+    }
+
+    /// Replace all entries.
+    pub fn replace(&self, new_filter: TargetFilter) {
+        let mut filter = self.filter.write().unwrap_or_else(|e| e.into_inner());
+        self.active.store(!new_filter.is_empty(), Ordering::Relaxed);
+        *filter = new_filter;
+        self.cache.invalidate();
+    }
+
+    pub fn max_level(&self) -> LevelFilter {
+        self.read().max_level()
+    }
+
+    pub fn read(&self) -> std::sync::RwLockReadGuard<'_, TargetFilter> {
+        self.filter.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Remembers the results of [`TargetFilter::level_for`] by target.
+#[derive(Debug)]
+struct LevelCache {
+    /// Created on first use. Cleared when full.
+    map: RwLock<Option<HashMap<Box<str>, Option<LevelFilter>>>>,
+    /// Incremented by each invalidation.
+    generation: AtomicUsize,
+}
+
+impl LevelCache {
+    const fn new() -> Self {
+        Self {
+            map: RwLock::new(None),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the cached result for `target`, or compute it by `f` and cache it.
+    fn get_or_insert_with(
+        &self,
+        target: &str,
+        f: impl FnOnce() -> Option<LevelFilter>,
+    ) -> Option<LevelFilter> {
+        let generation = self.generation.load(Ordering::Acquire);
+        {
+            let map = self.map.read().unwrap_or_else(|e| e.into_inner());
+            if let Some(&level) = map.as_ref().and_then(|map| map.get(target)) {
+                return level;
+            }
+        }
+
+        let level = f();
+        let mut map = self.map.write().unwrap_or_else(|e| e.into_inner());
+        // Do not cache a result that may have been computed from the filter
+        // before an invalidation
+        if self.generation.load(Ordering::Acquire) == generation {
+            let map = map.get_or_insert_with(HashMap::new);
+            if map.len() >= CACHE_CAPACITY {
+                map.clear();
+            }
+            map.insert(target.into(), level);
+        }
+        level
+    }
+
+    /// Forget all results. Must be called after changing the filter.
+    fn invalidate(&self) {
+        let mut map = self.map.write().unwrap_or_else(|e| e.into_inner());
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        *map = None;
+    }
+}
+
+/// An allow-list and a deny-list of targets.
+type Lists = (Option<HashSet<String>>, Option<HashSet<String>>);
+
+/// The allow-list and the deny-list of targets, which can be replaced at
+/// runtime. See [`is_allowed`].
+#[derive(Debug)]
+pub(crate) struct TargetLists {
+    /// The allow-list and the deny-list.
+    lists: RwLock<Lists>,
+    /// `false` if neither list is present, in which case checks are skipped.
+    active: AtomicBool,
+}
+
+impl TargetLists {
+    pub const fn empty() -> Self {
+        Self {
+            lists: RwLock::new((None, None)),
+            active: AtomicBool::new(false),
+        }
+    }
+
+    pub fn new(allowed: Option<HashSet<String>>, denied: Option<HashSet<String>>) -> Self {
+        Self {
+            active: AtomicBool::new(allowed.is_some() || denied.is_some()),
+            lists: RwLock::new((allowed, denied)),
+        }
+    }
+
+    /// Check `target` against the lists.
+    #[inline]
+    pub fn is_allowed(&self, target: &str) -> bool {
+        !self.active.load(Ordering::Relaxed) || {
+            let lists = self.read();
+            is_allowed(lists.0.as_ref(), lists.1.as_ref(), target)
+        }
+    }
+
+    /// Replace the lists.
+    pub fn set(&self, allowed: Option<HashSet<String>>, denied: Option<HashSet<String>>) {
+        let mut lists = self.lists.write().unwrap_or_else(|e| e.into_inner());
+        self.active
+            .store(allowed.is_some() || denied.is_some(), Ordering::Relaxed);
+        *lists = (allowed, denied);
+    }
+
+    /// Get the allow-list and the deny-list.
+    pub fn read(&self) -> std::sync::RwLockReadGuard<'_, Lists> {
+        self.lists.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Check `target` against the allow-list and the deny-list. The most
+/// specific entry matching `target` or one of its ancestors decides, and the
+/// allow-list wins if both lists have it. See
+/// [`WinDebugLoggerBuilder::deny_targets`].
+///
+/// [`WinDebugLoggerBuilder::deny_targets`]: crate::WinDebugLoggerBuilder::deny_targets
+pub fn is_allowed(
+    allowed: Option<&HashSet<String>>,
+    denied: Option<&HashSet<String>>,
+    target: &str,
+) -> bool {
+    for prefix in ancestors(target) {
+        if allowed.is_some_and(|allowed| allowed.contains(prefix)) {
+            return true;
+        }
+        if denied.is_some_and(|denied| denied.contains(prefix)) {
+            return false;
+        }
+    }
+    allowed.is_none()
+}
+
+/// Iterate over `target` and its ancestors, e.g., `app::net::tls`,
+/// `app::net`, and `app`.
+pub(crate) fn ancestors(target: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(target), |prefix| {
+        prefix.rfind("::").map(|i| &prefix[..i])
+    })
+}
+
+/// Parse a comma-separated list of directives, each of which is one of the
+/// following:
+///
+///  - `level`: Sets the global level filter.
+///  - `target=level`: Sets the level filter of `target`.
+///  - `target`: Enables all records of `target`.
+///
+/// Returns the global level filter, if specified, and the target filters.
+pub fn parse_directives(spec: &str) -> Result<(Option<LevelFilter>, TargetFilter), Error> {
+    let mut level = None;
+    let mut targets = TargetFilter::new();
+    for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        match directive.split_once('=') {
+            Some((target, target_level)) => {
+                let target = target.trim();
+                let target_level = parse_level(target_level)
+                    .ok()
+                    .filter(|_| !target.is_empty())
+                    .ok_or_else(|| Error::InvalidFilterDirective(directive.to_owned()))?;
+                targets.insert(target.to_owned(), target_level);
+            }
+            None => match parse_level(directive) {
+                Ok(l) => level = Some(l),
+                Err(_) => targets.insert(directive.to_owned(), LevelFilter::Trace),
+            },
+        }
+    }
+    Ok((level, targets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_for() {
+        let mut filter = TargetFilter::new();
+        filter.insert("app".to_owned(), LevelFilter::Debug);
+        filter.insert("hyper".to_owned(), LevelFilter::Warn);
+
+        assert_eq!(filter.level_for("app"), Some(LevelFilter::Debug));
+        assert_eq!(filter.level_for("app::net"), Some(LevelFilter::Debug));
+        assert_eq!(filter.level_for("application"), None);
+        assert_eq!(filter.level_for("hyper::client"), Some(LevelFilter::Warn));
+        assert_eq!(filter.level_for("other"), None);
+        assert_eq!(filter.max_level(), LevelFilter::Debug);
+
+        filter.insert("app".to_owned(), LevelFilter::Error);
+        assert_eq!(filter.level_for("app::net"), Some(LevelFilter::Error));
+        assert_eq!(filter.max_level(), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn test_level_for_hierarchy() {
+        let mut filter = TargetFilter::new();
+        // Inserted out of order; the most specific entry wins regardless
+        filter.insert("app::net::tls".to_owned(), LevelFilter::Trace);
+        filter.insert("app".to_owned(), LevelFilter::Debug);
+        filter.insert("app::net".to_owned(), LevelFilter::Warn);
+
+        // Exact matches take precedence over ancestors
+        assert_eq!(filter.level_for("app"), Some(LevelFilter::Debug));
+        assert_eq!(filter.level_for("app::net"), Some(LevelFilter::Warn));
+        assert_eq!(filter.level_for("app::net::tls"), Some(LevelFilter::Trace));
+
+        // Descendants inherit from the closest ancestor
+        assert_eq!(filter.level_for("app::db"), Some(LevelFilter::Debug));
+        assert_eq!(filter.level_for("app::net::http"), Some(LevelFilter::Warn));
+        assert_eq!(
+            filter.level_for("app::net::tls::handshake"),
+            Some(LevelFilter::Trace)
+        );
+
+        // Only whole path segments match
+        assert_eq!(filter.level_for("app::network"), Some(LevelFilter::Debug));
+        assert_eq!(filter.level_for("app::net::tlsx"), Some(LevelFilter::Warn));
+        assert_eq!(filter.level_for("app:net"), None);
+        assert_eq!(filter.level_for("apps"), None);
+        assert_eq!(filter.level_for("my::app"), None);
+        assert_eq!(filter.level_for(""), None);
+
+        assert_eq!(
+            filter.iter().map(|(t, _)| t).collect::<Vec<_>>(),
+            ["app", "app::net", "app::net::tls"]
+        );
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let allowed: HashSet<String> = ["myapp::db", "hyper"].iter().map(|&s| s.into()).collect();
+        let is_allowed = |target| is_allowed(Some(&allowed), None, target);
+        assert!(is_allowed("myapp::db"));
+        assert!(is_allowed("myapp::db::pool"));
+        assert!(is_allowed("hyper::client"));
+        assert!(!is_allowed("myapp"));
+        assert!(!is_allowed("myapp::dbx"));
+        assert!(!is_allowed("other"));
+        assert!(!is_allowed(""));
+    }
+
+    #[test]
+    fn test_is_allowed_deny() {
+        let set =
+            |targets: &[&str]| -> HashSet<String> { targets.iter().map(|&s| s.into()).collect() };
+
+        let denied = set(&["hyper", "myapp::noisy"]);
+        assert!(is_allowed(None, None, "hyper"));
+        assert!(!is_allowed(None, Some(&denied), "hyper"));
+        assert!(!is_allowed(None, Some(&denied), "hyper::client"));
+        assert!(!is_allowed(None, Some(&denied), "myapp::noisy::inner"));
+        assert!(is_allowed(None, Some(&denied), "myapp"));
+        assert!(is_allowed(None, Some(&denied), "hyperx"));
+
+        // The most specific entry wins, and the allow-list wins on a tie
+        let allowed = set(&["myapp", "hyper::client", "myapp::noisy"]);
+        let denied = set(&["hyper", "myapp::noisy"]);
+        let check = |target| is_allowed(Some(&allowed), Some(&denied), target);
+        assert!(check("myapp::ui"));
+        assert!(check("myapp::noisy"));
+        assert!(check("hyper::client::pool"));
+        assert!(!check("hyper::server"));
+        assert!(!check("other"));
+
+        let allowed = set(&["myapp"]);
+        let denied = set(&["myapp::noisy"]);
+        assert!(!is_allowed(
+            Some(&allowed),
+            Some(&denied),
+            "myapp::noisy::inner"
+        ));
+    }
+
+    #[test]
+    fn test_target_levels() {
+        let levels = TargetLevels::empty();
+        assert_eq!(levels.level_for("app::net"), None);
+
+        levels.insert("app".to_owned(), LevelFilter::Debug);
+        assert_eq!(levels.level_for("app::net"), Some(LevelFilter::Debug));
+        // Cached results are invalidated by changes
+        levels.insert("app::net".to_owned(), LevelFilter::Warn);
+        assert_eq!(levels.level_for("app::net"), Some(LevelFilter::Warn));
+        assert_eq!(levels.level_for("app"), Some(LevelFilter::Debug));
+    }
+
+    #[test]
+    fn test_level_cache() {
+        let cache = LevelCache::new();
+        let calls = std::cell::Cell::new(0);
+        let lookup = |target: &str| {
+            cache.get_or_insert_with(target, || {
+                calls.set(calls.get() + 1);
+                Some(LevelFilter::Info)
+            })
+        };
+
+        lookup("a");
+        lookup("a");
+        assert_eq!(calls.get(), 1);
+
+        // Bounded
+        for i in 0..CACHE_CAPACITY * 2 {
+            lookup(&i.to_string());
+        }
+        let len = cache.map.read().unwrap().as_ref().unwrap().len();
+        assert!(len <= CACHE_CAPACITY, "{}", len);
+
+        cache.invalidate();
+        calls.set(0);
+        lookup("a");
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_level_cache_concurrent() {
+        let levels = TargetLevels::empty();
+        levels.insert("app".to_owned(), LevelFilter::Info);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for i in 0..1000 {
+                        levels.level_for(&format!("app::m{}", i % 300));
+                    }
+                });
+            }
+            s.spawn(|| levels.insert("app".to_owned(), LevelFilter::Trace));
+        });
+        // No stale result survives the change
+        for i in 0..300 {
+            let target = format!("app::m{}", i);
+            assert_eq!(levels.level_for(&target), Some(LevelFilter::Trace));
+        }
+    }
+
+    #[test]
+    fn test_parse_directives() {
+        let (level, targets) = parse_directives("warning, app::net=Debug,hyper=0,mio,").unwrap();
+        assert_eq!(level, Some(LevelFilter::Warn));
+        assert_eq!(
+            targets.iter().collect::<Vec<_>>(),
+            [
+                ("app::net", LevelFilter::Debug),
+                ("hyper", LevelFilter::Off),
+                ("mio", LevelFilter::Trace),
+            ]
+        );
+
+        assert_eq!(parse_directives("").unwrap(), (None, TargetFilter::new()));
+        assert!(matches!(
+            parse_directives("info,app=loud"),
+            Err(Error::InvalidFilterDirective(d)) if d == "app=loud"
+        ));
+        assert!(parse_directives("=info").is_err());
+    }
+}
@@ -0,0 +1,1019 @@
+//! Rendering of records into lines.
+use log::Level;
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    fmt::Write,
+    panic::{catch_unwind, AssertUnwindSafe},
+    time::{SystemTime, UNIX_EPOCH},
+};
+#[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+use winapi::{
+    shared::minwindef::FILETIME,
+    um::{minwinbase::SYSTEMTIME, sysinfoapi, timezoneapi},
+};
+
+#[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+use crate::datetimeapi;
+#[cfg(feature = "time")]
+use crate::timestamp;
+use crate::{
+    global, CustomFormatter, CustomTimestamp, LevelStyle, OutputFormat, WinDebugLoggerBuilder,
+};
+
+/// Render `record` as a line in the format configured by `options`, e.g., to
+/// check a configuration in a test or to format records for another
+/// destination in the same way.
+///
+/// The result is what the logger would write for `record`, including the
+/// trailing newline (unless a custom formatter omits it), except that:
+///
+///  - [`elide_repeated_targets`] has no effect.
+///  - The session ID is that of the logger installed by this crate, and is
+///    omitted if none is installed.
+///  - [`split_level_tag`] has no effect; the line is returned in one piece.
+///
+/// Returns `None` if a custom formatter returns an error.
+///
+/// ```
+/// use windebug_logger::{format::render_to_string, LevelStyle, WinDebugLoggerBuilder};
+///
+/// let options = WinDebugLoggerBuilder::new()
+///     .level_style(LevelStyle::Bracketed)
+///     .timestamp_fn(|out| out.push_str("now"));
+/// let record = log::Record::builder()
+///     .args(format_args!("hello"))
+///     .level(log::Level::Warn)
+///     .target("app")
+///     .build();
+///
+/// assert_eq!(
+///     render_to_string(&record, &options).as_deref(),
+///     Some("now <W> [app] hello\n")
+/// );
+/// ```
+///
+/// [`elide_repeated_targets`]: WinDebugLoggerBuilder::elide_repeated_targets
+/// [`split_level_tag`]: WinDebugLoggerBuilder::split_level_tag
+pub fn render_to_string(record: &log::Record, options: &WinDebugLoggerBuilder) -> Option<String> {
+    let cx = Context {
+        session_id: global()
+            .filter(|_| options.session_id)
+            .map(|logger| logger.session_id()),
+        name: &options.name,
+        elide_target: false,
+        level_tag: Cell::new(None),
+        message_start: Cell::new(0),
+    };
+    let mut out = String::new();
+    render(&mut out, record, options, &cx, true)?;
+    if let Some(max_len) = options.max_message_len {
+        let start = cx.message_start.get();
+        let mut wide: Vec<u16> = out[start..].encode_utf16().collect();
+        truncate(&mut wide, 0, max_len);
+        out.truncate(start);
+        out.push_str(&String::from_utf16_lossy(&wide));
+    }
+    Some(out)
+}
+
+/// The state of the logger that affects rendering.
+pub(crate) struct Context<'a> {
+    /// The session ID to show, if enabled.
+    pub session_id: Option<&'a str>,
+    /// The name of the logger, shown if not empty.
+    pub name: &'a str,
+    /// Replace the target with a placeholder if it is identical to that of
+    /// the previous line rendered with this option by the current thread.
+    pub elide_target: bool,
+    /// Set by `render` to the byte range of the level tag in the output, for
+    /// `split_level_tag`.
+    pub level_tag: Cell<Option<(usize, usize)>>,
+    /// Set by `render` to the byte offset in the output at which the
+    /// message starts, for `with_max_message_len`. Left as is with a custom
+    /// formatter or [`OutputFormat::Logfmt`].
+    pub message_start: Cell<usize>,
+}
+
+/// The line written in place of a record whose custom formatter panicked.
+const FORMATTER_PANICKED: &str = "<windebug_logger: formatter panicked>\n";
+
+/// Render `record` into `out`. If `with_message` is `false`, stop before the
+/// message so that the caller can append it (followed by a newline) in
+/// another form. `with_message` has no effect with a custom formatter or
+/// [`OutputFormat::Logfmt`].
+pub(crate) fn render(
+    out: &mut String,
+    record: &log::Record,
+    config: &WinDebugLoggerBuilder,
+    cx: &Context<'_>,
+    with_message: bool,
+) -> Option<()> {
+    if let Some(CustomFormatter(formatter)) = &config.custom_formatter {
+        let start = out.len();
+        match catch_unwind(AssertUnwindSafe(|| formatter(record, out))) {
+            Ok(result) => result.ok()?,
+            Err(_) => {
+                out.truncate(start);
+                out.push_str(FORMATTER_PANICKED);
+            }
+        }
+        return Some(());
+    }
+
+    let target = if !record.target().is_empty() {
+        record.target()
+    } else {
+        record.module_path().unwrap_or(&config.default_target)
+    };
+    if config.output_format == OutputFormat::Logfmt {
+        return render_logfmt(out, record, config, cx, target);
+    }
+    let target = if cx.elide_target && is_repeated_target(target) {
+        REPEATED_TARGET
+    } else {
+        target
+    };
+
+    let timestamp = timestamp(config, record)?;
+    if !timestamp.is_empty() {
+        out.push_str(&timestamp);
+        out.push(' ');
+    }
+    if config.frame_number {
+        let _ = write!(out, "[f:{}] ", crate::frame::current());
+    }
+    if !cx.name.is_empty() {
+        let _ = write!(out, "[logger:{}] ", cx.name);
+    }
+
+    if let Some(session_id) = cx.session_id {
+        let _ = write!(out, "{} ", session_id);
+    }
+    if let Some(module_name) = module_name(config) {
+        let _ = write!(out, "({}) ", module_name);
+    }
+    let symbol = &config.level_symbols[record.level() as usize - 1];
+    if !symbol.is_empty() {
+        let _ = write!(out, "{} ", symbol);
+    }
+    let level_start = out.len();
+    let _ = match config.level_style {
+        LevelStyle::Label => write!(out, "{:<5}", record.level()),
+        LevelStyle::Bracketed => write!(out, "<{}>", level_letter(record.level())),
+    };
+    cx.level_tag.set(Some((level_start, out.len())));
+    let _ = write!(out, " [{}] ", target);
+    if config.span_indent > 0 {
+        let indent = crate::time_scope::depth() * config.span_indent;
+        let _ = write!(out, "{:1$}", "", indent);
+    }
+    cx.message_start.set(out.len());
+    if with_message {
+        let message_start = out.len();
+        push_message(out, record);
+        if out.len() == message_start {
+            // The key-values are the payload
+            push_key_values_as_message(out, record);
+        } else {
+            push_key_values(out, record, false);
+        }
+        render_location(out, record, config);
+        out.push('\n');
+    }
+
+    Some(())
+}
+
+/// Get the module name to show, if enabled by `with_module_name`.
+fn module_name(config: &WinDebugLoggerBuilder) -> Option<&'static str> {
+    if config.module_name {
+        crate::sysinfo::own_module_name()
+    } else {
+        None
+    }
+}
+
+/// Append the message of `record` to `out`. `benches/literal_message.rs`
+/// has a copy of this.
+#[inline]
+pub(crate) fn push_message(out: &mut String, record: &log::Record) {
+    // A literal message without arguments (e.g., `info!("starting up")`)
+    // needs no formatting
+    match record.args().as_str() {
+        Some(message) => out.push_str(message),
+        None => {
+            let _ = write!(out, "{}", record.args());
+        }
+    }
+}
+
+/// Append the key-values of `record` to `out` as ` key=value` pairs, quoting
+/// the values as logfmt values if `logfmt` is set. Requires the `kv`
+/// feature.
+#[cfg(feature = "kv")]
+fn push_key_values(out: &mut String, record: &log::Record, logfmt: bool) {
+    use log::kv::{Error, Key, Value, VisitSource};
+
+    struct Visitor<'a> {
+        out: &'a mut String,
+        logfmt: bool,
+    }
+
+    impl<'kvs> VisitSource<'kvs> for Visitor<'_> {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+            let value = kv_value_to_string(&value);
+            let _ = write!(self.out, " {}=", key);
+            if self.logfmt {
+                write_logfmt_value(self.out, &value);
+            } else {
+                self.out.push_str(&value);
+            }
+            Ok(())
+        }
+    }
+
+    let _ = record.key_values().visit(&mut Visitor { out, logfmt });
+}
+
+#[cfg(not(feature = "kv"))]
+#[inline]
+fn push_key_values(_out: &mut String, _record: &log::Record, _logfmt: bool) {}
+
+/// Append the key-values of `record` to `out` in braces, e.g.,
+/// `{k1=v1 k2=v2}`, in place of an empty message. Appends nothing if there
+/// are no key-values.
+fn push_key_values_as_message(out: &mut String, record: &log::Record) {
+    let start = out.len();
+    push_key_values(out, record, false);
+    if out.len() > start {
+        // Replace the leading space
+        out.replace_range(start..start + 1, "{");
+        out.push('}');
+    }
+}
+
+/// Render a key-value's value. With the `kv_serde` feature, structured
+/// values (structs, sequences, maps, etc.) are rendered as compact JSON,
+/// e.g., `{"width":800,"height":600}`. The other values, and those that
+/// fail to serialize, are rendered by their `Display` implementations.
+#[cfg(feature = "kv")]
+fn kv_value_to_string(value: &log::kv::Value) -> String {
+    #[cfg(feature = "kv_serde")]
+    if !is_primitive(value) {
+        if let Ok(json) = serde_json::to_value(value) {
+            if json.is_object() || json.is_array() {
+                return json.to_string();
+            }
+        }
+    }
+    value.to_string()
+}
+
+/// Check if `value` is a number, a Boolean, a character, or a string, which
+/// never serializes as a JSON object or array.
+#[cfg(feature = "kv_serde")]
+fn is_primitive(value: &log::kv::Value) -> bool {
+    value.to_borrowed_str().is_some()
+        || value.to_bool().is_some()
+        || value.to_char().is_some()
+        || value.to_i64().is_some()
+        || value.to_u64().is_some()
+        || value.to_i128().is_some()
+        || value.to_u128().is_some()
+        || value.to_f64().is_some()
+}
+
+/// Get the message of `record`, borrowing it if it is a literal without
+/// arguments.
+pub(crate) fn message(record: &log::Record) -> Cow<'static, str> {
+    match record.args().as_str() {
+        Some(message) => Cow::Borrowed(message),
+        None => Cow::Owned(record.args().to_string()),
+    }
+}
+
+/// Render `record` as [logfmt](OutputFormat::Logfmt).
+fn render_logfmt(
+    out: &mut String,
+    record: &log::Record,
+    config: &WinDebugLoggerBuilder,
+    cx: &Context<'_>,
+    target: &str,
+) -> Option<()> {
+    let timestamp = timestamp(config, record)?;
+    if !timestamp.is_empty() {
+        out.push_str("ts=");
+        write_logfmt_value(out, &timestamp);
+        out.push(' ');
+    }
+    if config.frame_number {
+        let _ = write!(out, "frame={} ", crate::frame::current());
+    }
+    if !cx.name.is_empty() {
+        out.push_str("logger=");
+        write_logfmt_value(out, cx.name);
+        out.push(' ');
+    }
+    if let Some(session_id) = cx.session_id {
+        out.push_str("session=");
+        write_logfmt_value(out, session_id);
+        out.push(' ');
+    }
+    if let Some(module_name) = module_name(config) {
+        out.push_str("module=");
+        write_logfmt_value(out, module_name);
+        out.push(' ');
+    }
+    let _ = write!(out, "level={} target=", level_name(record.level()));
+    write_logfmt_value(out, target);
+    out.push_str(" msg=");
+    write_logfmt_value(out, &message(record));
+    push_key_values(out, record, true);
+    if let (Some(file), true) = (record.file(), config.source_location) {
+        out.push_str(" file=");
+        write_logfmt_value(out, file);
+        if let Some(line) = record.line() {
+            let _ = write!(out, " line={}", line);
+        }
+    }
+    out.push('\n');
+    Some(())
+}
+
+/// Write `value` as a logfmt value, quoting it if it is empty or contains
+/// spaces or special characters.
+fn write_logfmt_value(out: &mut String, value: &str) {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c == ' ' || c == '=' || c == '"' || c == '\\' || c.is_control());
+    if !needs_quotes {
+        out.push_str(value);
+        return;
+    }
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Render the source location of `record` if enabled by `source_location`.
+pub(crate) fn render_location(
+    out: &mut String,
+    record: &log::Record,
+    config: &WinDebugLoggerBuilder,
+) {
+    let file = match record.file() {
+        Some(file) if config.source_location => file,
+        _ => return,
+    };
+    let _ = match (config.debugview_plus_plus, record.line()) {
+        (false, Some(line)) => write!(out, " @ {}:{}", file, line),
+        (false, None) => write!(out, " @ {}", file),
+        (true, Some(line)) => write!(out, " Source:{}@{}", file, line),
+        (true, None) => write!(out, " Source:{}", file),
+    };
+}
+
+/// Appended to a line truncated by `with_max_message_len`.
+const TRUNCATED: &str = "\u{2026}[truncated]";
+
+/// Truncate the part of `line` (without a null terminator) from `start` to
+/// `max_len` code units excluding the trailing newline, and append
+/// [`TRUNCATED`], if it is longer. Surrogate pairs are not split.
+pub(crate) fn truncate(line: &mut Vec<u16>, start: usize, max_len: usize) {
+    let newline = line.last() == Some(&u16::from(b'\n'));
+    if line.len() - usize::from(newline) - start <= max_len {
+        return;
+    }
+    let mut end = start + max_len;
+    if end > start && (0xd800..0xdc00).contains(&line[end - 1]) {
+        end -= 1;
+    }
+    line.truncate(end);
+    line.extend(TRUNCATED.encode_utf16());
+    if newline {
+        line.push(u16::from(b'\n'));
+    }
+}
+
+/// The number of 100-nanosecond intervals between 1601-01-01 (the `FILETIME`
+/// epoch) and 1970-01-01 (the Unix epoch).
+#[cfg(any(
+    feature = "time",
+    all(feature = "timestamps", not(feature = "no-timestamps"))
+))]
+pub(crate) const UNIX_EPOCH_FILETIME: i64 = 116_444_736_000_000_000;
+
+thread_local! {
+    /// The time rendered in place of the current time. See `with_timestamp`.
+    static TIMESTAMP: Cell<Option<SystemTime>> = const { Cell::new(None) };
+}
+
+/// Call `f`, rendering the lines formatted by the current thread in the
+/// meantime with `time` in place of the current time.
+pub(crate) fn with_timestamp<R>(time: SystemTime, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<SystemTime>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            TIMESTAMP.with(|timestamp| timestamp.set(self.0));
+        }
+    }
+
+    let _restore = Restore(TIMESTAMP.with(|timestamp| timestamp.replace(Some(time))));
+    f()
+}
+
+/// Get the time to render for `record` in place of the current time, if
+/// any: the one given to `with_timestamp`, or the key-value specified by
+/// `timestamp_key`.
+fn given_time(config: &WinDebugLoggerBuilder, record: &log::Record) -> Option<SystemTime> {
+    if let Some(time) = TIMESTAMP.with(Cell::get) {
+        return Some(time);
+    }
+    key_value_time(config, record)
+}
+
+/// Get the time given by the key-value specified by
+/// [`WinDebugLoggerBuilder::timestamp_key`], if any, in seconds since the
+/// Unix epoch.
+#[cfg(feature = "kv")]
+fn key_value_time(config: &WinDebugLoggerBuilder, record: &log::Record) -> Option<SystemTime> {
+    use std::time::Duration;
+
+    let key = config.timestamp_key.as_deref()?;
+    let value = record.key_values().get(log::kv::Key::from_str(key))?;
+    let secs = match value.to_i64() {
+        Some(secs) => secs as f64,
+        None => value.to_f64()?,
+    };
+    let duration = Duration::try_from_secs_f64(secs.abs()).ok()?;
+    if secs >= 0.0 {
+        UNIX_EPOCH.checked_add(duration)
+    } else {
+        UNIX_EPOCH.checked_sub(duration)
+    }
+}
+
+#[cfg(not(feature = "kv"))]
+fn key_value_time(_config: &WinDebugLoggerBuilder, _record: &log::Record) -> Option<SystemTime> {
+    None
+}
+
+/// Get the number of nanoseconds from the Unix epoch to `time`, which is
+/// negative if `time` is before it.
+fn unix_nanos(time: SystemTime) -> i128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_nanos() as i128,
+        Err(e) => -(e.duration().as_nanos() as i128),
+    }
+}
+
+/// Render the time of `record`. An empty result means no timestamp.
+fn timestamp(config: &WinDebugLoggerBuilder, record: &log::Record) -> Option<String> {
+    if let Some(CustomTimestamp(timestamp_fn)) = &config.custom_timestamp {
+        let mut timestamp = String::new();
+        // A panic leaves the timestamp empty
+        let _ = catch_unwind(AssertUnwindSafe(|| timestamp_fn(&mut timestamp)));
+        return Some(timestamp);
+    }
+
+    match given_time(config, record) {
+        // A given time that cannot be rendered (e.g., one before 1601)
+        // leaves the line without a timestamp
+        Some(time) => Some(render_time(config, Some(unix_nanos(time))).unwrap_or_default()),
+        None => render_time(config, None),
+    }
+}
+
+/// Render the time `at` (in nanoseconds since the Unix epoch), or the current
+/// time if `None`.
+fn render_time(config: &WinDebugLoggerBuilder, at: Option<i128>) -> Option<String> {
+    #[cfg(feature = "time")]
+    if let Some(format) = &config.timestamp_format {
+        return timestamp::format_at(format, config.utc, at);
+    }
+
+    system_timestamp(config, at)
+}
+
+/// Render the time `at` (in nanoseconds since the Unix epoch), or the current
+/// time if `None`, using `GetDateFormatW` and `GetTimeFormatW`.
+#[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+fn system_timestamp(config: &WinDebugLoggerBuilder, at: Option<i128>) -> Option<String> {
+    let system_time = match at {
+        Some(at) => time_at(at, config.utc)?,
+        None => current_time(config.utc),
+    };
+    let mut timestamp = Vec::new();
+    datetimeapi::format_date_into(&mut timestamp, &system_time, config.locale, 0, None)?;
+    timestamp.push(b' ' as u16);
+    datetimeapi::format_time_into(&mut timestamp, &system_time, config.locale, 0, None)?;
+    Some(String::from_utf16_lossy(&timestamp))
+}
+
+/// The built-in timestamp is compiled out.
+#[cfg(any(not(feature = "timestamps"), feature = "no-timestamps"))]
+fn system_timestamp(_config: &WinDebugLoggerBuilder, _at: Option<i128>) -> Option<String> {
+    Some(String::new())
+}
+
+/// Get the current time in UTC (`GetSystemTime`) or in the local time zone
+/// (`GetLocalTime`).
+#[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+fn current_time(utc: bool) -> SYSTEMTIME {
+    unsafe {
+        let mut out = std::mem::MaybeUninit::uninit();
+        if utc {
+            sysinfoapi::GetSystemTime(out.as_mut_ptr());
+        } else {
+            sysinfoapi::GetLocalTime(out.as_mut_ptr());
+        }
+        out.assume_init()
+    }
+}
+
+/// Convert `at` (in nanoseconds since the Unix epoch) to a `SYSTEMTIME` in
+/// UTC or in the local time zone, applying the daylight saving time rules
+/// in effect at that time. Returns `None` if it's out of range.
+#[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+fn time_at(at: i128, utc: bool) -> Option<SYSTEMTIME> {
+    let ticks = i128::from(UNIX_EPOCH_FILETIME) + at.div_euclid(100);
+    if !(0..=i128::from(i64::MAX)).contains(&ticks) {
+        return None;
+    }
+    let file_time = FILETIME {
+        dwLowDateTime: ticks as u32,
+        dwHighDateTime: (ticks >> 32) as u32,
+    };
+    unsafe {
+        let mut system_time = std::mem::zeroed();
+        if timezoneapi::FileTimeToSystemTime(&file_time, &mut system_time) == 0 {
+            return None;
+        }
+        if utc {
+            return Some(system_time);
+        }
+        let mut local_time = std::mem::zeroed();
+        if timezoneapi::SystemTimeToTzSpecificLocalTime(
+            std::ptr::null_mut(),
+            &system_time,
+            &mut local_time,
+        ) == 0
+        {
+            return None;
+        }
+        Some(local_time)
+    }
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warn => "warn",
+        Level::Info => "info",
+        Level::Debug => "debug",
+        Level::Trace => "trace",
+    }
+}
+
+fn level_letter(level: Level) -> char {
+    match level {
+        Level::Error => 'E',
+        Level::Warn => 'W',
+        Level::Info => 'I',
+        Level::Debug => 'D',
+        Level::Trace => 'T',
+    }
+}
+
+/// Shown in place of a target identical to that of the previous line.
+const REPEATED_TARGET: &str = " \" ";
+
+thread_local! {
+    /// The target of the last line formatted by the current thread. See
+    /// [`WinDebugLoggerBuilder::elide_repeated_targets`].
+    static LAST_TARGET: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Check if `target` is identical to the last target recorded by the current
+/// thread, and record `target`.
+fn is_repeated_target(target: &str) -> bool {
+    LAST_TARGET
+        .try_with(|last| {
+            let mut last = last.borrow_mut();
+            if *last == target {
+                true
+            } else {
+                last.clear();
+                last.push_str(target);
+                false
+            }
+        })
+        .unwrap_or(false)
+}
+
+/// Make the next line show its target even if it is a repeat.
+pub(crate) fn forget_last_target() {
+    let _ = LAST_TARGET.try_with(|last| last.borrow_mut().clear());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_message_matches_format() {
+        let word = "up";
+        let check = |args: std::fmt::Arguments| {
+            let record = log::Record::builder().args(args).build();
+            let expected = format!("{}", record.args());
+            let mut out = String::from("prefix ");
+            push_message(&mut out, &record);
+            assert_eq!(out, format!("prefix {}", expected));
+            assert_eq!(message(&record), expected);
+        };
+        // Literals take the fast path
+        check(format_args!("starting up"));
+        check(format_args!("100% {{braces}} \u{1f600}"));
+        check(format_args!(""));
+        check(format_args!("starting {}", word));
+        check(format_args!("{:>8}|{:.1}", word, 1.25));
+    }
+
+    #[test]
+    #[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+    fn test_with_timestamp() {
+        use std::time::Duration;
+
+        let options = WinDebugLoggerBuilder::new();
+        let record = log::Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Info)
+            .target("app")
+            .build();
+        let render_at =
+            |time| with_timestamp(time, || render_to_string(&record, &options).unwrap());
+
+        // 2020-09-13 12:26:40.5 UTC
+        let line = render_at(UNIX_EPOCH + Duration::from_millis(1_600_000_000_500));
+        assert!(
+            line.contains("2020") && line.contains("12:26:40"),
+            "{:?}",
+            line
+        );
+        assert!(line.ends_with(" INFO  [app] hello\n"), "{:?}", line);
+        // The current time is rendered again afterward
+        assert!(!render_to_string(&record, &options)
+            .unwrap()
+            .contains("12:26:40"));
+
+        // Out-of-range timestamps are left empty
+        let year_1000 = UNIX_EPOCH - Duration::from_secs(30_610_224_000);
+        assert_eq!(render_at(year_1000), "INFO  [app] hello\n");
+        let far_future = UNIX_EPOCH + Duration::from_secs(1 << 40);
+        assert_eq!(render_at(far_future), "INFO  [app] hello\n");
+
+        // Ignored by a custom timestamp function
+        let options = options.timestamp_fn(|out| out.push_str("now"));
+        let line = with_timestamp(year_1000, || render_to_string(&record, &options).unwrap());
+        assert_eq!(line, "now INFO  [app] hello\n");
+    }
+
+    #[test]
+    fn test_render_to_string() {
+        let options = WinDebugLoggerBuilder::new()
+            .timestamp_fn(|out| out.push_str("now"))
+            .split_level_tag(true);
+        let record = log::Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Info)
+            .target("app")
+            .build();
+        assert_eq!(
+            render_to_string(&record, &options).as_deref(),
+            Some("now INFO  [app] hello\n")
+        );
+
+        let options = options.custom_formatter(Box::new(|_, _| Err(std::fmt::Error)));
+        assert_eq!(render_to_string(&record, &options), None);
+    }
+
+    #[test]
+    fn test_source_location() {
+        let render = |options: WinDebugLoggerBuilder, line| {
+            let record = log::Record::builder()
+                .args(format_args!("hello"))
+                .level(Level::Info)
+                .target("app")
+                .file(Some("src/main.rs"))
+                .line(line)
+                .build();
+            render_to_string(&record, &options.timestamp_fn(|_| {})).unwrap()
+        };
+        let options = WinDebugLoggerBuilder::new().source_location(true);
+
+        assert_eq!(
+            render(options.clone(), Some(42)),
+            "INFO  [app] hello @ src/main.rs:42\n"
+        );
+        assert_eq!(
+            render(options.clone(), None),
+            "INFO  [app] hello @ src/main.rs\n"
+        );
+        assert_eq!(
+            render(options.clone().debugview_plus_plus(true), Some(42)),
+            "INFO  [app] hello Source:src/main.rs@42\n"
+        );
+        assert_eq!(
+            render(options.source_location(false), Some(42)),
+            "INFO  [app] hello\n"
+        );
+    }
+
+    #[test]
+    fn test_builtin_timestamp_options() {
+        // The line differs from that of the default build only in the
+        // timestamp, whether it is compiled out or not
+        let options = WinDebugLoggerBuilder::new()
+            .level_style(LevelStyle::Bracketed)
+            .level_symbol(Level::Warn, "!")
+            .source_location(true);
+        let line = render_to_string(
+            &log::Record::builder()
+                .args(format_args!("hello"))
+                .level(Level::Warn)
+                .target("app")
+                .file(Some("src/main.rs"))
+                .line(Some(42))
+                .build(),
+            &options,
+        )
+        .unwrap();
+        let expected = "! <W> [app] hello @ src/main.rs:42\n";
+        if cfg!(any(not(feature = "timestamps"), feature = "no-timestamps")) {
+            assert_eq!(line, expected);
+        } else {
+            assert!(line.ends_with(&format!(" {}", expected)), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn test_logfmt() {
+        let options = WinDebugLoggerBuilder::new()
+            .output_format(OutputFormat::Logfmt)
+            .timestamp_fn(|out| out.push_str("01/02/2020 12:34:56"))
+            .source_location(true);
+        let render = |message: &str| {
+            render_to_string(
+                &log::Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(Level::Warn)
+                    .target("app::net")
+                    .file(Some("src/main.rs"))
+                    .line(Some(42))
+                    .build(),
+                &options,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            render("disk is full"),
+            "ts=\"01/02/2020 12:34:56\" level=warn target=app::net \
+             msg=\"disk is full\" file=src/main.rs line=42\n"
+        );
+        assert!(render("ok").contains(" msg=ok "));
+        assert!(render("").contains(" msg=\"\" "));
+        assert!(render("a=\"b\"\\c\nd\0").contains(r#" msg="a=\"b\"\\c\nd\u0000" "#));
+    }
+
+    #[test]
+    fn test_frame_number() {
+        let record = log::Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Info)
+            .target("app")
+            .build();
+        let options = WinDebugLoggerBuilder::new()
+            .timestamp_fn(|out| out.push_str("now"))
+            .frame_number(true);
+
+        crate::set_frame(12345);
+        assert_eq!(
+            render_to_string(&record, &options).unwrap(),
+            "now [f:12345] INFO  [app] hello\n"
+        );
+        assert_eq!(
+            render_to_string(&record, &options.output_format(OutputFormat::Logfmt)).unwrap(),
+            "ts=now frame=12345 level=info target=app msg=hello\n"
+        );
+    }
+
+    #[test]
+    fn test_name() {
+        let record = log::Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Info)
+            .target("app")
+            .build();
+        let options = WinDebugLoggerBuilder::new().timestamp_fn(|out| out.push_str("now"));
+        assert_eq!(
+            render_to_string(&record, &options).unwrap(),
+            "now INFO  [app] hello\n"
+        );
+
+        let options = options.name("compact");
+        assert_eq!(
+            render_to_string(&record, &options).unwrap(),
+            "now [logger:compact] INFO  [app] hello\n"
+        );
+        assert_eq!(
+            render_to_string(&record, &options.output_format(OutputFormat::Logfmt)).unwrap(),
+            "ts=now logger=compact level=info target=app msg=hello\n"
+        );
+    }
+
+    #[cfg(feature = "kv")]
+    #[test]
+    fn test_key_values() {
+        let key_values = [("user", log::kv::Value::from("alice")), ("id", 42.into())];
+        let record = log::Record::builder()
+            .args(format_args!("logged in"))
+            .level(Level::Info)
+            .target("app")
+            .key_values(&key_values)
+            .build();
+        let options = WinDebugLoggerBuilder::new().timestamp_fn(|_| {});
+
+        assert_eq!(
+            render_to_string(&record, &options).unwrap(),
+            "INFO  [app] logged in user=alice id=42\n"
+        );
+        assert_eq!(
+            render_to_string(&record, &options.output_format(OutputFormat::Logfmt)).unwrap(),
+            "level=info target=app msg=\"logged in\" user=alice id=42\n"
+        );
+    }
+
+    #[cfg(all(feature = "kv", feature = "timestamps", not(feature = "no-timestamps")))]
+    #[test]
+    fn test_timestamp_key() {
+        let render = |ts: log::kv::Value| {
+            let key_values = [("ts", ts)];
+            let record = log::Record::builder()
+                .args(format_args!("replayed"))
+                .level(Level::Info)
+                .target("app")
+                .key_values(&key_values)
+                .build();
+            render_to_string(&record, &WinDebugLoggerBuilder::new().timestamp_key("ts")).unwrap()
+        };
+
+        assert!(render(1_600_000_000.into()).contains("12:26:40"));
+        assert!(render(1_600_000_000.5.into()).contains("12:26:40"));
+        // Not a number; the current time is rendered
+        assert!(!render("yesterday".into()).contains("12:26:40"));
+        assert!(!render(f64::NAN.into()).starts_with("INFO "));
+        // Out of range
+        assert!(render((-1e12).into()).starts_with("INFO "));
+    }
+
+    #[cfg(feature = "kv")]
+    #[test]
+    fn test_key_values_without_message() {
+        let options = WinDebugLoggerBuilder::new().timestamp_fn(|_| {});
+        let render = |key_values: &[(&str, log::kv::Value)]| {
+            render_to_string(
+                &log::Record::builder()
+                    .args(format_args!(""))
+                    .level(Level::Info)
+                    .target("app")
+                    .key_values(&key_values)
+                    .build(),
+                &options,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            render(&[("k1", "v1".into()), ("k2", 2.into())]),
+            "INFO  [app] {k1=v1 k2=2}\n"
+        );
+        assert_eq!(render(&[("k1", "v1".into())]), "INFO  [app] {k1=v1}\n");
+        assert_eq!(render(&[]), "INFO  [app] \n");
+    }
+
+    #[cfg(feature = "kv_serde")]
+    #[test]
+    fn test_is_primitive() {
+        use log::kv::Value;
+
+        assert!(is_primitive(&Value::from(42)));
+        assert!(is_primitive(&Value::from(-1.5)));
+        assert!(is_primitive(&Value::from(true)));
+        assert!(is_primitive(&Value::from("text")));
+        assert!(is_primitive(&Value::from_serde(&7u8)));
+        assert!(!is_primitive(&Value::from_serde(&vec![1, 2])));
+    }
+
+    #[cfg(feature = "kv_serde")]
+    #[test]
+    fn test_key_values_serde() {
+        use log::kv::Value;
+        use std::collections::BTreeMap;
+
+        let config = serde_json::json!({ "width": 800, "height": 600 });
+        let sizes = vec![1u32, 2, 3];
+        let counts: BTreeMap<&str, u32> = [("a", 1), ("b", 2)].iter().cloned().collect();
+        let key_values = [
+            ("config", Value::from_serde(&config)),
+            ("sizes", Value::from_serde(&sizes)),
+            ("counts", Value::from_serde(&counts)),
+        ];
+        let record = log::Record::builder()
+            .args(format_args!("start"))
+            .level(Level::Info)
+            .target("app")
+            .key_values(&key_values)
+            .build();
+        let options = WinDebugLoggerBuilder::new().timestamp_fn(|_| {});
+
+        assert_eq!(
+            render_to_string(&record, &options).unwrap(),
+            "INFO  [app] start config={\"height\":600,\"width\":800} sizes=[1,2,3] \
+             counts={\"a\":1,\"b\":2}\n"
+        );
+        assert!(
+            render_to_string(&record, &options.output_format(OutputFormat::Logfmt))
+                .unwrap()
+                .contains(r#" sizes="[1,2,3]" "#)
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let truncate_from = |line: &str, start, max_len| {
+            let mut line: Vec<u16> = line.encode_utf16().collect();
+            truncate(&mut line, start, max_len);
+            String::from_utf16(&line).unwrap()
+        };
+        let truncate = |line: &str, max_len| truncate_from(line, 0, max_len);
+        assert_eq!(truncate("hello\n", 5), "hello\n");
+        assert_eq!(truncate("hello, world\n", 5), "hello…[truncated]\n");
+        assert_eq!(truncate("hello, world", 5), "hello…[truncated]");
+        // "🦄" is a surrogate pair
+        assert_eq!(truncate("abcd🦄\n", 5), "abcd…[truncated]\n");
+        assert_eq!(truncate("abc🦄\n", 5), "abc🦄\n");
+        // The part before `start` is kept
+        assert_eq!(truncate_from("WARN hello\n", 5, 5), "WARN hello\n");
+        assert_eq!(
+            truncate_from("WARN hello, world\n", 5, 5),
+            "WARN hello…[truncated]\n"
+        );
+        assert_eq!(truncate_from("WARN 🦄\n", 5, 1), "WARN …[truncated]\n");
+    }
+
+    #[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+    fn assert_valid_time(time: &SYSTEMTIME) {
+        assert!((1601..=30827).contains(&time.wYear), "{}", time.wYear);
+        assert!((1..=12).contains(&time.wMonth), "{}", time.wMonth);
+        assert!(time.wDayOfWeek <= 6, "{}", time.wDayOfWeek);
+        assert!((1..=31).contains(&time.wDay), "{}", time.wDay);
+        assert!(time.wHour <= 23, "{}", time.wHour);
+        assert!(time.wMinute <= 59, "{}", time.wMinute);
+        assert!(time.wSecond <= 59, "{}", time.wSecond);
+        assert!(time.wMilliseconds <= 999, "{}", time.wMilliseconds);
+    }
+
+    #[test]
+    #[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+    fn test_current_time() {
+        let utc = current_time(true);
+        let local = current_time(false);
+        assert_valid_time(&utc);
+        assert_valid_time(&local);
+
+        // Time zone offsets are multiples of 15 minutes, so the two values
+        // should agree on the second (barring a tick between the two calls)
+        assert!(
+            utc.wSecond == local.wSecond || (utc.wSecond + 1) % 60 == local.wSecond,
+            "{} {}",
+            utc.wSecond,
+            local.wSecond
+        );
+    }
+}
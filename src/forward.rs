@@ -0,0 +1,318 @@
+//! Re-emitting the debug output of a child process. See
+//! [`forward_from_pid`].
+use log::Level;
+use std::{
+    fmt, io,
+    ptr::{self, null_mut},
+    sync::Arc,
+    thread,
+};
+use winapi::{
+    shared::{
+        minwindef::FALSE,
+        ntdef::HANDLE,
+        winerror::{ERROR_ALREADY_EXISTS, WAIT_TIMEOUT},
+    },
+    um::{
+        errhandlingapi, handleapi, memoryapi, processthreadsapi, synchapi,
+        winbase::{INFINITE, WAIT_OBJECT_0},
+        winnt,
+    },
+};
+
+use crate::codecvt;
+
+/// The target of forwarded records.
+pub const TARGET: &str = "windebug_logger::child";
+
+/// The size of `DBWIN_BUFFER`: the process ID (a little-endian `u32`)
+/// followed by a null-terminated string.
+const BUFFER_LEN: usize = 4096;
+
+/// An owned kernel object handle.
+struct Handle(HANDLE);
+
+// Kernel object handles can be used from any thread
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Handle {
+    /// Take ownership of `handle`, or fail with the last error if it's null.
+    fn new(handle: HANDLE) -> io::Result<Self> {
+        if handle.is_null() {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Self(handle))
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { handleapi::CloseHandle(self.0) };
+    }
+}
+
+/// The objects receiving the output of `OutputDebugStringW` in the current
+/// session.
+struct Dbwin {
+    buffer_ready: Handle,
+    data_ready: Handle,
+    _mapping: Handle,
+    view: *const u8,
+}
+
+// `view` is only read by the thread owning `Dbwin`
+unsafe impl Send for Dbwin {}
+
+impl Dbwin {
+    fn open() -> io::Result<Self> {
+        let create_event = |name: &[u16]| {
+            let event = unsafe { synchapi::CreateEventW(null_mut(), FALSE, FALSE, name.as_ptr()) };
+            let existed = unsafe { errhandlingapi::GetLastError() } == ERROR_ALREADY_EXISTS;
+            let event = Handle::new(event)?;
+            if existed {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "another debug output monitor is running",
+                ));
+            }
+            Ok(event)
+        };
+        let buffer_ready = create_event(wchar::wch_c!("DBWIN_BUFFER_READY"))?;
+        let data_ready = create_event(wchar::wch_c!("DBWIN_DATA_READY"))?;
+
+        let mapping = Handle::new(unsafe {
+            memoryapi::CreateFileMappingW(
+                handleapi::INVALID_HANDLE_VALUE,
+                null_mut(),
+                winnt::PAGE_READWRITE,
+                0,
+                BUFFER_LEN as u32,
+                wchar::wch_c!("DBWIN_BUFFER").as_ptr(),
+            )
+        })?;
+        let view =
+            unsafe { memoryapi::MapViewOfFile(mapping.0, memoryapi::FILE_MAP_READ, 0, 0, 0) };
+        if view.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            buffer_ready,
+            data_ready,
+            _mapping: mapping,
+            view: view as *const u8,
+        })
+    }
+
+    /// Copy the contents of the buffer and let the next writer in.
+    fn take(&self) -> Vec<u8> {
+        let mut buffer = vec![0; BUFFER_LEN];
+        unsafe {
+            ptr::copy_nonoverlapping(self.view, buffer.as_mut_ptr(), BUFFER_LEN);
+            synchapi::SetEvent(self.buffer_ready.0);
+        }
+        buffer
+    }
+}
+
+impl Drop for Dbwin {
+    fn drop(&mut self) {
+        unsafe { memoryapi::UnmapViewOfFile(self.view as _) };
+    }
+}
+
+/// Split the contents of `DBWIN_BUFFER` into the process ID and the string.
+fn parse(buffer: &[u8]) -> (u32, &[u8]) {
+    let pid = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+    let string = &buffer[4..];
+    let len = string.iter().position(|&b| b == 0).unwrap_or(string.len());
+    (pid, &string[..len])
+}
+
+/// Forwards the debug output of a child process. Returned by
+/// [`forward_from_pid`].
+///
+/// Forwarding stops when the child process exits, or when this is stopped
+/// by [`stop`](Self::stop) or dropped.
+pub struct ChildForwarder {
+    pid: u32,
+    stop: Arc<Handle>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl fmt::Debug for ChildForwarder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChildForwarder")
+            .field("pid", &self.pid)
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+impl ChildForwarder {
+    /// Get the ID of the process whose output is forwarded.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Check if forwarding has stopped, e.g., because the child process
+    /// exited.
+    // `is_none_or` requires Rust 1.82
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn is_finished(&self) -> bool {
+        self.thread
+            .as_ref()
+            .map_or(true, |thread| thread.is_finished())
+    }
+
+    /// Stop forwarding, waiting for the thread to exit, and release the
+    /// debug output objects so that another monitor can run.
+    pub fn stop(mut self) {
+        self.stop_thread();
+    }
+
+    fn stop_thread(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            unsafe { synchapi::SetEvent(self.stop.0) };
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ChildForwarder {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+/// Receive the output of `OutputDebugStringW` (and `OutputDebugStringA`) of
+/// the process `pid`, e.g., a helper process spawned by this process, and
+/// log each string as a record at `level` with the target
+/// `windebug_logger::child` and the prefix `[child PID]`.
+///
+/// Debuggers receive the strings as bytes, which are decoded with
+/// `code_page`. Pass `CP_ACP` (`0`) for the ANSI code page, which
+/// `OutputDebugStringW` converts to, or the code page given to
+/// [`WinDebugLoggerBuilder::code_page`] if the child process uses this
+/// crate with that setting.
+///
+/// The records go through the global logger (usually this crate's) like any
+/// other, so they are filtered and formatted in the same way. Trailing line
+/// breaks are removed from the strings. A background thread receives the
+/// strings until the child process exits or the returned [`ChildForwarder`]
+/// is stopped or dropped.
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// windebug_logger::WinDebugLoggerBuilder::new()
+///     .file("app.log")?
+///     .init()
+///     .unwrap();
+///
+/// let child = std::process::Command::new("helper.exe").spawn()?;
+/// let forwarder = windebug_logger::forward_from_pid(child.id(), log::Level::Debug, 0)?;
+/// // ...
+/// forwarder.stop();
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Contention with other monitors
+///
+/// Only one program per session can receive debug output, so this makes
+/// the current process the debug output monitor of its session, like
+/// DebugView. This fails with [`io::ErrorKind::AlreadyExists`] if another
+/// monitor (such as DebugView or another forwarder) is running, and other
+/// monitors cannot start while forwarding. The output of the processes
+/// other than `pid` is discarded in the meantime; this includes this
+/// process's own output unless a debugger is attached to it, so log to
+/// another sink, such as [`WinDebugLoggerBuilder::file`], while forwarding.
+/// The child process must run in the same session, and its output is not
+/// received while a debugger is attached to it.
+///
+/// [`WinDebugLoggerBuilder::code_page`]: crate::WinDebugLoggerBuilder::code_page
+/// [`WinDebugLoggerBuilder::file`]: crate::WinDebugLoggerBuilder::file
+pub fn forward_from_pid(pid: u32, level: Level, code_page: u32) -> io::Result<ChildForwarder> {
+    let process =
+        Handle::new(unsafe { processthreadsapi::OpenProcess(winnt::SYNCHRONIZE, FALSE, pid) })?;
+    let stop = Arc::new(Handle::new(unsafe {
+        synchapi::CreateEventW(null_mut(), FALSE, FALSE, null_mut())
+    })?);
+    let dbwin = Dbwin::open()?;
+    // Let the first writer in
+    unsafe { synchapi::SetEvent(dbwin.buffer_ready.0) };
+
+    let thread = thread::Builder::new()
+        .name("windebug_logger child forwarder".to_owned())
+        .spawn({
+            let stop = Arc::clone(&stop);
+            move || run(pid, level, code_page, &dbwin, &process, &stop)
+        })?;
+
+    Ok(ChildForwarder {
+        pid,
+        stop,
+        thread: Some(thread),
+    })
+}
+
+fn run(pid: u32, level: Level, code_page: u32, dbwin: &Dbwin, process: &Handle, stop: &Handle) {
+    // If both are signaled, the lowest index wins, so the last strings of
+    // the child process are received before its exit
+    let handles = [stop.0, dbwin.data_ready.0, process.0];
+    loop {
+        let result = unsafe {
+            synchapi::WaitForMultipleObjects(
+                handles.len() as u32,
+                handles.as_ptr(),
+                FALSE,
+                INFINITE,
+            )
+        };
+        if result != WAIT_OBJECT_0 + 1 {
+            // Stopped, the process exited, or the wait failed
+            debug_assert_ne!(result, WAIT_TIMEOUT);
+            return;
+        }
+
+        // Let the next writer in before logging, which might write to the
+        // buffer itself
+        let buffer = dbwin.take();
+        let (from, string) = parse(&buffer);
+        if from != pid || level > log::max_level() {
+            continue;
+        }
+        let message = codecvt::multibyte_to_wstr(code_page, string).unwrap_or_default();
+        let message = String::from_utf16_lossy(&message);
+        log::logger().log(
+            &log::Record::builder()
+                .args(format_args!(
+                    "[child {}] {}",
+                    pid,
+                    message.trim_end_matches(&['\r', '\n'][..])
+                ))
+                .level(level)
+                .target(TARGET)
+                .build(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mut buffer = vec![0; BUFFER_LEN];
+        buffer[..4].copy_from_slice(&1234u32.to_le_bytes());
+        buffer[4..10].copy_from_slice(b"hello\n");
+        assert_eq!(parse(&buffer), (1234, &b"hello\n"[..]));
+
+        // Not null-terminated
+        let buffer = [0xd2, 0x04, 0, 0, b'h', b'i'];
+        assert_eq!(parse(&buffer), (1234, &b"hi"[..]));
+    }
+}
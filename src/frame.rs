@@ -0,0 +1,30 @@
+//! The frame counter. See [`set_frame`].
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static FRAME: AtomicU64 = AtomicU64::new(0);
+
+/// Set the current frame number, which is shown in each line as `[f:12345]`
+/// if enabled by [`WinDebugLoggerBuilder::frame_number`].
+///
+/// This is meant to be called by a game or another real-time renderer once
+/// per frame, so that log lines can be correlated with the frames they were
+/// logged in. It can be called from any thread and is a single atomic
+/// store. The frame number is process-wide and starts at `0`.
+///
+/// ```
+/// # fn render() {}
+/// for frame in 0..3 {
+///     windebug_logger::set_frame(frame);
+///     render();
+/// }
+/// ```
+///
+/// [`WinDebugLoggerBuilder::frame_number`]: crate::WinDebugLoggerBuilder::frame_number
+pub fn set_frame(frame: u64) {
+    FRAME.store(frame, Ordering::Relaxed);
+}
+
+/// Get the frame number set by `set_frame`.
+pub(crate) fn current() -> u64 {
+    FRAME.load(Ordering::Relaxed)
+}
@@ -0,0 +1,199 @@
+//! Periodic records showing that the process is alive. See
+//! [`WinDebugLoggerBuilder::heartbeat`].
+//!
+//! [`WinDebugLoggerBuilder::heartbeat`]: crate::WinDebugLoggerBuilder::heartbeat
+use log::Level;
+use std::{
+    fmt::Write,
+    sync::{Arc, Condvar, Mutex, Once},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{sysinfo, WinDebugLogger};
+
+/// The target of heartbeat records.
+pub const TARGET: &str = "windebug_logger::heartbeat";
+
+#[derive(Debug)]
+pub(crate) struct Heartbeat {
+    interval: Duration,
+    /// Set by `close` to stop the thread.
+    closed: Arc<(Mutex<bool>, Condvar)>,
+    started: Once,
+    /// The thread, taken by `close` to join it.
+    handle: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl Heartbeat {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            closed: Arc::new((Mutex::new(false), Condvar::new())),
+            started: Once::new(),
+            handle: Mutex::new(None),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Start the thread logging heartbeat records through `logger`. Does
+    /// nothing if it's already started or `close` was called.
+    pub fn start(&self, logger: &'static WinDebugLogger) {
+        self.started.call_once(|| {
+            let closed = Arc::clone(&self.closed);
+            let interval = self.interval;
+            let totals = logger.counters.totals();
+            // If this fails, no heartbeat records are logged
+            let handle = thread::Builder::new()
+                .name("windebug_logger heartbeat".to_owned())
+                .spawn(move || run(logger, interval, totals, &closed));
+            *self.handle.lock().unwrap_or_else(|e| e.into_inner()) = handle.ok();
+        });
+    }
+
+    /// Stop the thread, waiting for it to exit.
+    pub fn close(&self) {
+        let (closed, condvar) = &*self.closed;
+        *closed.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_one();
+
+        let handle = self.handle.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(handle) = handle {
+            // A sink might shut down the logger from the thread itself
+            if handle.thread().id() != thread::current().id() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+fn run(
+    logger: &'static WinDebugLogger,
+    interval: Duration,
+    mut previous: (u64, u64),
+    closed: &(Mutex<bool>, Condvar),
+) {
+    let (closed, condvar) = closed;
+    let mut next = Instant::now() + interval;
+    let mut guard = closed.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        let now = Instant::now();
+        if now < next {
+            guard = condvar
+                .wait_timeout(guard, next - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+            if *guard {
+                return;
+            }
+            continue;
+        }
+        next += interval;
+        drop(guard);
+
+        let totals = logger.counters.totals();
+        // Skip while logging is paused by `enable_event`
+        if logger.is_active() {
+            let message = message(logger, previous, totals);
+            log::Log::log(
+                logger,
+                &log::Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(Level::Info)
+                    .target(TARGET)
+                    .build(),
+            );
+        }
+        previous = totals;
+
+        guard = closed.lock().unwrap_or_else(|e| e.into_inner());
+        if *guard {
+            return;
+        }
+    }
+}
+
+/// Describe the state of the process, with the numbers of the records
+/// logged and dropped since `previous`.
+fn message(logger: &WinDebugLogger, previous: (u64, u64), totals: (u64, u64)) -> String {
+    let mut out = String::from("heartbeat:");
+    if let Some(init_time) = logger.init_time.get() {
+        let secs = init_time.elapsed().as_secs();
+        let _ = write!(
+            out,
+            " uptime={}:{:02}:{:02}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        );
+    }
+    if let Some(size) = sysinfo::working_set_size() {
+        let _ = write!(out, " working-set={}KiB", size / 1024);
+    }
+    if let Some(count) = sysinfo::handle_count() {
+        let _ = write!(out, " handles={}", count);
+    }
+    if let Some(count) = sysinfo::thread_count() {
+        let _ = write!(out, " threads={}", count);
+    }
+    let _ = write!(
+        out,
+        " logged=+{} dropped=+{}",
+        totals.0.wrapping_sub(previous.0),
+        totals.1.wrapping_sub(previous.1)
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test, WinDebugLoggerBuilder};
+
+    #[test]
+    fn test_heartbeat() {
+        let _guard = test::lock();
+
+        let logger = test::install(
+            WinDebugLoggerBuilder::new()
+                .ring_buffer(1024)
+                .heartbeat(Duration::from_millis(20)),
+        );
+        let heartbeats = || -> Vec<String> {
+            (logger.history.as_ref().unwrap().snapshot().into_iter())
+                .filter(|line| line.contains(" [windebug_logger::heartbeat] heartbeat: uptime=0:"))
+                .collect()
+        };
+
+        // Wait for the heartbeats rather than for a fixed time
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while heartbeats().len() < 2 {
+            assert!(Instant::now() < deadline, "{:?}", heartbeats());
+            thread::sleep(Duration::from_millis(1));
+        }
+        test::reset();
+
+        // Nothing is logged before the first one, and each of the others
+        // counts the previous one, however late the thread runs
+        let heartbeats = heartbeats();
+        assert!(
+            heartbeats[0].ends_with(" logged=+0 dropped=+0\n"),
+            "{:?}",
+            heartbeats
+        );
+        for heartbeat in &heartbeats[1..] {
+            assert!(
+                heartbeat.ends_with(" logged=+1 dropped=+0\n"),
+                "{:?}",
+                heartbeats
+            );
+        }
+
+        // The thread is joined by `shutdown`
+        let heartbeat = logger.heartbeat.as_ref().unwrap();
+        assert!(heartbeat.handle.lock().unwrap().is_none());
+    }
+}
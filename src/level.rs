@@ -0,0 +1,84 @@
+//! Parsing of level filters. See [`parse_level`].
+use log::LevelFilter;
+use std::fmt;
+
+/// Parse a level filter given by a user, e.g., through a command-line flag.
+///
+/// This accepts `off`, `error`, `warn`, `warning`, `info`, `debug`, and
+/// `trace` in any case, and the numbers `0` (`off`) to `5` (`trace`).
+/// Surrounding whitespace is ignored.
+///
+/// ```
+/// use log::LevelFilter;
+/// use windebug_logger::parse_level;
+///
+/// assert_eq!(parse_level("Warning"), Ok(LevelFilter::Warn));
+/// assert_eq!(parse_level("5"), Ok(LevelFilter::Trace));
+/// assert!(parse_level("loud").is_err());
+/// ```
+pub fn parse_level(s: &str) -> Result<LevelFilter, ParseLevelError> {
+    let trimmed = s.trim();
+    let level = match trimmed.to_ascii_lowercase().as_str() {
+        "off" | "0" => LevelFilter::Off,
+        "error" | "1" => LevelFilter::Error,
+        "warn" | "warning" | "2" => LevelFilter::Warn,
+        "info" | "3" => LevelFilter::Info,
+        "debug" | "4" => LevelFilter::Debug,
+        "trace" | "5" => LevelFilter::Trace,
+        _ => {
+            return Err(ParseLevelError {
+                input: trimmed.to_owned(),
+            })
+        }
+    };
+    Ok(level)
+}
+
+/// The error returned by [`parse_level`]. Its `Display` implementation lists
+/// the accepted values, so it can be shown to the user as it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLevelError {
+    input: String,
+}
+
+impl ParseLevelError {
+    /// Get the string that could not be parsed.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+}
+
+impl fmt::Display for ParseLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid log level {:?}; expected one of off, error, warn (warning), \
+             info, debug, trace, or a number from 0 (off) to 5 (trace)",
+            self.input
+        )
+    }
+}
+
+impl std::error::Error for ParseLevelError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level("off"), Ok(LevelFilter::Off));
+        assert_eq!(parse_level("ERROR"), Ok(LevelFilter::Error));
+        assert_eq!(parse_level("Warning"), Ok(LevelFilter::Warn));
+        assert_eq!(parse_level(" info\n"), Ok(LevelFilter::Info));
+        assert_eq!(parse_level("4"), Ok(LevelFilter::Debug));
+        assert_eq!(parse_level("trace"), Ok(LevelFilter::Trace));
+
+        let e = parse_level("6").unwrap_err();
+        assert_eq!(e.input(), "6");
+        assert!(e
+            .to_string()
+            .starts_with("invalid log level \"6\"; expected"));
+        assert!(parse_level("").is_err());
+    }
+}
@@ -10,200 +10,3329 @@
 //! and Visual Studio's "Output" window.
 //!
 //! [DebugView]: https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
+//!
+//! # Supported Windows versions
+//!
+//! By default, this crate only calls Win32 API functions available on
+//! Windows XP, and looks up newer ones at runtime where they are beneficial:
+//!
+//! | Feature          | Minimum version | Notes |
+//! | ---------------- | --------------- | ----- |
+//! | (default)        | Windows XP      | |
+//! | `time`           | Windows XP      | Uses `GetSystemTimePreciseAsFileTime` if available (Windows 8 and later) |
+//! | `compat-xp`      | Windows XP      | Never looks up functions newer than Windows XP, making it a compile-time guarantee |
+//! | `etw-structured` | Windows Vista   | Calls `EventRegister` and `EventWrite`, even with `compat-xp`. Sets the provider traits with `EventSetInformation` if available (Windows 8 and later) |
+//!
+//! # Cargo features
+//!
+//!  - `time`: Enables `WinDebugLoggerBuilder::timestamp_format_description`.
+//!  - `compat-xp`: See above.
+//!  - `build-script`: Makes [`init_with_level_static!`] install a logger
+//!    that writes records as `cargo:warning=` directives, for use in build
+//!    scripts. Enable it only for `[build-dependencies]`, and make sure that
+//!    the package uses [feature resolver] version 2 so that it does not
+//!    affect normal dependencies.
+//!  - `serde`: Implements `Deserialize` and `Serialize` for `Config`, the
+//!    configuration applied by `WinDebugLoggerBuilder::from_config`, with
+//!    levels as lowercase names.
+//!  - `toml`, `json`: Enable `init_from_file` and `Config::from_file` for
+//!    the respective formats. Both imply `serde`. `json` also enables
+//!    `WinDebugLoggerBuilder::from_config_file`.
+//!  - `stats`: Enables `stats()`, which reports the time spent in
+//!    `OutputDebugStringW`.
+//!  - `kv`: Appends the key-values of records to lines as `key=value`
+//!    pairs, e.g., `INFO  [app] logged in user=alice`. If the message is
+//!    empty, the key-values take its place in braces, e.g.,
+//!    `INFO  [app] {user=alice id=42}`.
+//!  - `kv_serde`: Implies `kv`, and renders structured key-values (captured
+//!    with `log::kv::Value::from_serde`) as compact JSON, e.g.,
+//!    `config={"width":800,"height":600}`.
+//!  - `shared-memory`: Enables
+//!    [`WinDebugLoggerBuilder::with_shared_memory_ring`], which writes lines
+//!    to a ring buffer in shared memory for other processes to read.
+//!  - `etw-structured`: Enables [`WinEtwStructuredSink`], which writes
+//!    records to ETW as TraceLogging events with typed fields.
+//!  - `test-util`: Enables the `test` module, which lets tests install
+//!    loggers with different configurations in the same process, and the
+//!    `capture` module, which collects the output in memory.
+//!  - `timestamps` (default): Enables the built-in timestamp, which is
+//!    rendered by `GetDateFormatW` and `GetTimeFormatW`, and the `winapi`
+//!    features it needs (`datetimeapi` and `timezoneapi`).
+//!  - `no-timestamps`: Compiles out the built-in timestamp to reduce the
+//!    binary size, even if `timestamps` is enabled. Disable the default
+//!    features as well to leave out the `winapi` features used only by the
+//!    timestamp. Lines then start with the level, e.g.,
+//!    `INFO  [app] message`, [`WinDebugLoggerBuilder::locale`] has no
+//!    effect, and the banner is not dated. Lines are rendered by the same
+//!    code as in the default build, so all the other format options apply.
+//!    [`WinDebugLoggerBuilder::timestamp_fn`] and the `time` feature still
+//!    work.
+//!
+//! [feature resolver]: https://doc.rust-lang.org/cargo/reference/resolver.html#feature-resolver-version-2
+//!
+//! Note that the Rust standard library itself requires a newer version on
+//! most targets (e.g., Windows 10 for `x86_64-pc-windows-msvc` as of Rust
+//! 1.78). Running on Windows XP requires a toolchain targeting it.
+//!
+//! # Long messages
+//!
+//! Debuggers receive at most 4096 bytes per `OutputDebugStringW` call, so
+//! lines longer than 2000 UTF-16 code units are split into multiple calls.
+//! Each part starts with a marker such as `(part 2/5 k3f9) `, where `k3f9`
+//! is a random token shared by the parts of the line, so that they can be
+//! reassembled even if other processes' output is interleaved. Lines that
+//! fit in one call have no marker.
 
-// Used by `init_with_level_static!`
+// Formerly used by `init_with_level_static!`; kept for compatibility
 #[doc(hidden)]
 pub extern crate log;
 
-use log::{Level, SetLoggerError};
+use log::{Level, LevelFilter, SetLoggerError};
+use std::os::windows::ffi::OsStrExt;
 use std::{
-    convert::TryInto,
-    mem::{transmute, MaybeUninit},
-    ptr::null,
+    borrow::Cow,
+    ffi::OsStr,
+    fmt::{self, Write},
+    panic::{Location, RefUnwindSafe, UnwindSafe},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
 };
-use winapi::um::{datetimeapi, debugapi, sysinfoapi, winbase, winnt};
+use winapi::um::debugapi;
 
-mod codecvt;
+#[cfg(feature = "build-script")]
+mod build_logger;
+mod builder;
+#[cfg(any(test, feature = "test-util"))]
+pub mod capture;
+mod chunk;
+mod coalesce;
+pub mod codecvt;
+pub mod compat;
+mod config;
+#[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+mod datetimeapi;
+mod dedup;
+mod error;
+#[cfg(feature = "etw-structured")]
+mod etw;
+mod event;
+mod filter;
+pub mod format;
+mod forward;
+mod frame;
+mod heartbeat;
+mod level;
+mod listener;
+mod ring;
+mod sample;
+mod seh;
+mod session;
+#[cfg(feature = "shared-memory")]
+mod shm;
+mod sink;
+mod stats;
+pub mod stdio;
+mod sysinfo;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test;
+mod thread_filter;
+mod thread_level;
+mod time_scope;
+#[cfg(feature = "time")]
+mod timestamp;
 
-#[doc(hidden)]
-#[derive(Debug)]
+#[cfg(feature = "build-script")]
+pub use self::build_logger::{__install_build_script, BuildScriptLogger};
+pub use self::config::Config;
+#[cfg(feature = "etw-structured")]
+pub use self::etw::WinEtwStructuredSink;
+#[cfg(feature = "stats")]
+pub use self::stats::{stats, LogStats};
+pub use self::{
+    builder::{WinDebugLoggerBuilder, WinDebugLoggerDefaults},
+    error::Error,
+    forward::{forward_from_pid, ChildForwarder},
+    frame::set_frame,
+    level::{parse_level, ParseLevelError},
+    listener::{debug_listener_present, debugger_present, refresh_listener_state, ListenerKind},
+    seh::{emit_raw, emit_raw_static, install_exception_logger},
+    sink::{DebugSink, FnSink, Sinks},
+    thread_filter::{mark_main_thread, ThreadFilter, ThreadNameFn},
+    thread_level::ThreadLevelGuard,
+    time_scope::{time_scope, ScopeTimer},
+};
+
+/// The logger implementation.
+///
+/// Most applications do not need to use this type directly; the
+/// initialization functions such as [`init`] and [`WinDebugLoggerBuilder`]
+/// create and install it as the global logger. It can also be constructed
+/// by [`new`] or [`from_builder`] and used without being installed, e.g.,
+/// by passing it to a crate that dispatches records to multiple loggers. In
+/// that case, [`global`] does not return it, and the features that act on
+/// installation (such as [`WinDebugLoggerBuilder::banner`]) are not
+/// triggered.
+///
+/// This type is guaranteed to implement [`log::Log`], [`Debug`],
+/// [`Default`], `Send`, `Sync`, `UnwindSafe`, and `RefUnwindSafe`.
+///
+/// [`new`]: Self::new
+/// [`from_builder`]: Self::from_builder
+/// [`Debug`]: fmt::Debug
 pub struct WinDebugLogger {
-    pub level: Level,
+    /// The current level filter, stored as `LevelFilter as usize`.
+    level: AtomicUsize,
+    trace_window: Option<TraceWindow>,
+    enable_event: Option<event::EnableEvent>,
+    shut_down: AtomicBool,
+    targets: filter::TargetLevels,
+    /// See [`WinDebugLoggerBuilder::allow_targets`] and
+    /// [`WinDebugLoggerBuilder::deny_targets`].
+    target_lists: filter::TargetLists,
+    /// See [`WinDebugLoggerBuilder::only_threads`].
+    thread_filter: thread_filter::ThreadFilterState,
+    /// The configuration of formatting and sinks, which can be replaced by
+    /// `reconfigure`. Each line is formatted and written with a single
+    /// snapshot. `None` for the default configuration.
+    config: RwLock<Option<Arc<WinDebugLoggerBuilder>>>,
+    /// Serializes `reconfigure`.
+    reconfigure_lock: Mutex<()>,
+    duplicates: Option<dedup::DuplicateFilter>,
+    sampler: Option<sample::Sampler>,
+    /// Lines filtered out by the level filter, retained for
+    /// `debug_on_error`.
+    retained: Option<ring::RingBuffer<(Level, Line)>>,
+    /// The lines written to `Sinks::RING_BUFFER`.
+    history: Option<ring::RingBuffer<String>>,
+    counters: stats::Counters,
+    /// The time when the logger was installed.
+    init_time: OnceLock<Instant>,
+    /// Generated when the logger is installed or first used.
+    session_id: OnceLock<String>,
+    coalesce: Option<coalesce::Coalescer>,
+    heartbeat: Option<heartbeat::Heartbeat>,
+    /// The code page for `OutputDebugStringA`, or `None` to use
+    /// `OutputDebugStringW`.
+    code_page: Option<u32>,
+    /// See [`WinDebugLoggerBuilder::name`].
+    name: Cow<'static, str>,
 }
 
-impl log::Log for WinDebugLogger {
-    fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level() <= self.level
+/// Specifies how the level of a record is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum LevelStyle {
+    /// The level name padded to five characters, e.g., `WARN `. This is the
+    /// default.
+    #[default]
+    Label,
+    /// A single letter in angle brackets, e.g., `<W>`. These markers never
+    /// change and are trivial to match in DebugView's highlight filters.
+    Bracketed,
+}
+
+/// Specifies the layout of lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum OutputFormat {
+    /// The human-readable layout, e.g.,
+    /// `01/02/2020 12:34:56 WARN  [app] message`. This is the default.
+    #[default]
+    Text,
+    /// Space-separated `key=value` pairs ([logfmt]), e.g.,
+    /// `ts="01/02/2020 12:34:56" level=warn target=app msg="disk is full"`.
+    /// Values are quoted if they contain spaces or special characters.
+    ///
+    /// [`level_style`], [`level_symbol`], [`split_level_tag`], and
+    /// [`elide_repeated_targets`] have no effect.
+    ///
+    /// [logfmt]: https://brandur.org/logfmt
+    /// [`level_style`]: WinDebugLoggerBuilder::level_style
+    /// [`level_symbol`]: WinDebugLoggerBuilder::level_symbol
+    /// [`split_level_tag`]: WinDebugLoggerBuilder::split_level_tag
+    /// [`elide_repeated_targets`]: WinDebugLoggerBuilder::elide_repeated_targets
+    Logfmt,
+}
+
+impl fmt::Debug for WinDebugLogger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let config = self.config();
+        f.debug_struct("WinDebugLogger")
+            .field("level", &self.level())
+            .field("timestamp_format", &describe_timestamp(&config))
+            .field("routes", &config.routes)
+            // Sinks are not required to implement `Debug`
+            .field("sinks", &format_args!("[<{} sinks>]", config.sinks.len()))
+            .finish_non_exhaustive()
     }
+}
 
-    fn log(&self, record: &log::Record) {
-        if !self.enabled(record.metadata()) {
-            return;
-        }
+/// Describe the timestamp format used by `WinDebugLogger::timestamp`.
+fn describe_timestamp(config: &WinDebugLoggerBuilder) -> String {
+    if config.custom_timestamp.is_some() {
+        return "custom".to_owned();
+    }
 
-        // Silently ignore errors
-        let _ = log(record);
+    #[cfg(feature = "time")]
+    if config.timestamp_format.is_some() {
+        return "format description".to_owned();
     }
 
-    fn flush(&self) {}
+    if cfg!(any(not(feature = "timestamps"), feature = "no-timestamps")) {
+        "none".to_owned()
+    } else {
+        format!(
+            "{}, locale {:#06x}",
+            if config.utc { "UTC" } else { "local time" },
+            config.locale
+        )
+    }
 }
 
-fn log(record: &log::Record) -> Option<()> {
-    let target = if record.target().len() > 0 {
-        record.target()
-    } else {
-        record.module_path().unwrap_or_default()
-    };
+/// The signature of a custom formatter. See
+/// [`WinDebugLoggerBuilder::custom_formatter`].
+pub type FormatFn = dyn Fn(&log::Record, &mut dyn fmt::Write) -> fmt::Result + Send + Sync;
 
-    // Everything except the timestamp
-    let body = format!("{:<5} [{}] {}", record.level(), target, record.args());
-    let body = codecvt::str_to_c_wstr(&body)?;
+#[derive(Clone)]
+pub(crate) struct CustomFormatter(Arc<FormatFn>);
 
-    // The timestamp is rendered using `GetTimeFormatW`
-    let system_time = unsafe {
-        let mut out = MaybeUninit::uninit();
-        sysinfoapi::GetSystemTime(out.as_mut_ptr());
-        out.assume_init()
-    };
+impl CustomFormatter {
+    pub(crate) fn new(formatter: Box<FormatFn>) -> Self {
+        Self(formatter.into())
+    }
+}
+
+impl fmt::Debug for CustomFormatter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomFormatter(..)")
+    }
+}
 
-    const MAX_LEN: usize = 40;
+/// The signature of a custom timestamp function. See
+/// [`WinDebugLoggerBuilder::timestamp_fn`].
+pub type TimestampFn = dyn Fn(&mut String) + Send + Sync;
 
-    let (date_str, date_str_len) = unsafe {
-        // This is safe because `[MaybeUninit<u16>; MAX_LEN]` has no portion
-        // that requires initialization
-        let mut date_str_buf: [MaybeUninit<u16>; MAX_LEN] =
-            transmute(MaybeUninit::<[u16; MAX_LEN]>::uninit());
+#[derive(Clone)]
+pub(crate) struct CustomTimestamp(Arc<TimestampFn>);
 
-        let result = datetimeapi::GetDateFormatW(
-            winnt::LOCALE_INVARIANT,
-            0, // no flags
-            &system_time,
-            null(),
-            date_str_buf[0].as_mut_ptr(),
-            MAX_LEN as _,
-        );
-        if result == 0 {
-            return None;
+impl CustomTimestamp {
+    pub(crate) fn new(timestamp_fn: impl Fn(&mut String) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(timestamp_fn))
+    }
+}
+
+impl fmt::Debug for CustomTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomTimestamp(..)")
+    }
+}
+
+/// The state of the initial period during which a different level filter is
+/// in effect. See [`WinDebugLoggerBuilder::trace_window`].
+#[derive(Debug)]
+struct TraceWindow {
+    level: AtomicUsize,
+    duration: Duration,
+    end: Instant,
+    /// `true` until the first record after `end` closes the window.
+    open: AtomicBool,
+}
+
+impl WinDebugLogger {
+    /// Construct a logger with the specified level filter and the default
+    /// configuration.
+    ///
+    /// This is a `const fn`, so the logger can be placed in a `static` item
+    /// (see [`init_with_level_static!`]).
+    pub const fn new(level: Level) -> Self {
+        Self {
+            level: AtomicUsize::new(level as usize),
+            trace_window: None,
+            enable_event: None,
+            shut_down: AtomicBool::new(false),
+            targets: filter::TargetLevels::empty(),
+            target_lists: filter::TargetLists::empty(),
+            thread_filter: thread_filter::ThreadFilterState::empty(),
+            config: RwLock::new(None),
+            reconfigure_lock: Mutex::new(()),
+            duplicates: None,
+            sampler: None,
+            retained: None,
+            history: None,
+            counters: stats::Counters::new(),
+            init_time: OnceLock::new(),
+            session_id: OnceLock::new(),
+            coalesce: None,
+            heartbeat: None,
+            code_page: None,
+            name: Cow::Borrowed(""),
         }
-        (date_str_buf, (result - 1).try_into().ok()?)
-    };
+    }
+
+    /// Formerly used by `init_with_level_static!`. Kept for compatibility
+    /// with code expanded from older versions of the macro; will be removed
+    /// in the next major version. Use [`new`](Self::new) instead.
+    #[doc(hidden)]
+    pub const fn __new(level: Level) -> Self {
+        Self::new(level)
+    }
+
+    /// Install the logger as the global logger and return a reference to it,
+    /// which lives for the rest of the program.
+    ///
+    /// ```
+    /// use windebug_logger::WinDebugLogger;
+    ///
+    /// # fn main() {
+    /// let logger = WinDebugLogger::new(log::Level::Debug).install().unwrap();
+    /// logger.set_level(log::LevelFilter::Info);
+    /// # }
+    /// ```
+    ///
+    /// The logger is also returned by [`global`] afterwards. On failure, the
+    /// logger is shut down and dropped.
+    pub fn install(self) -> Result<&'static WinDebugLogger, SetLoggerError> {
+        install_boxed(Box::new(self))
+    }
+
+    /// Construct a logger with the configuration of `builder`.
+    ///
+    /// Unlike [`WinDebugLoggerBuilder::init`], this does not install the
+    /// logger.
+    pub fn from_builder(builder: &WinDebugLoggerBuilder) -> Self {
+        if builder.module_name {
+            // Look up the name now rather than while logging
+            sysinfo::own_module_name();
+        }
+        Self {
+            level: AtomicUsize::new(builder.level as usize),
+            trace_window: builder.trace_window.map(|duration| TraceWindow {
+                level: AtomicUsize::new(builder.trace_window_level as usize),
+                duration,
+                end: Instant::now() + duration,
+                open: AtomicBool::new(true),
+            }),
+            enable_event: builder
+                .enable_event
+                .as_deref()
+                .and_then(event::EnableEvent::open),
+            shut_down: AtomicBool::new(false),
+            targets: filter::TargetLevels::new(builder.target_filter.clone()),
+            target_lists: filter::TargetLists::new(
+                builder.allowed_targets.clone(),
+                builder.denied_targets.clone(),
+            ),
+            thread_filter: thread_filter::ThreadFilterState::new(builder.thread_filter.clone()),
+            config: RwLock::new(Some(Arc::new(builder.clone()))),
+            reconfigure_lock: Mutex::new(()),
+            duplicates: builder.suppress_duplicates.map(|window| {
+                dedup::DuplicateFilter::new(window, builder.suppress_duplicates_capacity)
+            }),
+            sampler: sample::Sampler::new(&builder.sampling),
+            retained: builder
+                .debug_on_error
+                .map(|capacity| ring::RingBuffer::new(builder.debug_on_error_level, capacity)),
+            history: builder
+                .ring_buffer
+                .map(|capacity| ring::RingBuffer::new(LevelFilter::Trace, capacity)),
+            counters: stats::Counters::new(),
+            init_time: OnceLock::new(),
+            session_id: OnceLock::new(),
+            coalesce: builder.coalesce.map(|(max_records, max_bytes, max_delay)| {
+                coalesce::Coalescer::new(
+                    max_records,
+                    max_bytes,
+                    max_delay,
+                    builder.flush_interval,
+                    chunk::Measure::new(builder.code_page),
+                    {
+                        let code_page = builder.code_page;
+                        Box::new(move |line| write_line_in(code_page, line))
+                    },
+                )
+            }),
+            heartbeat: builder.heartbeat.map(heartbeat::Heartbeat::new),
+            code_page: builder.code_page,
+            name: builder.name.clone(),
+        }
+    }
+
+    /// Replace the configuration of formatting and sinks at runtime.
+    ///
+    /// `f` receives a builder holding the current configuration and returns
+    /// the new one, which is swapped in atomically. Each line is formatted
+    /// and written entirely with either the old or the new configuration.
+    ///
+    /// ```no_run
+    /// use log::LevelFilter;
+    /// use windebug_logger::{LevelStyle, Sinks};
+    ///
+    /// # fn main() {
+    /// let handle = windebug_logger::WinDebugLoggerBuilder::new()
+    ///     .file("app.log")
+    ///     .unwrap()
+    ///     .init()
+    ///     .unwrap();
+    ///
+    /// // Later, e.g., from a debug menu
+    /// handle.reconfigure(|builder| {
+    ///     builder
+    ///         .level(LevelFilter::Debug)
+    ///         .level_style(LevelStyle::Bracketed)
+    ///         // Stop writing to the file, but keep it open
+    ///         .route(LevelFilter::Off, Sinks::FILE)
+    /// });
+    /// # }
+    /// ```
+    ///
+    /// The following settings take effect: the filters ([`level`],
+    /// [`trace_window_level`] if a trace window was configured,
+    /// [`target_level`], [`allow_targets`], [`deny_targets`], and
+    /// [`only_threads`]), the format options
+    /// ([`output_format`], [`level_style`], [`level_symbol`], [`utc`],
+    /// [`locale`], [`custom_formatter`], [`timestamp_fn`], [`split_level_tag`],
+    /// [`elide_repeated_targets`], [`with_default_target`], [`with_module_name`],
+    /// [`session_id`], [`with_max_message_len`], [`source_location`],
+    /// [`debugview_plus_plus`], [`span_indent`], [`frame_number`], and the
+    /// `time` feature's
+    /// `timestamp_format_description`), [`route`],
+    /// [`dry_run`], [`skip_when_unattended`], [`flush_on_error`], and the
+    /// attached sinks. The filters are swapped in together with the rest
+    /// of the configuration, and `f` receives them as currently in effect,
+    /// including the changes made by [`set_level`](Self::set_level) and
+    /// the like. The other settings are fixed at construction and ignored.
+    ///
+    /// Sinks are opened by the builder methods that attach them, such as
+    /// [`file`]. A sink kept by `f` stays open and is shared by both
+    /// configurations. A sink removed by `f` (see [`remove_sinks`]) is
+    /// flushed and closed once the lines being written with the old
+    /// configuration are finished.
+    ///
+    /// Concurrent calls are serialized. `f` must not call `reconfigure`.
+    ///
+    /// [`level`]: WinDebugLoggerBuilder::level
+    /// [`trace_window_level`]: WinDebugLoggerBuilder::trace_window_level
+    /// [`target_level`]: WinDebugLoggerBuilder::target_level
+    /// [`allow_targets`]: WinDebugLoggerBuilder::allow_targets
+    /// [`deny_targets`]: WinDebugLoggerBuilder::deny_targets
+    /// [`only_threads`]: WinDebugLoggerBuilder::only_threads
+    /// [`output_format`]: WinDebugLoggerBuilder::output_format
+    /// [`dry_run`]: WinDebugLoggerBuilder::dry_run
+    /// [`skip_when_unattended`]: WinDebugLoggerBuilder::skip_when_unattended
+    /// [`flush_on_error`]: WinDebugLoggerBuilder::flush_on_error
+    /// [`with_module_name`]: WinDebugLoggerBuilder::with_module_name
+    /// [`level_style`]: WinDebugLoggerBuilder::level_style
+    /// [`level_symbol`]: WinDebugLoggerBuilder::level_symbol
+    /// [`utc`]: WinDebugLoggerBuilder::utc
+    /// [`locale`]: WinDebugLoggerBuilder::locale
+    /// [`custom_formatter`]: WinDebugLoggerBuilder::custom_formatter
+    /// [`timestamp_fn`]: WinDebugLoggerBuilder::timestamp_fn
+    /// [`split_level_tag`]: WinDebugLoggerBuilder::split_level_tag
+    /// [`elide_repeated_targets`]: WinDebugLoggerBuilder::elide_repeated_targets
+    /// [`with_default_target`]: WinDebugLoggerBuilder::with_default_target
+    /// [`session_id`]: WinDebugLoggerBuilder::session_id
+    /// [`with_max_message_len`]: WinDebugLoggerBuilder::with_max_message_len
+    /// [`source_location`]: WinDebugLoggerBuilder::source_location
+    /// [`debugview_plus_plus`]: WinDebugLoggerBuilder::debugview_plus_plus
+    /// [`span_indent`]: WinDebugLoggerBuilder::span_indent
+    /// [`frame_number`]: WinDebugLoggerBuilder::frame_number
+    /// [`route`]: WinDebugLoggerBuilder::route
+    /// [`file`]: WinDebugLoggerBuilder::file
+    /// [`remove_sinks`]: WinDebugLoggerBuilder::remove_sinks
+    pub fn reconfigure(&self, f: impl FnOnce(WinDebugLoggerBuilder) -> WinDebugLoggerBuilder) {
+        let _guard = self
+            .reconfigure_lock
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
 
-    let (time_str, time_str_len) = unsafe {
-        // This is safe because `[MaybeUninit<u16>; MAX_LEN]` has no portion
-        // that requires initialization
-        let mut time_str_buf: [MaybeUninit<u16>; MAX_LEN] =
-            transmute(MaybeUninit::<[u16; MAX_LEN]>::uninit());
+        let mut builder = (*self.config()).clone();
+        builder.level = self.level();
+        if let Some(level) = self.trace_window_level() {
+            builder.trace_window_level = level;
+        }
+        builder.target_filter = self.targets.read().clone();
+        (builder.allowed_targets, builder.denied_targets) = self.target_lists.read().clone();
+        builder.thread_filter = self.thread_filter.get();
+        let builder = f(builder);
 
-        let result = datetimeapi::GetTimeFormatW(
-            winnt::LOCALE_INVARIANT,
-            0, // no flags
-            &system_time,
-            null(),
-            time_str_buf[0].as_mut_ptr(),
-            MAX_LEN as _,
+        // Replace the filters while holding the lock so that no line is
+        // formatted with the new configuration before they take effect
+        let mut config = self.config.write().unwrap_or_else(|e| e.into_inner());
+        self.level.store(builder.level as usize, Ordering::Relaxed);
+        if let Some(window) = &self.trace_window {
+            window
+                .level
+                .store(builder.trace_window_level as usize, Ordering::Relaxed);
+        }
+        self.targets.replace(builder.target_filter.clone());
+        self.target_lists.set(
+            builder.allowed_targets.clone(),
+            builder.denied_targets.clone(),
         );
-        if result == 0 {
-            return None;
+        self.thread_filter.set(builder.thread_filter.clone());
+        let old = config.replace(Arc::new(builder));
+        drop(config);
+
+        self.update_max_level();
+        // Drop the old configuration (and the sinks only it holds) outside
+        // the lock
+        drop(old);
+    }
+
+    /// Also write every line to `f` (as [`Sinks::CUSTOM`]), e.g., to capture
+    /// the output in a test. `f` receives the same lines as
+    /// [`DebugSink::write`].
+    ///
+    /// This is a shorthand for attaching [`FnSink`] by
+    /// [`WinDebugLoggerBuilder::sink`], for a logger that is not installed
+    /// yet.
+    ///
+    /// ```
+    /// use windebug_logger::WinDebugLogger;
+    ///
+    /// fn capture(line: &[u16]) {
+    ///     eprint!("{}", String::from_utf16_lossy(line));
+    /// }
+    ///
+    /// let mut logger = WinDebugLogger::default();
+    /// logger.add_sink_fn(capture);
+    /// ```
+    pub fn add_sink_fn(&mut self, f: fn(&[u16])) {
+        let config = self
+            .config
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(|| Arc::new(WinDebugLoggerBuilder::new()));
+        Arc::make_mut(config).sinks.push(sink::SinkEntry::new(
+            Sinks::CUSTOM,
+            FnSink(f).name(),
+            Arc::new(FnSink(f)),
+        ));
+    }
+
+    /// Get the current configuration of formatting and sinks.
+    fn config(&self) -> Arc<WinDebugLoggerBuilder> {
+        static DEFAULT: OnceLock<Arc<WinDebugLoggerBuilder>> = OnceLock::new();
+
+        let config = self.config.read().unwrap_or_else(|e| e.into_inner());
+        match &*config {
+            Some(config) => Arc::clone(config),
+            None => Arc::clone(DEFAULT.get_or_init(|| Arc::new(WinDebugLoggerBuilder::new()))),
         }
-        (time_str_buf, (result - 1).try_into().ok()?)
-    };
+    }
 
-    let _: usize = date_str_len;
-    let _: usize = time_str_len;
+    /// Get the random session ID generated for this process. See
+    /// [`WinDebugLoggerBuilder::session_id`].
+    pub fn session_id(&self) -> &str {
+        self.session_id.get_or_init(session::generate)
+    }
 
-    // Build the final output
-    let final_str = unsafe {
-        let mut out = MaybeUninit::<*mut u16>::uninit();
+    /// Get the name of the logger. See [`WinDebugLoggerBuilder::name`].
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 
-        let parts = [date_str[0].as_ptr(), time_str[0].as_ptr(), body.as_ptr()];
+    /// Override the level filter on the current thread until the returned
+    /// guard is dropped, e.g., to trace a single operation in detail.
+    ///
+    /// ```
+    /// use log::{debug, Level};
+    /// use windebug_logger::WinDebugLogger;
+    ///
+    /// # fn main() {
+    /// windebug_logger::init_with_level(Level::Info).unwrap();
+    ///
+    /// {
+    ///     let _guard = WinDebugLogger::enable_thread_override(Level::Trace);
+    ///     debug!("This message will be logged.");
+    /// }
+    /// debug!("This message will not be logged.");
+    /// # }
+    /// ```
+    ///
+    /// The override applies to every logger of this crate and takes
+    /// precedence over the level filter and the trace window. Overrides can
+    /// be nested; dropping a guard restores the override that was in effect
+    /// when it was created (or none). While any override is active, `log`'s
+    /// global filter is relaxed accordingly, so the records of the other
+    /// threads take a slightly slower path to be filtered out.
+    pub fn enable_thread_override(level: Level) -> ThreadLevelGuard {
+        ThreadLevelGuard::new(level)
+    }
 
-        let result = winbase::FormatMessageW(
-            winbase::FORMAT_MESSAGE_ALLOCATE_BUFFER		// allocate buffer using `LocalAlloc`
-                | winbase::FORMAT_MESSAGE_FROM_STRING	// use a given format string
-                | winbase::FORMAT_MESSAGE_ARGUMENT_ARRAY, // arguments are in an array, not `va_list`
-            wchar::wch_c!("%1 %2 %3\n").as_ptr() as _,
-            0, // message id - ignored
-            0, // language id - ignored
-            out.as_mut_ptr() as _,
-            1, // minmum number of output `WCHAR` elements
-            parts.as_ptr() as _,
-        );
+    /// Get the level filter in effect after the trace window (if any).
+    pub fn level(&self) -> LevelFilter {
+        level_filter_from_usize(self.level.load(Ordering::Relaxed))
+    }
+
+    /// Set the level filter in effect after the trace window (if any).
+    pub fn set_level(&self, level: LevelFilter) {
+        self.level.store(level as usize, Ordering::Relaxed);
+        self.update_max_level();
+    }
+
+    /// Set the level filter of `target` and its descendants. See
+    /// [`WinDebugLoggerBuilder::target_level`].
+    pub fn set_target_level(&self, target: &str, level: LevelFilter) {
+        self.targets.insert(target.to_owned(), level);
+        self.update_max_level();
+    }
+
+    /// Get the level filter in effect during the trace window. Returns `None`
+    /// if the logger was configured without a trace window.
+    pub fn trace_window_level(&self) -> Option<LevelFilter> {
+        let window = self.trace_window.as_ref()?;
+        Some(level_filter_from_usize(
+            window.level.load(Ordering::Relaxed),
+        ))
+    }
 
-        if result == 0 {
-            return None;
+    /// Set the level filter in effect during the trace window. Does nothing
+    /// if the logger was configured without a trace window.
+    pub fn set_trace_window_level(&self, level: LevelFilter) {
+        if let Some(window) = &self.trace_window {
+            window.level.store(level as usize, Ordering::Relaxed);
+            self.update_max_level();
         }
+    }
 
-        out.assume_init()
-    };
+    /// Replace the thread filter, or remove it if `None`. See
+    /// [`WinDebugLoggerBuilder::only_threads`].
+    pub fn set_thread_filter(&self, filter: Option<ThreadFilter>) {
+        self.thread_filter.set(filter);
+    }
 
-    // Write the output
-    unsafe {
-        debugapi::OutputDebugStringW(final_str);
+    /// Get the level filter that `log`'s global filter should be set to.
+    fn max_level(&self) -> LevelFilter {
+        if self.shut_down.load(Ordering::Relaxed) {
+            return LevelFilter::Off;
+        }
+        let level = self.level();
+        let level = match &self.trace_window {
+            Some(window) if window.open.load(Ordering::Relaxed) => level.max(
+                level_filter_from_usize(window.level.load(Ordering::Relaxed)),
+            ),
+            _ => level,
+        };
+        let level = level.max(self.targets.max_level());
+        let level = level.max(thread_level::max_active());
+        match &self.retained {
+            Some(retained) => level.max(retained.level()),
+            None => level,
+        }
     }
 
-    unsafe {
-        winbase::LocalFree(final_str as _);
+    /// Recompute `log`'s global filter if this is the global logger.
+    /// Serialized so that a value computed before a concurrent change (e.g.,
+    /// by a `ThreadLevelGuard` on another thread) cannot overwrite the one
+    /// computed after it.
+    fn update_max_level(&self) {
+        // A logger that is not installed, e.g., one a dispatcher forwards
+        // to, must not change the filter of the one that is
+        match global() {
+            Some(global) if ptr::eq(global, self) => {}
+            _ => return,
+        }
+        static LOCK: Mutex<()> = Mutex::new(());
+        let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        log::set_max_level(self.max_level());
     }
-    Some(())
-}
 
-/// Initialize the global logger with a specific log level that is
-/// determined at compile time.
-///
-/// ```
-/// # use log::{warn, info};
-/// # fn main() {
-/// windebug_logger::init_with_level_static!(log::Level::Warn).unwrap();
-///
-/// warn!("This is an example message.");
-/// info!("This message will not be logged.");
-/// # }
-/// ```
-#[macro_export]
-macro_rules! init_with_level_static {
-    ($level:expr) => {{
-        let logger = &$crate::WinDebugLogger { level: $level };
-        match $crate::log::set_logger(logger) {
-            ::std::result::Result::Ok(()) => {
-                $crate::log::set_max_level(logger.level.to_level_filter());
-                Ok(())
+    /// Get the level filter currently in effect on the current thread,
+    /// closing the trace window if it has elapsed.
+    #[inline]
+    fn effective_level(&self) -> LevelFilter {
+        if let Some(level) = thread_level::current() {
+            return level;
+        }
+
+        if let Some(window) = &self.trace_window {
+            if window.open.load(Ordering::Relaxed) {
+                if Instant::now() < window.end {
+                    return level_filter_from_usize(window.level.load(Ordering::Relaxed));
+                }
+                self.close_trace_window(window);
             }
-            ::std::result::Result::Err(e) => ::std::result::Result::Err(e),
         }
-    }};
+
+        self.level()
+    }
+
+    /// Get the level filter in effect for `target` on the current thread.
+    #[inline]
+    fn level_for(&self, target: &str) -> LevelFilter {
+        if let Some(level) = thread_level::current() {
+            return level;
+        }
+        if let Some(level) = self.targets.level_for(target) {
+            return level;
+        }
+        self.effective_level()
+    }
+
+    #[cold]
+    fn close_trace_window(&self, window: &TraceWindow) {
+        // Only one thread gets to announce the transition
+        if window.open.swap(false, Ordering::Relaxed) {
+            self.update_max_level();
+
+            let _ = self.output(
+                &log::Record::builder()
+                    .args(format_args!(
+                        "Trace window of {:?} has elapsed; switching to level {}",
+                        window.duration,
+                        self.level()
+                    ))
+                    .level(Level::Info)
+                    .target("windebug_logger")
+                    .build(),
+            );
+        }
+    }
+
+    /// Check the master switches that disable the logger entirely.
+    #[inline]
+    fn is_active(&self) -> bool {
+        if self.shut_down.load(Ordering::Relaxed) {
+            return false;
+        }
+        match &self.enable_event {
+            Some(event) => event.is_signaled(),
+            None => true,
+        }
+    }
+
+    /// Check the target allow-list and deny-list.
+    #[inline]
+    fn is_allowed(&self, target: &str) -> bool {
+        self.target_lists.is_allowed(target)
+    }
+
+    /// Check if a record with `target` and `level` would be logged. See
+    /// [`enabled_for`](crate::enabled_for).
+    #[inline]
+    pub fn enabled_for(&self, target: &str, level: Level) -> bool {
+        log::Log::enabled(
+            self,
+            &log::Metadata::builder().target(target).level(level).build(),
+        )
+    }
+
+    /// Stop logging permanently. See [`LoggerHandle::shutdown`].
+    pub fn shutdown(&self) {
+        self.shut_down.store(true, Ordering::Relaxed);
+        if let Some(coalesce) = &self.coalesce {
+            coalesce.close();
+        }
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.close();
+        }
+        if let Some(event) = &self.enable_event {
+            event.close();
+        }
+
+        // Flush and release the sinks, closing them unless a builder still
+        // holds them
+        let mut config = self.config.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(config) = &mut *config {
+            for entry in &config.sinks {
+                let _ = entry.sink.flush();
+            }
+            Arc::make_mut(config).sinks.clear();
+        }
+    }
 }
 
-/// Initialize the global logger with a specific log level.
+/// Build a copy of `record` with the message replaced by `args`.
+fn with_args<'a>(record: &'a log::Record<'a>, args: fmt::Arguments<'a>) -> log::Record<'a> {
+    let mut builder = log::Record::builder();
+    builder
+        .args(args)
+        .metadata(record.metadata().clone())
+        .module_path(record.module_path())
+        .file(record.file())
+        .line(record.line());
+    #[cfg(feature = "kv")]
+    builder.key_values(record.key_values());
+    builder.build()
+}
+
+fn level_filter_from_usize(x: usize) -> LevelFilter {
+    match x {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+// A panic can unwind through `WinDebugLogger`'s methods (e.g., from a
+// `Display` implementation invoked while formatting a message), but this never
+// leaves the logger in an inconsistent state: its state is made of atomics
+// that are updated individually, and any lock it holds only protects
+// bookkeeping data that remains valid at every point where a panic can occur.
+// These implementations keep the logger usable from `catch_unwind` even when
+// fields that are not automatically `RefUnwindSafe` are added.
+impl UnwindSafe for WinDebugLogger {}
+impl RefUnwindSafe for WinDebugLogger {}
+
+impl Default for WinDebugLogger {
+    /// Construct a logger with the level filter `Trace` and the default
+    /// configuration, as [`WinDebugLoggerBuilder::new`] does.
+    fn default() -> Self {
+        Self::new(Level::Trace)
+    }
+}
+
+impl log::Log for WinDebugLogger {
+    #[inline(always)]
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.is_active()
+            && self.is_allowed(metadata.target())
+            && self.thread_filter.allows_current()
+            && (metadata.level() <= self.level_for(metadata.target())
+                || matches!(&self.retained, Some(r) if metadata.level() <= r.level()))
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.log_record(record, None);
+    }
+
+    fn flush(&self) {
+        if self.shut_down.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(coalesce) = &self.coalesce {
+            coalesce.flush();
+        }
+        for entry in &self.config().sinks {
+            let _ = entry.sink.flush();
+        }
+    }
+}
+
+impl WinDebugLogger {
+    fn stats_to_string(&self) -> String {
+        let counters = &self.counters;
+        let load = |counter: &std::sync::atomic::AtomicU64| counter.load(Ordering::Relaxed);
+        let mut out = String::from("windebug_logger statistics:");
+
+        if let Some(init_time) = self.init_time.get() {
+            let _ = write!(out, "\n  uptime: {:?}", init_time.elapsed());
+        }
+
+        let _ = write!(out, "\n  logged:");
+        for &level in &[
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ] {
+            let _ = write!(out, " {}={}", level, load(counters.logged(level)));
+        }
+
+        let _ = write!(
+            out,
+            "\n  dropped: duplicates={} write-failures={} threads={} sampled={}",
+            load(&counters.suppressed_duplicates),
+            load(&counters.write_failures),
+            load(&counters.thread_filtered),
+            load(&counters.sampled_out),
+        );
+
+        #[cfg(feature = "stats")]
+        {
+            let stats = stats::stats();
+            let _ = write!(
+                out,
+                "\n  debugger calls: {} ({:?} in total, {:?} at most)",
+                stats.call_count,
+                Duration::from_nanos(stats.total_ns),
+                Duration::from_nanos(stats.max_ns)
+            );
+        }
+
+        // Unlike `effective_level`, this leaves an elapsed trace window for
+        // the next record to close
+        let window_level = (self.trace_window.as_ref())
+            .filter(|window| window.open.load(Ordering::Relaxed) && Instant::now() < window.end)
+            .map(|window| level_filter_from_usize(window.level.load(Ordering::Relaxed)));
+        let level = thread_level::current()
+            .or(window_level)
+            .unwrap_or_else(|| self.level());
+        let _ = write!(out, "\n  effective level: {}", level);
+        if window_level.is_some() {
+            let _ = write!(out, " (trace window; {} afterwards)", self.level());
+        }
+        if let Some(event) = &self.enable_event {
+            let state = if event.is_signaled() { "on" } else { "off" };
+            let _ = write!(out, "\n  enable event: {}", state);
+        }
+        if self.shut_down.load(Ordering::Relaxed) {
+            let _ = write!(out, "\n  shut down");
+        }
+
+        out
+    }
+}
+
+/// A handle to the installed logger, used to change its configuration at
+/// runtime and to tear it down at exit.
 ///
-/// ```
-/// # use log::{warn, info};
-/// # fn main() {
-/// windebug_logger::init_with_level(log::Level::Warn).unwrap();
+/// The `log` crate requires the global logger to be `'static`, so the
+/// initialization functions leak the logger once it's installed and return
+/// this handle instead of the logger. The leak is a single allocation per
+/// process, which the operating system reclaims at exit, and it is what
+/// makes handing out `&'static` references sound: the logger is never
+/// freed while the `log` crate or a handle may still reach it. Because the
+/// memory is never freed, deterministic teardown is done by
+/// [`shutdown`](Self::shutdown), which releases the threads, buffers, and
+/// handles held by the logger.
 ///
-/// warn!("This is an example message.");
-/// info!("This message will not be logged.");
+/// ```no_run
+/// # fn main() -> Result<(), windebug_logger::Error> {
+/// let handle = windebug_logger::WinDebugLoggerBuilder::new()
+///     .file("app.log")?
+///     .init()?;
+///
+/// // ...
+///
+/// // At the end of `main`
+/// handle.shutdown();
+/// # Ok(())
 /// # }
 /// ```
-pub fn init_with_level(level: Level) -> Result<(), SetLoggerError> {
-    let logger = WinDebugLogger { level };
-    match log::set_boxed_logger(Box::new(logger)) {
-        Ok(()) => {
-            log::set_max_level(level.to_level_filter());
-            Ok(())
+#[derive(Debug, Clone, Copy)]
+pub struct LoggerHandle {
+    logger: &'static WinDebugLogger,
+}
+
+impl LoggerHandle {
+    /// Get the level filter in effect after the trace window (if any).
+    pub fn level(&self) -> LevelFilter {
+        self.logger.level()
+    }
+
+    /// Set the level filter in effect after the trace window (if any).
+    pub fn set_level(&self, level: LevelFilter) {
+        self.logger.set_level(level);
+    }
+
+    /// Set the level filter of `target` and its descendants. See
+    /// [`WinDebugLoggerBuilder::target_level`].
+    pub fn set_target_level(&self, target: &str, level: LevelFilter) {
+        self.logger.set_target_level(target, level);
+    }
+
+    /// Get the level filter in effect during the trace window. Returns `None`
+    /// if the logger was configured without a trace window.
+    pub fn trace_window_level(&self) -> Option<LevelFilter> {
+        self.logger.trace_window_level()
+    }
+
+    /// Set the level filter in effect during the trace window. Does nothing
+    /// if the logger was configured without a trace window.
+    pub fn set_trace_window_level(&self, level: LevelFilter) {
+        self.logger.set_trace_window_level(level);
+    }
+
+    /// Replace the thread filter, or remove it if `None`. See
+    /// [`WinDebugLoggerBuilder::only_threads`].
+    pub fn set_thread_filter(&self, filter: Option<ThreadFilter>) {
+        self.logger.set_thread_filter(filter);
+    }
+
+    /// Write the lines buffered by the logger and its sinks. This is what
+    /// [`log::logger().flush()`](log::Log::flush) does for this logger.
+    pub fn flush(&self) {
+        log::Log::flush(self.logger);
+    }
+
+    /// Stop logging permanently and release the resources held by the
+    /// logger: the buffered lines are written, the threads started by
+    /// [`WinDebugLoggerBuilder::flush_interval`] and
+    /// [`WinDebugLoggerBuilder::heartbeat`] are joined, the sinks are
+    /// flushed and closed, and the handle of the event object specified by
+    /// [`WinDebugLoggerBuilder::enable_event`] is closed.
+    ///
+    /// The logger remains installed but discards all records afterwards.
+    /// Records being logged by other threads at the same time may still be
+    /// written. See also [`prepare_for_unload`].
+    pub fn shutdown(&self) {
+        self.logger.shutdown();
+    }
+
+    /// Get the random session ID generated for this process. See
+    /// [`WinDebugLoggerBuilder::session_id`].
+    pub fn session_id(&self) -> &'static str {
+        self.logger.session_id()
+    }
+
+    /// Get the name of each sink and whether its last write succeeded. See
+    /// [`sink_status`].
+    pub fn sink_status(&self) -> Vec<(String, bool)> {
+        self.logger.sink_status()
+    }
+
+    /// Replace the configuration of formatting and sinks. See
+    /// [`WinDebugLogger::reconfigure`].
+    pub fn reconfigure(&self, f: impl FnOnce(WinDebugLoggerBuilder) -> WinDebugLoggerBuilder) {
+        self.logger.reconfigure(f);
+    }
+
+    /// Get the lines retained by [`WinDebugLoggerBuilder::ring_buffer`],
+    /// oldest first. Returns an empty `Vec` if the ring buffer is not
+    /// enabled.
+    pub fn history(&self) -> Vec<String> {
+        match &self.logger.history {
+            Some(history) => history.snapshot(),
+            None => Vec::new(),
         }
-        Err(e) => Err(e),
     }
 }
 
-/// Initializes the global logger with a log level set to `LogLevel::Trace`.
-///
-/// ```
-/// # use log::warn;
-/// # fn main() {
-/// windebug_logger::init().unwrap();
-/// warn!("This is an example message.");
-/// # }
-/// ```
-pub fn init() -> Result<(), SetLoggerError> {
-    init_with_level_static!(Level::Trace)
+impl WinDebugLogger {
+    /// Process a record. `wide`, if given, replaces the message of `record`
+    /// in the output.
+    ///
+    /// Only the filters are checked here. Most records are usually filtered
+    /// out, so everything else is moved to the out-of-line `log_cold` to keep
+    /// this path short.
+    #[inline]
+    fn log_record(&self, record: &log::Record, wide: Option<&[u16]>) {
+        if !self.is_active() || !self.is_allowed(record.target()) {
+            return;
+        }
+        let level = self.level_for(record.target());
+        if record.level() > level && self.retained.is_none() {
+            return;
+        }
+        if !self.thread_filter.allows_current() {
+            stats::bump(&self.counters.thread_filtered);
+            return;
+        }
+        self.log_cold(record, wide, level);
+    }
+
+    /// Format and output a record that passed the filters of `log_record`.
+    /// `level` is the level filter `log_record` found for its target.
+    #[cold]
+    #[inline(never)]
+    fn log_cold(&self, record: &log::Record, wide: Option<&[u16]>, level: LevelFilter) {
+        if record.level() > level {
+            // Keep the record around in case an error follows
+            if let Some(retained) = &self.retained {
+                if record.level() <= retained.level() {
+                    if let Some(line) = self.format(record, wide) {
+                        retained.push((record.level(), line));
+                    }
+                }
+            }
+            return;
+        }
+
+        if record.level() == Level::Error {
+            if let Some(retained) = &self.retained {
+                let config = self.config();
+                for (level, line) in retained.drain() {
+                    self.write(&config, level, None, &line);
+                }
+                format::forget_last_target();
+            }
+        }
+
+        match self.sampler.as_ref().map(|s| s.sample(record.target())) {
+            Some(sample::Sample::Drop) => stats::bump(&self.counters.sampled_out),
+            Some(sample::Sample::Keep(rate)) => {
+                let suffix = format!(" (sampled 1/{})", rate);
+                let wide = wide.map(|wide| {
+                    let mut wide = wide.to_vec();
+                    wide.extend(suffix.encode_utf16());
+                    wide
+                });
+                self.log_unique(
+                    &with_args(record, format_args!("{}{}", record.args(), suffix)),
+                    wide.as_deref(),
+                );
+            }
+            _ => self.log_unique(record, wide),
+        }
+    }
+
+    /// Format and output a record that passed the filters, unless it is a
+    /// duplicate suppressed by `suppress_duplicates`.
+    fn log_unique(&self, record: &log::Record, wide: Option<&[u16]>) {
+        if let Some(duplicates) = &self.duplicates {
+            let mut expired = Vec::new();
+            let suppress = duplicates.filter(record, &mut expired);
+
+            for suppressed in expired {
+                let _ = self.output(
+                    &log::Record::builder()
+                        .args(format_args!(
+                            "[suppressed {} duplicate messages for {:?}]",
+                            suppressed.count, suppressed.message
+                        ))
+                        .level(suppressed.level)
+                        .target(&suppressed.target)
+                        .build(),
+                );
+            }
+
+            if suppress {
+                stats::bump(&self.counters.suppressed_duplicates);
+                return;
+            }
+        }
+
+        // Errors are only counted
+        if self.output_with(record, wide).is_some() {
+            stats::bump(self.counters.logged(record.level()));
+        } else {
+            stats::bump(&self.counters.write_failures);
+        }
+
+        if record.level() == Level::Error && self.config().flush_on_error {
+            log::Log::flush(self);
+        }
+    }
+
+    /// Log a message given as a UTF-16 string. See [`log_wide`].
+    pub fn log_wide(&self, level: Level, target: &str, message: &[u16]) {
+        self.log_record(
+            &log::Record::builder()
+                .args(format_args!("{}", codecvt::WideDisplay(message)))
+                .level(level)
+                .target(target)
+                .build(),
+            Some(message),
+        );
+    }
+
+    /// Log a message with the given timestamp in place of the current time.
+    /// See [`log_message_at`].
+    pub fn log_message_at(&self, timestamp: SystemTime, level: Level, target: &str, message: &str) {
+        format::with_timestamp(timestamp, || {
+            log::Log::log(
+                self,
+                &log::Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(level)
+                    .target(target)
+                    .build(),
+            );
+        });
+    }
+
+    /// Format and write a record, bypassing all filters.
+    fn output(&self, record: &log::Record) -> Option<()> {
+        self.output_with(record, None)
+    }
+
+    fn output_with(&self, record: &log::Record, wide: Option<&[u16]>) -> Option<()> {
+        let config = self.config();
+        let line = self.format_line(&config, record, wide, config.elide_repeated_targets)?;
+        if self.write(&config, record.level(), Some(record), &line) {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    /// Format a record into a null-terminated wide string. `wide`, if given,
+    /// is used in place of the message of `record`.
+    fn format(&self, record: &log::Record, wide: Option<&[u16]>) -> Option<Line> {
+        self.format_line(&self.config(), record, wide, false)
+    }
+
+    /// `format` with an option to replace the target with a placeholder if it
+    /// is identical to that of the previous line formatted with this option
+    /// by the current thread.
+    fn format_line(
+        &self,
+        config: &WinDebugLoggerBuilder,
+        record: &log::Record,
+        wide: Option<&[u16]>,
+        elide_target: bool,
+    ) -> Option<Line> {
+        let cx = format::Context {
+            session_id: config.session_id.then(|| self.session_id()),
+            name: &self.name,
+            elide_target,
+            level_tag: std::cell::Cell::new(None),
+            message_start: std::cell::Cell::new(0),
+        };
+        let mut out = String::with_capacity(config.initial_buffer_capacity);
+        let wide = match wide {
+            // The custom formatter and logfmt need the message as text
+            Some(wide)
+                if config.custom_formatter.is_some()
+                    || config.output_format != OutputFormat::Text =>
+            {
+                let args = format_args!("{}", codecvt::WideDisplay(wide));
+                format::render(&mut out, &with_args(record, args), config, &cx, true)?;
+                None
+            }
+            Some(wide) => {
+                format::render(&mut out, record, config, &cx, false)?;
+                Some(wide)
+            }
+            None => {
+                format::render(&mut out, record, config, &cx, true)?;
+                None
+            }
+        };
+
+        // A UTF-8 string never has fewer bytes than UTF-16 code units
+        let mut line = Vec::with_capacity(config.initial_buffer_capacity.max(out.len() + 1));
+        line.extend(out.encode_utf16());
+        // Convert the range of the level tag into UTF-16 code units
+        let level_tag =
+            cx.level_tag
+                .get()
+                .filter(|_| config.split_level_tag)
+                .map(|(start, end)| {
+                    let start16 = out[..start].encode_utf16().count();
+                    (start16, start16 + out[start..end].encode_utf16().count())
+                });
+        let message_start = out[..cx.message_start.get()].encode_utf16().count();
+        if let Some(wide) = wide {
+            out.clear();
+            format::render_location(&mut out, record, config);
+            line.reserve(wide.len() + out.len() + 2);
+            line.extend_from_slice(wide);
+            line.extend(out.encode_utf16());
+            line.push(b'\n' as u16);
+        }
+        if let Some(max_len) = config.max_message_len {
+            format::truncate(&mut line, message_start, max_len);
+        }
+        line.push(0);
+        Some(Line {
+            level_tag: level_tag.filter(|&(_, end)| end < line.len()),
+            text: line,
+        })
+    }
+}
+
+/// A line formatted by `WinDebugLogger::format`.
+#[derive(Debug)]
+struct Line {
+    /// The null-terminated text.
+    text: Vec<u16>,
+    /// The range of the level tag in `text` if it is to be written by its
+    /// own `OutputDebugStringW` call. See
+    /// [`WinDebugLoggerBuilder::split_level_tag`].
+    level_tag: Option<(usize, usize)>,
+}
+
+impl WinDebugLogger {
+    /// Write a line produced by `format` to the sinks `level` is routed to.
+    /// `record` is the record the line was formatted from, if at hand.
+    /// Returns `false` if any of the sinks failed.
+    fn write(
+        &self,
+        config: &WinDebugLoggerBuilder,
+        level: Level,
+        record: Option<&log::Record>,
+        line: &Line,
+    ) -> bool {
+        let route = config.routes.get(level);
+        if route.contains(Sinks::ODS)
+            && !config.dry_run
+            && !(config.skip_when_unattended && debug_listener_present() == ListenerKind::None)
+        {
+            self.write_ods(line);
+        }
+
+        if !route.intersects(!Sinks::ODS) {
+            return true;
+        }
+
+        // Other sinks receive the line without the null terminator
+        let line = &line.text[..line.text.len() - 1];
+
+        if route.contains(Sinks::RING_BUFFER) {
+            if let Some(history) = &self.history {
+                history.push(codecvt::WideDisplay(line).to_string());
+            }
+        }
+
+        let mut ok = true;
+        for entry in &config.sinks {
+            if route.contains(entry.kind) && entry.write(level, record, line).is_err() {
+                ok = false;
+            }
+        }
+        ok
+    }
+
+    /// Write a line produced by `format` to the debugger.
+    fn write_ods(&self, line: &Line) {
+        let measure = chunk::Measure::new(self.code_page);
+        let write_chunked = |line: &[u16], output: &dyn Fn(&[u16])| {
+            chunk::split(
+                line,
+                measure.max_chunk_len(),
+                measure,
+                session::token,
+                output,
+            )
+        };
+
+        let (start, end) = match line.level_tag {
+            Some(level_tag) => level_tag,
+            None => {
+                match &self.coalesce {
+                    Some(coalesce) => write_chunked(&line.text, &|part| coalesce.push(part)),
+                    None => write_chunked(&line.text, &|part| write_line_in(self.code_page, part)),
+                }
+                return;
+            }
+        };
+
+        // Write the text before the level tag, the level tag, and the text
+        // after it separately
+        let write = |segment: &[u16]| {
+            if segment.len() > 1 {
+                write_chunked(segment, &|part| write_line_in(self.code_page, part));
+            }
+        };
+        let text = &line.text;
+        write(&[&text[..start], &[0]].concat());
+        write(&[&text[start..end], &[0]].concat());
+        write(&text[end..]);
+    }
+}
+
+/// Write a null-terminated wide string to the debugger, converting it to
+/// `code_page` and using `OutputDebugStringA` if specified.
+fn write_line_in(code_page: Option<u32>, line: &[u16]) {
+    let code_page = match code_page {
+        Some(code_page) => code_page,
+        None => return write_line(line),
+    };
+    debug_assert_eq!(line.last(), Some(&0));
+    if let Some(bytes) = codecvt::wstr_to_multibyte(code_page, line) {
+        stats::time_ods(|| unsafe {
+            debugapi::OutputDebugStringA(bytes.as_ptr() as *const i8);
+        });
+    }
+}
+
+/// Write a null-terminated wide string to the debugger.
+fn write_line(line: &[u16]) {
+    debug_assert_eq!(line.last(), Some(&0));
+    stats::time_ods(|| unsafe {
+        debugapi::OutputDebugStringW(line.as_ptr());
+    });
+}
+
+/// Initialize the global logger with a specific log level that is
+/// determined at compile time.
+///
+/// ```
+/// # use log::{warn, info};
+/// # fn main() {
+/// windebug_logger::init_with_level_static!(log::Level::Warn).unwrap();
+///
+/// warn!("This is an example message.");
+/// info!("This message will not be logged.");
+/// # }
+/// ```
+///
+/// Unlike the other initialization functions, this places the logger in a
+/// `static` item of the concrete type [`WinDebugLogger`], which involves no
+/// heap allocation. [`WinDebugLogger`] is not generic over its formatter:
+/// [`WinDebugLoggerBuilder::custom_formatter`] and
+/// [`WinDebugLoggerBuilder::timestamp_fn`] are stored as optional trait
+/// objects, which are absent in this logger's configuration (unless added
+/// by [`WinDebugLogger::reconfigure`]), so formatting never calls through
+/// them. Note that the `log` crate's macros still reach the logger through
+/// `&dyn Log`.
+///
+/// With the `build-script` feature, this installs `BuildScriptLogger`
+/// instead, which makes the records visible in Cargo's output.
+#[cfg(not(feature = "build-script"))]
+#[macro_export]
+macro_rules! init_with_level_static {
+    ($level:expr) => {{
+        static LOGGER: $crate::WinDebugLogger = $crate::WinDebugLogger::new($level);
+        $crate::__install_static(&LOGGER)
+    }};
+}
+
+/// Initialize the global logger with [`BuildScriptLogger`], which writes
+/// records as `cargo:warning=` directives, with a specific log level that is
+/// determined at compile time.
+///
+/// ```no_run
+/// // build.rs
+/// # use log::warn;
+/// # fn main() {
+/// windebug_logger::init_with_level_static!(log::Level::Warn).unwrap();
+///
+/// warn!("This message is shown by `cargo build`.");
+/// # }
+/// ```
+#[cfg(feature = "build-script")]
+#[macro_export]
+macro_rules! init_with_level_static {
+    ($level:expr) => {{
+        static LOGGER: $crate::BuildScriptLogger = $crate::BuildScriptLogger::new($level);
+        $crate::__install_build_script(&LOGGER)
+    }};
+}
+
+/// Used by `init_with_level_static!`.
+#[doc(hidden)]
+pub fn __install_static(logger: &'static WinDebugLogger) -> Result<(), SetLoggerError> {
+    install(logger)
+}
+
+/// The first logger this crate attempted to install as the global logger.
+static LOGGER: OnceLock<&'static WinDebugLogger> = OnceLock::new();
+
+impl WinDebugLogger {
+    /// Log information about the environment the process is running in.
+    fn log_environment(&self) {
+        let unknown = || "unknown".to_owned();
+        let lines = [
+            (
+                "Current directory",
+                sysinfo::current_directory().unwrap_or_else(unknown),
+            ),
+            (
+                "Executable",
+                sysinfo::executable_path().unwrap_or_else(unknown),
+            ),
+            (
+                "OS version",
+                sysinfo::os_version()
+                    .map(|(major, minor, build)| format!("{}.{}.{}", major, minor, build))
+                    .unwrap_or_else(unknown),
+            ),
+        ];
+
+        for (name, value) in lines.iter() {
+            let _ = self.output(
+                &log::Record::builder()
+                    .args(format_args!("{}: {}", name, value))
+                    .level(Level::Info)
+                    .target("windebug_logger")
+                    .build(),
+            );
+        }
+    }
+
+    /// Log the line written by [`WinDebugLoggerBuilder::log_on_init`].
+    fn log_init_marker(&self, level: LevelFilter) {
+        let _ = self.output(
+            &log::Record::builder()
+                .args(format_args!(
+                    "windebug_logger {} initialized with level={:?}",
+                    env!("CARGO_PKG_VERSION"),
+                    level
+                ))
+                .level(Level::Info)
+                .target("windebug_logger")
+                .build(),
+        );
+    }
+
+    /// Get the name of each sink and whether its last write succeeded. See
+    /// [`sink_status`].
+    pub fn sink_status(&self) -> Vec<(String, bool)> {
+        // `OutputDebugStringW` and the ring buffer cannot fail
+        let mut status = vec![("debugger".to_owned(), true)];
+        if self.history.is_some() {
+            status.push(("ring buffer".to_owned(), true));
+        }
+        for entry in &self.config().sinks {
+            status.push((entry.name.clone(), entry.ok.load(Ordering::Relaxed)));
+        }
+        status
+    }
+
+    /// Get the kinds of the sinks attached to the logger.
+    fn sink_kinds(&self) -> Sinks {
+        let mut kinds = Sinks::ODS;
+        if self.history.is_some() {
+            kinds |= Sinks::RING_BUFFER;
+        }
+        for entry in &self.config().sinks {
+            kinds |= entry.kind;
+        }
+        kinds
+    }
+
+    /// Log a warning about the configuration, regardless of the level filter.
+    fn log_config_warning(&self, message: fmt::Arguments<'_>) {
+        let _ = self.output(
+            &log::Record::builder()
+                .args(message)
+                .level(Level::Warn)
+                .target("windebug_logger")
+                .build(),
+        );
+    }
+
+    /// Log the banner. See [`WinDebugLoggerBuilder::banner`].
+    fn log_banner(&self, app_version: Option<&str>) {
+        let _ = self.output(
+            &log::Record::builder()
+                .args(format_args!("{}", self.banner_to_string(app_version)))
+                .level(Level::Info)
+                .target("windebug_logger")
+                .build(),
+        );
+    }
+
+    fn banner_to_string(&self, app_version: Option<&str>) -> String {
+        let unknown = || "unknown".to_owned();
+        let mut out = String::from("Logging started");
+
+        // Dates the banner even if lines have no timestamps
+        #[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+        let _ = write!(out, "\n  time: {}", sysinfo::utc_now());
+
+        let _ = write!(
+            out,
+            "\n  executable: {}",
+            sysinfo::executable_path().unwrap_or_else(unknown)
+        );
+        let _ = write!(out, "\n  process id: {}", std::process::id());
+        let _ = write!(out, "\n  session id: {}", self.session_id());
+        if !self.name.is_empty() {
+            let _ = write!(out, "\n  logger name: {}", self.name);
+        }
+        let _ = write!(
+            out,
+            "\n  OS version: {}",
+            sysinfo::os_version()
+                .map(|(major, minor, build)| format!("{}.{}.{}", major, minor, build))
+                .unwrap_or_else(unknown)
+        );
+        let _ = write!(
+            out,
+            "\n  architecture: {} (OS: {})",
+            std::env::consts::ARCH,
+            sysinfo::native_architecture().unwrap_or("unknown")
+        );
+        let _ = write!(
+            out,
+            "\n  version: {} (windebug_logger {})",
+            app_version.unwrap_or("unknown"),
+            env!("CARGO_PKG_VERSION")
+        );
+
+        let _ = write!(out, "\n  level: {}", self.level());
+        if let Some(window) = &self.trace_window {
+            let _ = write!(
+                out,
+                " ({} for the first {:?})",
+                level_filter_from_usize(window.level.load(Ordering::Relaxed)),
+                window.duration
+            );
+        }
+        for (i, (target, level)) in self.targets.read().iter().enumerate() {
+            let sep = if i == 0 { "\n  targets: " } else { ", " };
+            let _ = write!(out, "{}{}={}", sep, target, level);
+        }
+        let lists = self.target_lists.read();
+        for (name, targets) in [("allowed", &lists.0), ("denied", &lists.1)] {
+            if let Some(targets) = targets {
+                let mut targets: Vec<&str> = targets.iter().map(String::as_str).collect();
+                targets.sort_unstable();
+                let _ = write!(out, "\n  {} targets: {}", name, targets.join(", "));
+            }
+        }
+        if let Some(filter) = self.thread_filter.get() {
+            let _ = write!(out, "\n  threads: {:?}", filter);
+        }
+        let config = self.config();
+        let _ = write!(
+            out,
+            "\n  format: {:?}, {:?}, {}",
+            config.output_format,
+            config.level_style,
+            if config.utc { "UTC" } else { "local time" }
+        );
+        if let Some(duplicates) = &self.duplicates {
+            let _ = write!(out, "\n  suppress duplicates: {:?}", duplicates.window());
+        }
+        if let Some(sampler) = &self.sampler {
+            for (i, (target, rate)) in sampler.rates().into_iter().enumerate() {
+                let sep = if i == 0 { "\n  sampling: " } else { ", " };
+                let _ = write!(out, "{}{}=1/{}", sep, target, rate);
+            }
+        }
+        if let Some(retained) = &self.retained {
+            let _ = write!(
+                out,
+                "\n  debug on error: {} records up to {}",
+                retained.capacity(),
+                retained.level()
+            );
+        }
+        if let Some(coalesce) = &self.coalesce {
+            let _ = write!(
+                out,
+                "\n  coalesce: {} records or {:?}",
+                coalesce.max_records(),
+                coalesce.max_delay()
+            );
+            if let Some(interval) = coalesce.flush_interval() {
+                let _ = write!(out, ", flushed every {:?}", interval);
+            }
+        }
+        if let Some(heartbeat) = &self.heartbeat {
+            let _ = write!(out, "\n  heartbeat: {:?}", heartbeat.interval());
+        }
+        if self.enable_event.is_some() {
+            let _ = write!(out, "\n  enable event: yes");
+        }
+        let _ = write!(out, "\n  output: {:?}", self.sink_kinds());
+
+        out
+    }
+}
+
+/// Held while a logger is being installed.
+///
+/// Without this, another thread could call `log::set_logger` between our
+/// `LOGGER.set` and `log::set_logger`, leaving `LOGGER` pointing at a logger
+/// that never became the global logger.
+static INSTALL_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock_install() -> std::sync::MutexGuard<'static, ()> {
+    INSTALL_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn install(logger: &'static WinDebugLogger) -> Result<(), SetLoggerError> {
+    let _guard = lock_install();
+
+    // Set `LOGGER` first so that `global()` never returns `None` while the
+    // logger is active
+    let _ = LOGGER.set(logger);
+    log::set_logger(logger)?;
+    // Only the logger that won sets `log`'s global level filter
+    activate(logger);
+    Ok(())
+}
+
+/// Like [`install`], but only leaks `logger` if it becomes the global logger.
+fn install_boxed(logger: Box<WinDebugLogger>) -> Result<&'static WinDebugLogger, SetLoggerError> {
+    let _guard = lock_install();
+
+    let logger = Box::into_raw(logger);
+    // Safety: `logger` is valid until it's reclaimed below, which only
+    // happens if `log` did not keep the reference
+    if let Err(e) = log::set_logger(unsafe { &*logger }) {
+        let logger = unsafe { Box::from_raw(logger) };
+        logger.shutdown();
+        return Err(e);
+    }
+    // Safety: `log` holds on to the logger for the rest of the program, so
+    // it's never reclaimed
+    let logger: &'static WinDebugLogger = unsafe { &*logger };
+
+    // `LOGGER` can only be set after `log::set_logger` here. `global()`
+    // returning `None` in the meantime is harmless because we still hold
+    // `INSTALL_LOCK` and `activate` hasn't run yet.
+    let _ = LOGGER.set(logger);
+    activate(logger);
+    Ok(logger)
+}
+
+/// Prepare a logger that has just become the global logger.
+fn activate(logger: &'static WinDebugLogger) {
+    let _ = logger.init_time.set(Instant::now());
+    logger.session_id();
+    logger.update_max_level();
+    if let Some(heartbeat) = &logger.heartbeat {
+        heartbeat.start(logger);
+    }
+}
+
+/// Get the logger installed by this crate, if any.
+///
+/// This returns `None` if the logger has not been initialized or if another
+/// logger implementation was installed instead.
+///
+/// ```
+/// # fn main() {
+/// windebug_logger::init().unwrap();
+///
+/// // Later, e.g., from a debug menu
+/// if let Some(logger) = windebug_logger::global() {
+///     logger.set_level(log::LevelFilter::Debug);
+/// }
+/// # }
+/// ```
+pub fn global() -> Option<&'static WinDebugLogger> {
+    #[cfg(any(test, feature = "test-util"))]
+    if let Some(logger) = test::current() {
+        return Some(logger);
+    }
+
+    let logger = *LOGGER.get()?;
+
+    // `LOGGER` is set even if `log::set_logger` fails because another logger
+    // is already installed
+    let global = log::logger() as *const dyn log::Log as *const ();
+    if global == logger as *const WinDebugLogger as *const () {
+        Some(logger)
+    } else {
+        None
+    }
+}
+
+/// Shut down the logger installed by this crate and make the logging macros
+/// skip it, in preparation for unloading the DLL containing this crate.
+///
+/// `log` provides no way to uninstall a logger, so the global logger keeps
+/// pointing at this crate's logger even after the DLL is unloaded. This
+/// function does the following to make that safe in practice:
+///
+///  - Sets `log`'s global level filter to [`LevelFilter::Off`] (and keeps it
+///    so), so the logging macros return before calling into the logger.
+///  - Makes the logger discard records and ignore `flush` in case it is
+///    still called, e.g., through [`log::logger`].
+///  - Does everything [`LoggerHandle::shutdown`] does: the threads started by
+///    [`WinDebugLoggerBuilder::flush_interval`] and
+///    [`WinDebugLoggerBuilder::heartbeat`] are joined, and the sinks
+///    (files, pipes, event logs) and the event handle are closed.
+///
+/// Call this from the DLL's own shutdown routine before the host calls
+/// `FreeLibrary`, *not* from `DllMain`: joining a thread while holding the
+/// loader lock deadlocks. Components that share the `log` crate instance
+/// with the DLL must not call [`log::logger`] directly after the DLL is
+/// unloaded. This function does nothing (apart from resetting the level
+/// filter) if no logger was installed by this crate, and calling it more
+/// than once is harmless.
+///
+/// ```no_run
+/// # fn main() {
+/// windebug_logger::init().unwrap();
+///
+/// // In the plugin's shutdown routine
+/// windebug_logger::prepare_for_unload();
+/// log::info!("this is not logged");
+/// # }
+/// ```
+pub fn prepare_for_unload() {
+    if let Some(logger) = global() {
+        logger.shutdown();
+    }
+    log::set_max_level(LevelFilter::Off);
+}
+
+/// Initialize the global logger with a specific log level.
+///
+/// ```
+/// # use log::{warn, info};
+/// # fn main() {
+/// windebug_logger::init_with_level(log::Level::Warn).unwrap();
+///
+/// warn!("This is an example message.");
+/// info!("This message will not be logged.");
+/// # }
+/// ```
+///
+/// This is equivalent to `WinDebugLogger::new(level).install()` (see
+/// [`WinDebugLogger::install`]), which is preferred in new code. This
+/// function may be removed in a future major version.
+///
+/// # Concurrency
+///
+/// This function, like the other initialization functions (including
+/// [`init_with_level_static!`]), may be called from multiple threads at the
+/// same time. Exactly one call succeeds and the others return `Err`; the
+/// installations are serialized internally. Only the call that succeeds
+/// sets `log`'s global level filter, so it always reflects the
+/// configuration of the installed logger.
+///
+/// Once a call has returned `Ok`, [`global`] returns the installed logger
+/// on every thread, and the logging macros on every thread use it: both
+/// `log` and this crate publish the logger with release-acquire
+/// synchronization, so a thread that sees the logger also sees it fully
+/// constructed. However, records logged by other threads *while* the
+/// logger is being installed might be discarded, because `log`'s global
+/// level filter is raised only after the logger is installed.
+pub fn init_with_level(level: Level) -> Result<(), SetLoggerError> {
+    WinDebugLoggerBuilder::new()
+        .level(level.to_level_filter())
+        .install()
+        .map(drop)
+}
+
+/// Initialize the global logger with a level filter given as a string, e.g.,
+/// from a command-line flag. See [`parse_level`] for the accepted values.
+///
+/// ```
+/// # fn main() -> Result<(), windebug_logger::Error> {
+/// windebug_logger::init_with_level_str("warning")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn init_with_level_str(level: &str) -> Result<LoggerHandle, Error> {
+    WinDebugLoggerBuilder::new()
+        .level(parse_level(level)?)
+        .init()
+}
+
+/// Initialize the global logger with a specific log level, tolerating
+/// repeated initialization.
+///
+///  - If no logger is installed yet, this works like [`init_with_level`].
+///  - If a logger installed by this crate (through any of the initialization
+///    functions) is already in place, this updates its level filter to
+///    `level`, logs a warning about the repeated initialization, and returns
+///    `Ok(())`. The rest of the existing configuration is left untouched.
+///  - If a logger from some other crate is already installed, this returns
+///    `Err(SetLoggerError)` and changes nothing.
+///
+/// ```
+/// # use log::{warn, info};
+/// # fn main() {
+/// windebug_logger::init_idempotent(log::Level::Info).unwrap();
+/// windebug_logger::init_idempotent(log::Level::Warn).unwrap();
+///
+/// warn!("This is an example message.");
+/// info!("This message will not be logged.");
+/// # }
+/// ```
+pub fn init_idempotent(level: Level) -> Result<(), SetLoggerError> {
+    let update = |logger: &WinDebugLogger| {
+        logger.set_level(level.to_level_filter());
+        log::warn!(
+            target: "windebug_logger",
+            "The logger is already initialized; changed the level to {}",
+            level
+        );
+    };
+
+    let installed = {
+        // `global()` returns the logger as soon as `log::set_logger`
+        // succeeds; wait until it's activated so that `update` isn't undone
+        let _guard = lock_install();
+        global()
+    };
+    if let Some(logger) = installed {
+        update(logger);
+        return Ok(());
+    }
+
+    match init_with_level(level) {
+        Ok(()) => Ok(()),
+        Err(e) => match global() {
+            // Another thread won the race
+            Some(logger) => {
+                update(logger);
+                Ok(())
+            }
+            None => Err(e),
+        },
+    }
+}
+
+/// Get a human-readable summary of the statistics collected by the logger
+/// installed by this crate, or `None` if no such logger is installed.
+///
+/// The summary spans multiple lines and includes the number of records
+/// logged at each level, the number of records dropped for each cause, the
+/// time elapsed since initialization, and the level filter currently in
+/// effect.
+pub fn stats_to_string() -> Option<String> {
+    global().map(WinDebugLogger::stats_to_string)
+}
+
+/// Get the name of each sink attached to the logger installed by this crate
+/// and whether its last write succeeded, e.g., to show that a named pipe is
+/// disconnected. Returns an empty `Vec` if no such logger is installed.
+///
+/// The debugger (`OutputDebugStringW`) comes first, followed by the ring
+/// buffer (see [`WinDebugLoggerBuilder::ring_buffer`]), if any, and the other
+/// sinks in the order they were attached. A sink that has not been written
+/// to yet is reported as successful.
+///
+/// ```
+/// for (name, ok) in windebug_logger::sink_status() {
+///     println!("{}: {}", name, if ok { "OK" } else { "failing" });
+/// }
+/// ```
+pub fn sink_status() -> Vec<(String, bool)> {
+    global()
+        .map(WinDebugLogger::sink_status)
+        .unwrap_or_default()
+}
+
+/// Log the summary returned by [`stats_to_string`] as a single record.
+///
+/// The record is logged at `Info` level with the target `windebug_logger`,
+/// regardless of the level filter. This function can be called from any
+/// thread and does nothing if this crate's logger is not installed.
+///
+/// ```
+/// # fn main() {
+/// windebug_logger::init().unwrap();
+/// windebug_logger::log_stats();
+/// # }
+/// ```
+pub fn log_stats() {
+    if let Some(logger) = global() {
+        if !logger.is_active() {
+            return;
+        }
+        let _ = logger.output(
+            &log::Record::builder()
+                .args(format_args!("{}", logger.stats_to_string()))
+                .level(Level::Info)
+                .target("windebug_logger")
+                .build(),
+        );
+    }
+}
+
+/// Check if a record with `target` and `level` would be logged by the logger
+/// installed by this crate, applying the same filters as
+/// [`log::Log::enabled`].
+///
+/// Unlike [`log::log_enabled!`], which uses the calling module's path, this
+/// takes an arbitrary target, e.g., to skip expensive diagnostics for a
+/// subsystem before logging them. This function returns `false` if this
+/// crate's logger is not installed.
+///
+/// ```
+/// # fn main() {
+/// windebug_logger::init_with_level(log::Level::Info).unwrap();
+///
+/// assert!(windebug_logger::enabled_for("net", log::Level::Info));
+/// assert!(!windebug_logger::enabled_for("net", log::Level::Debug));
+/// # }
+/// ```
+pub fn enabled_for(target: &str, level: Level) -> bool {
+    global().is_some_and(|logger| logger.enabled_for(target, level))
+}
+
+/// Log a message given as a UTF-16 string through the logger installed by
+/// this crate.
+///
+/// The message is written as-is, without a round trip through UTF-8, so
+/// unpaired surrogates are preserved. The record is filtered in the same way
+/// as the records logged through the `log` crate's macros. Features that
+/// need the message as text (such as
+/// [`WinDebugLoggerBuilder::suppress_duplicates`] and
+/// [`WinDebugLoggerBuilder::custom_formatter`]) see unpaired surrogates
+/// replaced with U+FFFD.
+///
+/// This function does nothing if this crate's logger is not installed.
+///
+/// ```
+/// # fn main() {
+/// windebug_logger::init().unwrap();
+///
+/// let message: Vec<u16> = "Hello, world!".encode_utf16().collect();
+/// windebug_logger::log_wide(log::Level::Info, "app", &message);
+/// # }
+/// ```
+pub fn log_wide(level: Level, target: &str, message: &[u16]) {
+    if let Some(logger) = global() {
+        logger.log_wide(level, target, message);
+    }
+}
+
+/// Log a message given as an `OsStr` through the logger installed by this
+/// crate. This is equivalent to calling [`log_wide`] with the UTF-16 encoding
+/// of `message`.
+pub fn log_os(level: Level, target: &str, message: &OsStr) {
+    if let Some(logger) = global() {
+        let message: Vec<u16> = message.encode_wide().collect();
+        logger.log_wide(level, target, &message);
+    }
+}
+
+/// Log a message through the logger installed by this crate, rendering
+/// `timestamp` in place of the current time, e.g., to replay records read
+/// from a file or forwarded from another process.
+///
+/// The record is filtered and formatted in the same way as the records
+/// logged through the `log` crate's macros. Timestamps need not be in order.
+/// A timestamp that cannot be rendered (e.g., one before the year 1601)
+/// leaves the line without one. [`WinDebugLoggerBuilder::timestamp_fn`], if
+/// used, ignores `timestamp`.
+///
+/// This function does nothing if this crate's logger is not installed.
+///
+/// ```
+/// # fn main() {
+/// use std::time::{Duration, SystemTime};
+///
+/// windebug_logger::init().unwrap();
+///
+/// let timestamp = SystemTime::now() - Duration::from_secs(60);
+/// windebug_logger::log_message_at(timestamp, log::Level::Info, "app", "a minute ago");
+/// # }
+/// ```
+pub fn log_message_at(timestamp: SystemTime, level: Level, target: &str, message: &str) {
+    if let Some(logger) = global() {
+        logger.log_message_at(timestamp, level, target, message);
+    }
+}
+
+/// Write a string to the debugger using `OutputDebugStringW`, bypassing the
+/// logger entirely.
+///
+/// The string is written as-is; no timestamp or trailing newline is added.
+/// Nothing is written if the string contains a null character or cannot be
+/// converted.
+pub fn output_debug_string(s: &str) {
+    if s.contains('\0') {
+        return;
+    }
+    if let Some(s) = codecvt::str_to_c_wstr(s) {
+        write_line(&s);
+    }
+}
+
+/// Write a message prefixed with the source location to the debugger.
+///
+/// This is meant for quick debugging and works even if no logger is
+/// installed. It deliberately bypasses all configuration: the message is
+/// passed to [`output_debug_string`] regardless of the installed logger, its
+/// level filter, or its format options.
+///
+/// ```
+/// # fn main() {
+/// let x = 42;
+/// windebug_logger::dbgmsg!("x = {}", x); // src/main.rs:3: x = 42
+/// windebug_logger::dbgmsg!(tag: "NET", "connected"); // src/main.rs:4: [NET] connected
+/// # }
+/// ```
+#[macro_export]
+macro_rules! dbgmsg {
+    (tag: $tag:expr, $($arg:tt)+) => {
+        $crate::__dbgmsg(::std::option::Option::Some($tag), format_args!($($arg)+))
+    };
+    ($($arg:tt)+) => {
+        $crate::__dbgmsg(::std::option::Option::None, format_args!($($arg)+))
+    };
+}
+
+/// Used by `dbgmsg!`.
+#[doc(hidden)]
+#[track_caller]
+pub fn __dbgmsg(tag: Option<&str>, args: fmt::Arguments<'_>) {
+    output_debug_string(&format_dbgmsg(Location::caller(), tag, args));
+}
+
+fn format_dbgmsg(location: &Location<'_>, tag: Option<&str>, args: fmt::Arguments<'_>) -> String {
+    let mut out = format!("{}:{}: ", location.file(), location.line());
+    if let Some(tag) = tag {
+        let _ = write!(out, "[{}] ", tag);
+    }
+    let _ = writeln!(out, "{}", args);
+    out.replace('\0', "\\0")
+}
+
+/// Initializes the global logger with a log level set to `LogLevel::Trace`.
+///
+/// ```
+/// # use log::warn;
+/// # fn main() {
+/// windebug_logger::init().unwrap();
+/// warn!("This is an example message.");
+/// # }
+/// ```
+pub fn init() -> Result<(), SetLoggerError> {
+    init_with_level_static!(Level::Trace)
+}
+
+/// Initialize the global logger with the configuration read from the file at
+/// `path`, or with `default` if the file does not exist. Requires the `toml`
+/// or `json` feature.
+///
+/// This makes it possible to change the configuration in the field by
+/// placing a file such as `windebug_logger.toml` next to the executable and
+/// restarting it. See [`Config::from_file`] for the supported formats and
+/// [`Config`] for the fields.
+///
+/// ```toml
+/// level = "info"
+/// file = 'C:\ProgramData\App\app.log'
+///
+/// [targets]
+/// "app::net" = "trace"
+/// hyper = "warn"
+/// ```
+///
+/// ```no_run
+/// # fn main() -> Result<(), windebug_logger::Error> {
+/// let path = std::env::current_exe()?.with_file_name("windebug_logger.toml");
+/// windebug_logger::init_from_file(path, windebug_logger::Config::default())?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(any(feature = "toml", feature = "json"))]
+pub fn init_from_file(
+    path: impl AsRef<std::path::Path>,
+    default: Config,
+) -> Result<LoggerHandle, Error> {
+    let config = Config::from_file(path)?.unwrap_or(default);
+    WinDebugLoggerBuilder::from_config(config)?.init()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capture::CaptureSink;
+    use log::Log;
+    use std::panic::catch_unwind;
+
+    fn log_message(logger: &WinDebugLogger, message: &str) {
+        logger.log(
+            &log::Record::builder()
+                .args(format_args!("{}", message))
+                .level(Level::Info)
+                .target("windebug_logger::tests")
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_guaranteed_traits() {
+        fn assert_traits<
+            T: log::Log + fmt::Debug + Default + Send + Sync + UnwindSafe + RefUnwindSafe,
+        >() {
+        }
+        assert_traits::<WinDebugLogger>();
+    }
+
+    #[test]
+    fn test_log_inside_catch_unwind() {
+        let logger = WinDebugLogger::new(Level::Trace);
+
+        let result = catch_unwind(|| {
+            log_message(&logger, "This message precedes a panic.");
+            panic!("intentional panic");
+        });
+        assert!(result.is_err());
+
+        // The logger is still usable after the panic
+        log_message(&logger, "This message follows a panic.");
+    }
+
+    #[test]
+    fn test_const_logger_has_no_callbacks() {
+        let logger = WinDebugLogger::new(Level::Info);
+        let config = logger.config();
+        assert!(config.custom_formatter.is_none());
+        assert!(config.custom_timestamp.is_none());
+    }
+
+    #[test]
+    fn test_stats_to_string() {
+        let logger = WinDebugLogger::new(Level::Info);
+        log_message(&logger, "hello");
+        log_message(&logger, "hello");
+
+        let stats = logger.stats_to_string();
+        assert!(stats.contains(" INFO=2 "), "{}", stats);
+        assert!(stats.contains("effective level: INFO"), "{}", stats);
+    }
+
+    #[test]
+    fn test_stats_to_string_trace_window() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .level(LevelFilter::Warn)
+                .trace_window(Duration::ZERO)
+                .sink(sink),
+        );
+
+        // The elapsed window is not reported but stays open until a record
+        // closes it
+        let stats = logger.stats_to_string();
+        assert!(stats.ends_with("effective level: WARN"), "{}", stats);
+        assert!(captured.lines().is_empty());
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_debug_on_error() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .level(LevelFilter::Info)
+                .debug_on_error(4)
+                .debug_on_error_level(LevelFilter::Debug),
+        );
+        let retained = logger.retained.as_ref().unwrap();
+        let log_at = |level: Level| {
+            logger.log(
+                &log::Record::builder()
+                    .args(format_args!("{}", level))
+                    .level(level)
+                    .build(),
+            )
+        };
+
+        assert_eq!(logger.max_level(), LevelFilter::Debug);
+
+        log_at(Level::Trace);
+        log_at(Level::Debug);
+        log_at(Level::Info);
+        assert_eq!(retained.drain().len(), 1);
+
+        log_at(Level::Debug);
+        log_at(Level::Error);
+        assert!(retained.drain().is_empty());
+    }
+
+    #[test]
+    fn test_trace_window_transition() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .level(LevelFilter::Warn)
+                .trace_window(Duration::ZERO)
+                .sink(sink),
+        );
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+
+        // The first record closes the window and is filtered at `Warn`
+        log_message(&logger, "after the window");
+        log_message(&logger, "after the window");
+        assert_eq!(logger.max_level(), LevelFilter::Warn);
+
+        let lines = captured.records();
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        assert_eq!(lines[0].0, Level::Info);
+        assert!(
+            lines[0].1.contains("switching to level WARN"),
+            "{:?}",
+            lines
+        );
+    }
+
+    #[test]
+    fn test_trace_window_handle_levels() {
+        let (sink, captured) = CaptureSink::new();
+        let logger: &'static WinDebugLogger = Box::leak(Box::new(WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .level(LevelFilter::Warn)
+                .trace_window(Duration::from_secs(3600))
+                .trace_window_level(LevelFilter::Debug)
+                .sink(sink),
+        )));
+        let handle = LoggerHandle { logger };
+        let count = || captured.lines().len();
+
+        log_message(logger, "inside the window");
+        assert_eq!(count(), 1);
+
+        handle.set_trace_window_level(LevelFilter::Warn);
+        assert_eq!(handle.trace_window_level(), Some(LevelFilter::Warn));
+        log_message(logger, "inside the window");
+        assert_eq!(count(), 1);
+
+        // The level after the window is independent of the window's level
+        handle.set_level(LevelFilter::Error);
+        assert_eq!(handle.level(), LevelFilter::Error);
+        assert_eq!(handle.trace_window_level(), Some(LevelFilter::Warn));
+        assert_eq!(logger.max_level(), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn test_target_level() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .level(LevelFilter::Info)
+                .target_level("app::net", LevelFilter::Trace)
+                .target_level("hyper", LevelFilter::Error),
+        );
+        let enabled = |target: &str, level: Level| {
+            logger.enabled(&log::Metadata::builder().target(target).level(level).build())
+        };
+
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+        assert!(enabled("app::net", Level::Trace));
+        assert!(enabled("app::net::tcp", Level::Debug));
+        assert!(!enabled("app", Level::Debug));
+        assert!(enabled("app", Level::Info));
+        assert!(!enabled("hyper::client", Level::Warn));
+
+        let banner = logger.banner_to_string(None);
+        assert!(
+            banner.contains("\n  targets: app::net=TRACE, hyper=ERROR\n"),
+            "{}",
+            banner
+        );
+    }
+
+    #[test]
+    fn test_allow_targets() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .sink(sink)
+                .allow_targets(&["myapp", "mylib::db"]),
+        );
+        let enabled = |target: &str| {
+            logger.enabled(
+                &log::Metadata::builder()
+                    .target(target)
+                    .level(Level::Error)
+                    .build(),
+            )
+        };
+
+        assert!(enabled("myapp"));
+        assert!(enabled("myapp::ui"));
+        assert!(enabled("mylib::db"));
+        assert!(!enabled("mylib"));
+        assert!(!enabled("hyper"));
+
+        for target in &["myapp::ui", "hyper"] {
+            logger.log(
+                &log::Record::builder()
+                    .args(format_args!("hello"))
+                    .target(target)
+                    .build(),
+            );
+        }
+        assert_eq!(captured.lines().len(), 1);
+    }
+
+    #[test]
+    fn test_deny_targets() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .target_level("hyper", LevelFilter::Trace)
+                .deny_targets(&["hyper", "myapp::noisy"]),
+        );
+        assert!(!logger.enabled_for("hyper", Level::Error));
+        assert!(!logger.enabled_for("hyper::client", Level::Error));
+        assert!(!logger.enabled_for("myapp::noisy", Level::Error));
+        assert!(logger.enabled_for("myapp", Level::Error));
+        assert!(logger
+            .banner_to_string(None)
+            .contains("\n  denied targets: hyper, myapp::noisy\n"));
+    }
+
+    #[test]
+    fn test_thread_filter() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .level(LevelFilter::Info)
+                .ring_buffer(8)
+                .only_threads(ThreadFilter::name(|name| name == Some("ui"))),
+        );
+        let log_on = |name: &str| {
+            std::thread::scope(|s| {
+                std::thread::Builder::new()
+                    .name(name.to_owned())
+                    .spawn_scoped(s, || log_message(&logger, name))
+                    .unwrap()
+                    .join()
+                    .unwrap()
+            })
+        };
+        log_on("ui");
+        log_on("worker");
+        log_on("worker");
+        let history = logger.history.as_ref().unwrap().snapshot();
+        assert_eq!(history.len(), 1);
+        assert!(history[0].ends_with(" ui\n"), "{:?}", history);
+        let stats = logger.stats_to_string();
+        assert!(stats.contains(" threads=2"), "{}", stats);
+
+        logger.set_thread_filter(None);
+        log_on("worker");
+        assert_eq!(logger.history.as_ref().unwrap().snapshot().len(), 2);
+    }
+
+    #[test]
+    fn test_sampling() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .ring_buffer(8)
+                .with_sampling("windebug_logger", 2),
+        );
+        for i in 0..4 {
+            log_message(&logger, &i.to_string());
+        }
+        let history = logger.history.as_ref().unwrap().snapshot();
+        assert_eq!(history.len(), 2);
+        assert!(history[0].ends_with(" 0 (sampled 1/2)\n"), "{:?}", history);
+        assert!(history[1].ends_with(" 2 (sampled 1/2)\n"), "{:?}", history);
+        let stats = logger.stats_to_string();
+        assert!(stats.contains(" sampled=2"), "{}", stats);
+        assert!(logger
+            .banner_to_string(None)
+            .contains("\n  sampling: windebug_logger=1/2\n"));
+    }
+
+    #[test]
+    fn test_sampling_wide() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|_| {})
+                .with_sampling("app", 2)
+                .sink(sink),
+        );
+        // The unpaired surrogate is written as-is
+        logger.log_wide(Level::Info, "app", &[0x61, 0xd800]);
+        logger.log_wide(Level::Info, "app", &[0x62]);
+
+        let expected: Vec<u16> = "INFO  [app] a"
+            .encode_utf16()
+            .chain([0xd800])
+            .chain(" (sampled 1/2)\n".encode_utf16())
+            .collect();
+        assert_eq!(captured.wide_lines(), [expected]);
+    }
+
+    #[cfg(feature = "kv")]
+    #[test]
+    fn test_sampling_key_values() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|_| {})
+                .with_sampling("app", 2)
+                .sink(sink),
+        );
+        let key_values = [("user", log::kv::Value::from("alice"))];
+        logger.log(
+            &log::Record::builder()
+                .args(format_args!("logged in"))
+                .level(Level::Info)
+                .target("app")
+                .key_values(&key_values)
+                .build(),
+        );
+
+        let lines = captured.lines();
+        assert_eq!(lines, ["INFO  [app] logged in (sampled 1/2) user=alice\n"]);
+    }
+
+    #[test]
+    fn test_new_from_default() {
+        // The defaults match those of `new`
+        let from_default = WinDebugLoggerBuilder::new_from_default(Default::default());
+        assert_eq!(
+            format!("{:?}", from_default),
+            format!("{:?}", WinDebugLoggerBuilder::new())
+        );
+
+        let defaults = WinDebugLoggerDefaults {
+            level: LevelFilter::Info,
+            level_style: LevelStyle::Bracketed,
+            default_target: "main".to_owned(),
+            ..Default::default()
+        };
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new_from_default(defaults).timestamp_fn(|_| {}),
+        );
+        assert_eq!(logger.level(), LevelFilter::Info);
+        let line = logger
+            .format(
+                &log::Record::builder()
+                    .args(format_args!("hello"))
+                    .level(Level::Warn)
+                    .build(),
+                None,
+            )
+            .unwrap()
+            .text;
+        assert_eq!(line, wchar::wch_c!("<W> [main] hello\n"));
+    }
+
+    #[test]
+    fn test_banner_to_string() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .level(LevelFilter::Warn)
+                .suppress_duplicates(Duration::from_secs(1)),
+        );
+        let banner = logger.banner_to_string(Some("app 1.0"));
+        assert!(banner.starts_with("Logging started\n  "), "{}", banner);
+        #[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+        {
+            let time = banner
+                .lines()
+                .nth(1)
+                .unwrap()
+                .trim_start_matches("  time: ");
+            assert!(time.len() == 24 && time.ends_with('Z'), "{}", banner);
+        }
+        assert!(
+            banner.contains("\n  version: app 1.0 (windebug_logger "),
+            "{}",
+            banner
+        );
+        assert!(banner.contains("\n  level: WARN\n"), "{}", banner);
+        assert!(banner.contains("\n  suppress duplicates: 1s"), "{}", banner);
+    }
+
+    #[test]
+    fn test_max_message_len() {
+        let record = log::Record::builder()
+            .args(format_args!("hello, world"))
+            .level(Level::Info)
+            .target("app")
+            .build();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|out| out.push_str("now"))
+                .with_max_message_len(5),
+        );
+        let format = |wide: Option<&[u16]>| {
+            String::from_utf16(&logger.format(&record, wide).unwrap().text).unwrap()
+        };
+
+        // The prefix does not count toward the limit
+        let expected = "now INFO  [app] hello\u{2026}[truncated]\n\0";
+        assert_eq!(format(None), expected);
+        let wide: Vec<u16> = "hello, world".encode_utf16().collect();
+        assert_eq!(format(Some(&wide)), expected);
+        assert_eq!(
+            format::render_to_string(&record, &logger.config()).unwrap(),
+            expected.trim_end_matches('\0')
+        );
+    }
+
+    #[test]
+    fn test_initial_buffer_capacity() {
+        let record = log::Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Info)
+            .build();
+        let format = |builder: WinDebugLoggerBuilder| {
+            WinDebugLogger::from_builder(&builder)
+                .format(&record, None)
+                .unwrap()
+                .text
+        };
+
+        let line = format(WinDebugLoggerBuilder::new().initial_buffer_capacity(1024));
+        assert!(line.capacity() >= 1024, "{}", line.capacity());
+
+        // Lines longer than the capacity are not cut off
+        let line = format(WinDebugLoggerBuilder::new().initial_buffer_capacity(1));
+        assert!(String::from_utf16_lossy(&line).ends_with(" hello\n\0"));
+    }
+
+    #[test]
+    fn test_custom_formatter() {
+        let logger = WinDebugLogger::from_builder(&WinDebugLoggerBuilder::new().custom_formatter(
+            Box::new(|record, out| writeln!(out, "{}|{}", record.level(), record.args())),
+        ));
+        let line = logger
+            .format(
+                &log::Record::builder()
+                    .args(format_args!("hello"))
+                    .level(Level::Warn)
+                    .build(),
+                None,
+            )
+            .unwrap()
+            .text;
+        assert_eq!(String::from_utf16_lossy(&line), "WARN|hello\n\0");
+    }
+
+    #[test]
+    fn test_custom_formatter_panic() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new().custom_formatter(Box::new(|_, _| panic!("oops"))),
+        );
+        let line = logger
+            .format(
+                &log::Record::builder().args(format_args!("hello")).build(),
+                None,
+            )
+            .unwrap()
+            .text;
+        assert_eq!(
+            String::from_utf16_lossy(&line),
+            "<windebug_logger: formatter panicked>\n\0"
+        );
+    }
+
+    #[test]
+    fn test_format_wide() {
+        let logger = WinDebugLogger::new(Level::Trace);
+        let message = [0x61, 0xd83e, 0x62];
+        let line = logger
+            .format(
+                &log::Record::builder()
+                    .args(format_args!("ignored"))
+                    .level(Level::Info)
+                    .target("app")
+                    .build(),
+                Some(&message),
+            )
+            .unwrap()
+            .text;
+        assert!(
+            line.ends_with(&[0x5d, 0x20, 0x61, 0xd83e, 0x62, 0x0a, 0]),
+            "{:x?}",
+            line
+        );
+    }
+
+    #[test]
+    fn test_default_target() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new().with_default_target("unknown"),
+        );
+        let format = |target, module_path| {
+            let line = logger
+                .format(
+                    &log::Record::builder()
+                        .args(format_args!("hello"))
+                        .target(target)
+                        .module_path(module_path)
+                        .build(),
+                    None,
+                )
+                .unwrap()
+                .text;
+            String::from_utf16_lossy(&line)
+        };
+        assert!(format("app", None).ends_with(" [app] hello\n\0"));
+        assert!(format("", Some("app::ui")).ends_with(" [app::ui] hello\n\0"));
+        assert!(format("", None).ends_with(" [unknown] hello\n\0"));
+    }
+
+    #[test]
+    fn test_format_dbgmsg() {
+        let location = Location::caller();
+        let prefix = format!("{}:{}: ", location.file(), location.line());
+        assert_eq!(
+            format_dbgmsg(location, None, format_args!("x = {}", 42)),
+            format!("{}x = 42\n", prefix)
+        );
+        assert_eq!(
+            format_dbgmsg(location, Some("NET"), format_args!("connected")),
+            format!("{}[NET] connected\n", prefix)
+        );
+    }
+
+    #[test]
+    fn test_split_level_tag() {
+        // A fixed timestamp keeps the test independent of `no-timestamps`.
+        // It takes more bytes than UTF-16 code units.
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|out| out.push('\u{1f552}'))
+                .split_level_tag(true)
+                .sink(sink),
+        );
+        let record = log::Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Warn)
+            .target("app")
+            .build();
+        let line = logger.format(&record, None).unwrap();
+        let (start, end) = line.level_tag.unwrap();
+        let text = |range: std::ops::Range<usize>| String::from_utf16_lossy(&line.text[range]);
+        assert_eq!(text(0..start), "\u{1f552} ");
+        assert_eq!(text(start..end), "WARN ");
+        assert_eq!(text(end..line.text.len()), " [app] hello\n\0");
+
+        // Other sinks receive the line in one piece
+        logger.log(&record);
+        assert_eq!(captured.lines(), ["\u{1f552} WARN  [app] hello\n"]);
+    }
+
+    #[test]
+    fn test_elide_repeated_targets() {
+        let logger = WinDebugLogger::new(Level::Trace);
+        let config = logger.config();
+        let format = |target| {
+            let line = logger
+                .format_line(
+                    &config,
+                    &log::Record::builder()
+                        .args(format_args!("hello"))
+                        .target(target)
+                        .build(),
+                    None,
+                    true,
+                )
+                .unwrap()
+                .text;
+            String::from_utf16_lossy(&line)
+        };
+        assert!(format("app").ends_with(" [app] hello\n\0"));
+        assert!(format("app").ends_with(" [ \" ] hello\n\0"));
+        assert!(format("app::ui").ends_with(" [app::ui] hello\n\0"));
+        format::forget_last_target();
+        assert!(format("app::ui").ends_with(" [app::ui] hello\n\0"));
+    }
+
+    #[test]
+    fn test_timestamp_fn() {
+        let format = |builder: WinDebugLoggerBuilder| {
+            let line = WinDebugLogger::from_builder(&builder)
+                .format(
+                    &log::Record::builder()
+                        .args(format_args!("hello"))
+                        .target("app")
+                        .build(),
+                    None,
+                )
+                .unwrap()
+                .text;
+            String::from_utf16_lossy(&line)
+        };
+        let builder = WinDebugLoggerBuilder::new();
+        assert_eq!(
+            format(builder.clone().timestamp_fn(|out| out.push_str("T+1"))),
+            "T+1 INFO  [app] hello\n\0"
+        );
+        assert_eq!(
+            format(builder.clone().timestamp_fn(|_| {})),
+            "INFO  [app] hello\n\0"
+        );
+        assert_eq!(
+            format(builder.timestamp_fn(|_| panic!("intentional panic"))),
+            "INFO  [app] hello\n\0"
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Log a record with a fixed timestamp and return the line passed to
+    /// `OutputDebugStringW` and the line passed to sinks.
+    fn golden_output(builder: WinDebugLoggerBuilder, record: &log::Record) -> (String, String) {
+        let (sink, captured) = CaptureSink::new();
+        let builder = builder.sink(sink);
+        let logger = WinDebugLogger::from_builder(&builder);
+        // 2020-01-02 03:04:05.678 UTC
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1_577_934_245_678);
+        let ods_line = format::with_timestamp(time, || {
+            let line = logger.format(record, None).unwrap().text;
+            logger.log(record);
+            line
+        });
+        let mut lines = captured.wide_lines();
+        assert_eq!(lines.len(), 1);
+        (
+            String::from_utf16(&ods_line).unwrap(),
+            String::from_utf16(&lines.pop().unwrap()).unwrap(),
+        )
+    }
+
+    /// The timestamp rendered by `golden_output` with the default settings.
+    const GOLDEN_TIMESTAMP: &str =
+        if cfg!(all(feature = "timestamps", not(feature = "no-timestamps"))) {
+            "01/02/2020 03:04:05 "
+        } else {
+            ""
+        };
+
+    // These tests lock down the exact output that log parsers depend on.
+    // Update them only when the format is changed deliberately.
+
+    #[test]
+    fn test_golden_default() {
+        let (ods_line, sink_line) = golden_output(
+            WinDebugLoggerBuilder::new(),
+            &log::Record::builder()
+                .args(format_args!("hello, world"))
+                .level(Level::Info)
+                .target("app::net")
+                .build(),
+        );
+        let expected = format!("{}INFO  [app::net] hello, world\n", GOLDEN_TIMESTAMP);
+        assert_eq!(ods_line, format!("{}\0", expected));
+        assert_eq!(sink_line, expected);
+    }
+
+    #[test]
+    fn test_golden_no_timestamp() {
+        let (ods_line, sink_line) = golden_output(
+            WinDebugLoggerBuilder::new().timestamp_fn(|_| {}),
+            &log::Record::builder()
+                .args(format_args!("disk almost full"))
+                .level(Level::Warn)
+                .target("app")
+                .build(),
+        );
+        assert_eq!(ods_line, "WARN  [app] disk almost full\n\0");
+        assert_eq!(sink_line, "WARN  [app] disk almost full\n");
+    }
+
+    #[test]
+    fn test_golden_no_target() {
+        // Neither a target nor a module path
+        let record = log::Record::builder()
+            .args(format_args!("failed"))
+            .level(Level::Error)
+            .build();
+        let (ods_line, sink_line) = golden_output(WinDebugLoggerBuilder::new(), &record);
+        assert_eq!(ods_line, format!("{}ERROR [] failed\n\0", GOLDEN_TIMESTAMP));
+        assert_eq!(sink_line, format!("{}ERROR [] failed\n", GOLDEN_TIMESTAMP));
+
+        let (ods_line, _) = golden_output(
+            WinDebugLoggerBuilder::new().with_default_target("main"),
+            &record,
+        );
+        assert_eq!(
+            ods_line,
+            format!("{}ERROR [main] failed\n\0", GOLDEN_TIMESTAMP)
+        );
+    }
+
+    #[test]
+    fn test_golden_bracketed() {
+        let (ods_line, _) = golden_output(
+            WinDebugLoggerBuilder::new()
+                .level_style(LevelStyle::Bracketed)
+                .source_location(true),
+            &log::Record::builder()
+                .args(format_args!("x = 1"))
+                .level(Level::Debug)
+                .target("app")
+                .file(Some("src/main.rs"))
+                .line(Some(7))
+                .build(),
+        );
+        assert_eq!(
+            ods_line,
+            format!("{}<D> [app] x = 1 @ src/main.rs:7\n\0", GOLDEN_TIMESTAMP)
+        );
+    }
+
+    #[test]
+    fn test_route() {
+        let file = SharedBuf::default();
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|out| out.push_str("now"))
+                .file_writer(file.clone())
+                .sink(sink)
+                .ring_buffer(4)
+                .route(LevelFilter::Warn, Sinks::FILE)
+                .route(LevelFilter::Error, Sinks::CUSTOM)
+                .route(LevelFilter::Off, Sinks::ODS),
+        );
+        let log_at = |level: Level| {
+            logger.log(
+                &log::Record::builder()
+                    .args(format_args!("{}", level))
+                    .level(level)
+                    .target("app")
+                    .build(),
+            )
+        };
+
+        log_at(Level::Warn);
+        let file_contents = String::from_utf8(file.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            file_contents.ends_with(" WARN  [app] WARN\n"),
+            "{:?}",
+            file_contents
+        );
+        assert!(captured.lines().is_empty());
+        assert_eq!(
+            logger.history.as_ref().unwrap().snapshot(),
+            [&file_contents[..]]
+        );
+
+        log_at(Level::Info);
+        assert_eq!(file.0.lock().unwrap().len(), file_contents.len());
+        assert_eq!(logger.history.as_ref().unwrap().snapshot().len(), 2);
+
+        log_at(Level::Error);
+        let lines = captured.records();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].0, Level::Error);
+        assert!(
+            lines[0].1.ends_with(" ERROR [app] ERROR\n"),
+            "{:?}",
+            lines[0].1
+        );
+    }
+
+    /// A sink that fails while `fail` is set.
+    #[derive(Default, Clone)]
+    struct FlakySink(Arc<AtomicBool>);
+
+    impl DebugSink for FlakySink {
+        fn write(&self, _level: Level, _line: &[u16]) -> std::io::Result<()> {
+            if self.0.load(Ordering::Relaxed) {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            } else {
+                Ok(())
+            }
+        }
+
+        fn name(&self) -> String {
+            "flaky".to_owned()
+        }
+    }
+
+    #[test]
+    fn test_sink_status() {
+        let flaky = FlakySink::default();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .file_writer(SharedBuf::default())
+                .sink(flaky.clone()),
+        );
+        let log = || {
+            logger.log(
+                &log::Record::builder()
+                    .args(format_args!("hello"))
+                    .level(Level::Info)
+                    .build(),
+            )
+        };
+        let status = |ok| {
+            vec![
+                ("debugger".to_owned(), true),
+                ("file".to_owned(), true),
+                ("flaky".to_owned(), ok),
+            ]
+        };
+
+        assert_eq!(logger.sink_status(), status(true));
+        flaky.0.store(true, Ordering::Relaxed);
+        log();
+        assert_eq!(logger.sink_status(), status(false));
+        flaky.0.store(false, Ordering::Relaxed);
+        log();
+        assert_eq!(logger.sink_status(), status(true));
+    }
+
+    #[test]
+    fn test_reconfigure() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|out| out.push_str("now"))
+                .sink(sink),
+        );
+        let log = || {
+            logger.log(
+                &log::Record::builder()
+                    .args(format_args!("hello"))
+                    .level(Level::Warn)
+                    .target("app")
+                    .build(),
+            )
+        };
+
+        log();
+        logger.reconfigure(|b| b.level_style(LevelStyle::Bracketed));
+        log();
+        logger.reconfigure(|b| b.remove_sinks(Sinks::CUSTOM));
+        log();
+
+        let lines = captured.lines();
+        assert_eq!(lines, ["now WARN  [app] hello\n", "now <W> [app] hello\n"]);
+        assert_eq!(logger.sink_status(), [("debugger".to_owned(), true)]);
+    }
+
+    #[test]
+    fn test_reconfigure_filters() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|out| out.push_str("now"))
+                .level(LevelFilter::Info)
+                .sink(sink),
+        );
+        let log = |target, level| {
+            logger.log(
+                &log::Record::builder()
+                    .args(format_args!("hello"))
+                    .level(level)
+                    .target(target)
+                    .build(),
+            )
+        };
+
+        logger.set_target_level("app::db", LevelFilter::Off);
+        logger.reconfigure(|b| {
+            // `set_target_level` is reflected in the builder
+            assert_eq!(b.target_filter.level_for("app::db"), Some(LevelFilter::Off));
+            b.target_level("app::net", LevelFilter::Debug)
+                .deny_targets(&["noisy"])
+        });
+        log("app::net", Level::Debug);
+        log("app::db", Level::Error);
+        log("noisy", Level::Error);
+        log("app", Level::Debug);
+
+        logger.reconfigure(|b| b.only_threads(ThreadFilter::name(|_| false)));
+        log("app", Level::Error);
+
+        let lines = captured.lines();
+        assert_eq!(lines, ["now DEBUG [app::net] hello\n"]);
+    }
+
+    #[test]
+    fn test_add_sink_fn() {
+        static LINES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        fn capture(line: &[u16]) {
+            LINES
+                .lock()
+                .unwrap()
+                .push(String::from_utf16(line).unwrap());
+        }
+
+        let mut logger = WinDebugLogger::new(Level::Info);
+        logger.add_sink_fn(capture);
+        logger.log(
+            &log::Record::builder()
+                .args(format_args!("hello"))
+                .level(Level::Info)
+                .target("app")
+                .build(),
+        );
+
+        let lines = LINES.lock().unwrap();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with("INFO  [app] hello\n"), "{:?}", lines[0]);
+    }
+
+    #[test]
+    fn test_level_symbols() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|out| out.push_str("now"))
+                .level_symbol(Level::Error, "\u{2716}")
+                .level_symbol(Level::Warn, "🔥"),
+        );
+        let format = |level| {
+            let line = logger
+                .format(
+                    &log::Record::builder()
+                        .args(format_args!("hello"))
+                        .level(level)
+                        .target("app")
+                        .build(),
+                    None,
+                )
+                .unwrap()
+                .text;
+            String::from_utf16(&line).unwrap()
+        };
+
+        assert_eq!(format(Level::Error), "now ✖ ERROR [app] hello\n\0");
+        assert_eq!(format(Level::Warn), "now 🔥 WARN  [app] hello\n\0");
+        assert_eq!(format(Level::Info), "now INFO  [app] hello\n\0");
+    }
+
+    #[test]
+    fn test_debug() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .level(LevelFilter::Info)
+                .timestamp_fn(|out| out.push_str("now"))
+                .sink(CaptureSink::new().0)
+                .sink(FlakySink::default()),
+        );
+        let debug = format!("{:?}", logger);
+        assert!(
+            debug.starts_with(
+                "WinDebugLogger { level: Info, timestamp_format: \"custom\", routes: "
+            ),
+            "{}",
+            debug
+        );
+        assert!(debug.ends_with(", sinks: [<2 sinks>], .. }"), "{}", debug);
+    }
+
+    #[test]
+    fn test_enabled_for() {
+        let logger = WinDebugLogger::new(Level::Info);
+        assert!(logger.enabled_for("net", Level::Warn));
+        assert!(logger.enabled_for("net", Level::Info));
+        assert!(!logger.enabled_for("net", Level::Debug));
+
+        logger.shutdown();
+        assert!(!logger.enabled_for("net", Level::Error));
+    }
+
+    #[test]
+    fn test_uninstalled_logger_keeps_max_level() {
+        // Changes `log`'s global level filter
+        let _guard = test::lock();
+        log::set_max_level(LevelFilter::Trace);
+
+        let logger = WinDebugLogger::from_builder(&WinDebugLoggerBuilder::new());
+        logger.set_level(LevelFilter::Off);
+        assert_eq!(log::max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_shutdown_releases_sinks() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(&WinDebugLoggerBuilder::new().sink(sink));
+        log_message(&logger, "hello");
+        assert_eq!(logger.sink_status().len(), 2);
+
+        logger.shutdown();
+        assert_eq!(logger.sink_status(), [("debugger".to_owned(), true)]);
+        assert_eq!(logger.max_level(), LevelFilter::Off);
+        // Changes `log`'s global level filter
+        let _guard = test::lock();
+        logger.set_level(LevelFilter::Trace);
+        assert_eq!(logger.max_level(), LevelFilter::Off);
+        log_message(&logger, "discarded");
+        assert_eq!(captured.lines().len(), 1);
+    }
+
+    #[test]
+    fn test_init_marker() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .level(LevelFilter::Warn)
+                .timestamp_fn(|_| {})
+                .sink(sink),
+        );
+        logger.log_init_marker(LevelFilter::Warn);
+
+        let lines = captured.lines();
+        assert_eq!(
+            lines[0],
+            format!(
+                "INFO  [windebug_logger] windebug_logger {} initialized with level=Warn\n",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[test]
+    fn test_flush_on_error() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new().sink(sink).flush_on_error(true),
+        );
+        let log_at = |level: Level| {
+            logger.log(
+                &log::Record::builder()
+                    .args(format_args!("{}", level))
+                    .level(level)
+                    .build(),
+            )
+        };
+
+        log_at(Level::Warn);
+        assert_eq!(captured.flushes(), 0);
+        log_at(Level::Error);
+        assert_eq!(captured.flushes(), 1);
+
+        logger.reconfigure(|b| b.flush_on_error(false));
+        log_at(Level::Error);
+        assert_eq!(captured.flushes(), 1);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|_| {})
+                .sink(sink)
+                .ring_buffer(4)
+                .dry_run(true),
+        );
+        log_message(&logger, "hello");
+
+        assert_eq!(
+            captured.records(),
+            [(
+                Level::Info,
+                "INFO  [windebug_logger::tests] hello\n".to_owned()
+            )]
+        );
+        assert_eq!(logger.history.as_ref().unwrap().snapshot().len(), 1);
+    }
+
+    #[test]
+    fn test_surrogate_pairs() {
+        let (sink, captured) = CaptureSink::new();
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .sink(sink)
+                .route(LevelFilter::Off, Sinks::ODS),
+        );
+        let record = log::Record::builder()
+            .args(format_args!("🦄 ✨ 𐍈"))
+            .target("app")
+            .build();
+
+        let line = logger.format(&record, None).unwrap().text;
+        assert!(
+            line.ends_with(&[0xd83e, 0xdd84, 0x20, 0x2728, 0x20, 0xd800, 0xdf48, 0x0a, 0]),
+            "{:x?}",
+            line
+        );
+        assert_eq!(line.iter().filter(|&&c| c == 0).count(), 1);
+
+        logger.log(&record);
+        let lines = captured.lines();
+        assert!(lines[0].ends_with(" [app] 🦄 ✨ 𐍈\n"), "{:?}", lines[0]);
+    }
+
+    #[test]
+    fn test_session_id() {
+        let logger = WinDebugLogger::from_builder(
+            &WinDebugLoggerBuilder::new()
+                .timestamp_fn(|out| out.push_str("now"))
+                .session_id(true),
+        );
+        let line = logger
+            .format(
+                &log::Record::builder()
+                    .args(format_args!("hello"))
+                    .level(Level::Info)
+                    .target("app")
+                    .build(),
+                None,
+            )
+            .unwrap()
+            .text;
+        let line = String::from_utf16_lossy(&line);
+        let expected = format!(" {} INFO  [app] hello\n\0", logger.session_id());
+        assert!(line.ends_with(&expected), "{:?}", line);
+    }
 }
@@ -0,0 +1,143 @@
+//! Detection of programs receiving the debug output. See
+//! [`debug_listener_present`].
+use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use winapi::um::{debugapi, handleapi, synchapi, sysinfoapi, winnt::SYNCHRONIZE};
+
+/// The kind of program receiving the output of `OutputDebugStringW`. See
+/// [`debug_listener_present`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ListenerKind {
+    /// Nothing receives the output, so it is discarded.
+    None,
+    /// A debugger is attached to the process. It takes precedence over a
+    /// monitor, which does not receive the output in this case.
+    Debugger,
+    /// A debug output monitor such as [DebugView] is running.
+    ///
+    /// [DebugView]: https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
+    Monitor,
+}
+
+/// The interval after which the cached state is re-evaluated, in
+/// milliseconds.
+const REFRESH_INTERVAL_MS: u32 = 1000;
+
+/// The cached state: `0` if not evaluated yet, or `ListenerKind as u8 + 1`.
+static STATE: AtomicU8 = AtomicU8::new(0);
+/// The `GetTickCount` value at the last evaluation.
+static CHECKED_AT: AtomicU32 = AtomicU32::new(0);
+
+/// Check whether a debugger is attached to the process. This is a shorthand
+/// for `debug_listener_present() == ListenerKind::Debugger` and uses the
+/// same cache.
+///
+/// ```
+/// if windebug_logger::debugger_present() {
+///     // Build an expensive description only if someone is watching
+/// }
+/// ```
+pub fn debugger_present() -> bool {
+    debug_listener_present() == ListenerKind::Debugger
+}
+
+/// Determine what receives the output of `OutputDebugStringW`: an attached
+/// debugger, a debug output monitor such as DebugView, or nothing.
+///
+/// The result is cached and re-evaluated at most once a second, so this is
+/// cheap enough to call before every expensive log message. Call
+/// [`refresh_listener_state`] to re-evaluate it immediately.
+/// [`WinDebugLoggerBuilder::skip_when_unattended`] uses this function.
+///
+/// A monitor is detected by the named event object it creates to receive
+/// the output (`DBWIN_BUFFER_READY`), either in the current session or, as
+/// DebugView's "Capture Global Win32" does, in the global namespace. A
+/// kernel debugger is not detected.
+///
+/// ```
+/// use windebug_logger::{debug_listener_present, ListenerKind};
+///
+/// if debug_listener_present() != ListenerKind::None {
+///     log::debug!("state: {}", "an expensive description");
+/// }
+/// ```
+///
+/// [`WinDebugLoggerBuilder::skip_when_unattended`]: crate::WinDebugLoggerBuilder::skip_when_unattended
+pub fn debug_listener_present() -> ListenerKind {
+    let state = STATE.load(Ordering::Acquire);
+    if state != 0 {
+        let now = unsafe { sysinfoapi::GetTickCount() };
+        if now.wrapping_sub(CHECKED_AT.load(Ordering::Relaxed)) < REFRESH_INTERVAL_MS {
+            return decode(state);
+        }
+    }
+    refresh_listener_state()
+}
+
+/// Re-evaluate and cache the state returned by [`debug_listener_present`],
+/// e.g., after the user attached a debugger from a debug menu, and return
+/// the new state.
+///
+/// This opens a few named objects, so it's cheap enough to call, e.g., once
+/// a second from a UI timer, but not for every log message.
+pub fn refresh_listener_state() -> ListenerKind {
+    let kind = detect();
+    CHECKED_AT.store(unsafe { sysinfoapi::GetTickCount() }, Ordering::Relaxed);
+    STATE.store(kind as u8 + 1, Ordering::Release);
+    kind
+}
+
+fn decode(state: u8) -> ListenerKind {
+    match state - 1 {
+        x if x == ListenerKind::Debugger as u8 => ListenerKind::Debugger,
+        x if x == ListenerKind::Monitor as u8 => ListenerKind::Monitor,
+        _ => ListenerKind::None,
+    }
+}
+
+fn detect() -> ListenerKind {
+    if unsafe { debugapi::IsDebuggerPresent() } != 0 {
+        ListenerKind::Debugger
+    } else if event_exists(wchar::wch_c!("DBWIN_BUFFER_READY"))
+        || event_exists(wchar::wch_c!("Global\\DBWIN_BUFFER_READY"))
+    {
+        ListenerKind::Monitor
+    } else {
+        ListenerKind::None
+    }
+}
+
+/// Check if a named event object exists (and is accessible).
+fn event_exists(name: &[u16]) -> bool {
+    debug_assert_eq!(name.last(), Some(&0));
+    let handle = unsafe { synchapi::OpenEventW(SYNCHRONIZE, 0, name.as_ptr()) };
+    if handle.is_null() {
+        false
+    } else {
+        unsafe { handleapi::CloseHandle(handle) };
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        for &kind in &[
+            ListenerKind::None,
+            ListenerKind::Debugger,
+            ListenerKind::Monitor,
+        ] {
+            assert_eq!(decode(kind as u8 + 1), kind);
+        }
+    }
+
+    #[test]
+    fn test_cache() {
+        let kind = refresh_listener_state();
+        assert_eq!(debug_listener_present(), kind);
+        assert_eq!(debugger_present(), kind == ListenerKind::Debugger);
+    }
+}
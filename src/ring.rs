@@ -0,0 +1,76 @@
+use log::LevelFilter;
+use std::{collections::VecDeque, sync::Mutex};
+
+/// Retains the most recent formatted lines, e.g., the ones that were filtered
+/// out, to be written when an error is logged (see
+/// [`WinDebugLoggerBuilder::debug_on_error`]), or the ones written to
+/// [`Sinks::RING_BUFFER`].
+///
+/// [`WinDebugLoggerBuilder::debug_on_error`]: crate::WinDebugLoggerBuilder::debug_on_error
+/// [`Sinks::RING_BUFFER`]: crate::Sinks::RING_BUFFER
+#[derive(Debug)]
+pub(crate) struct RingBuffer<T> {
+    /// The least severe level retained.
+    level: LevelFilter,
+    capacity: usize,
+    /// Oldest first.
+    lines: Mutex<VecDeque<T>>,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(level: LevelFilter, capacity: usize) -> Self {
+        Self {
+            level,
+            capacity: capacity.max(1),
+            lines: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Append a line, discarding the oldest one if the buffer is full.
+    pub fn push(&self, line: T) {
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() >= self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// Remove and return all retained lines, oldest first.
+    pub fn drain(&self) -> VecDeque<T> {
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        std::mem::take(&mut *lines)
+    }
+
+    /// Return a copy of all retained lines, oldest first.
+    pub fn snapshot(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        lines.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        let ring = RingBuffer::new(LevelFilter::Debug, 2);
+        ring.push(vec![1, 0]);
+        ring.push(vec![2, 0]);
+        ring.push(vec![3, 0]);
+        assert_eq!(ring.snapshot(), [vec![2, 0], vec![3, 0]]);
+        assert_eq!(ring.drain(), [vec![2, 0], vec![3, 0]]);
+        assert!(ring.drain().is_empty());
+    }
+}
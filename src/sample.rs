@@ -0,0 +1,104 @@
+//! Sampling of the records of noisy targets. See
+//! [`WinDebugLoggerBuilder::with_sampling`].
+//!
+//! [`WinDebugLoggerBuilder::with_sampling`]: crate::WinDebugLoggerBuilder::with_sampling
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::filter;
+
+/// Keeps one in every N records of the configured targets. See
+/// [`WinDebugLoggerBuilder::with_sampling`].
+///
+/// [`WinDebugLoggerBuilder::with_sampling`]: crate::WinDebugLoggerBuilder::with_sampling
+#[derive(Debug)]
+pub(crate) struct Sampler {
+    /// The sampling rate and the number of records seen so far, indexed by
+    /// target.
+    targets: HashMap<String, (u32, AtomicU64)>,
+}
+
+/// The result of [`Sampler::sample`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Sample {
+    /// The target is not sampled.
+    All,
+    /// The record is one in every N records of the target.
+    Keep(u32),
+    /// The record is not sampled.
+    Drop,
+}
+
+impl Sampler {
+    /// Construct a `Sampler`, or return `None` if no target is sampled.
+    pub fn new(rates: &HashMap<String, u32>) -> Option<Self> {
+        if rates.values().all(|&rate| rate <= 1) {
+            return None;
+        }
+        let targets = rates
+            .iter()
+            .map(|(target, &rate)| (target.clone(), (rate, AtomicU64::new(0))))
+            .collect();
+        Some(Self { targets })
+    }
+
+    /// Decide whether to keep a record of `target`. The most specific
+    /// configured ancestor of `target` decides, and the first record of each
+    /// group of N is kept.
+    pub fn sample(&self, target: &str) -> Sample {
+        let entry = filter::ancestors(target).find_map(|prefix| self.targets.get(prefix));
+        match entry {
+            Some((rate, count)) if *rate > 1 => {
+                if count.fetch_add(1, Ordering::Relaxed) % u64::from(*rate) == 0 {
+                    Sample::Keep(*rate)
+                } else {
+                    Sample::Drop
+                }
+            }
+            _ => Sample::All,
+        }
+    }
+
+    /// Get the sampled targets and their rates, sorted by target.
+    pub fn rates(&self) -> Vec<(&str, u32)> {
+        let mut rates: Vec<_> = self
+            .targets
+            .iter()
+            .map(|(target, (rate, _))| (target.as_str(), *rate))
+            .collect();
+        rates.sort_unstable();
+        rates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        let rates = HashMap::from([("hot".to_owned(), 3), ("hot::cold".to_owned(), 1)]);
+        let sampler = Sampler::new(&rates).unwrap();
+        let samples: Vec<_> = (0..7).map(|_| sampler.sample("hot::net")).collect();
+        assert_eq!(
+            samples,
+            [
+                Sample::Keep(3),
+                Sample::Drop,
+                Sample::Drop,
+                Sample::Keep(3),
+                Sample::Drop,
+                Sample::Drop,
+                Sample::Keep(3),
+            ]
+        );
+        // A rate of 1 disables sampling
+        assert_eq!(sampler.sample("hot::cold::tls"), Sample::All);
+        assert_eq!(sampler.sample("app"), Sample::All);
+
+        assert!(Sampler::new(&HashMap::new()).is_none());
+        assert!(Sampler::new(&HashMap::from([("hot".to_owned(), 1)])).is_none());
+    }
+}
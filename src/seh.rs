@@ -0,0 +1,525 @@
+use std::{
+    cell::UnsafeCell,
+    fmt::{self, Write},
+    mem::{size_of, transmute, MaybeUninit},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Once,
+    },
+};
+use winapi::{
+    shared::minwindef::MAX_PATH,
+    um::{
+        debugapi, errhandlingapi, memoryapi, processthreadsapi, psapi,
+        winnt::{
+            CONTEXT, EXCEPTION_POINTERS, EXCEPTION_RECORD, LONG, MEMORY_BASIC_INFORMATION,
+            MEM_IMAGE,
+        },
+    },
+    vc::excpt::EXCEPTION_CONTINUE_SEARCH,
+};
+
+/// The unhandled exception filter that was in place before
+/// `install_exception_logger`, stored as a function pointer cast to `usize`.
+static PREVIOUS_FILTER: AtomicUsize = AtomicUsize::new(0);
+
+type FilterFn = unsafe extern "system" fn(*mut EXCEPTION_POINTERS) -> LONG;
+
+/// Install an unhandled exception filter that logs structured exceptions
+/// (access violations, stack overflows, etc.) that are about to terminate
+/// the process.
+///
+/// The filter outputs the exception code, the faulting address, the module
+/// containing it, and the registers of the faulting thread directly via
+/// `OutputDebugStringW`, bypassing the logger's configuration, and then
+/// passes the exception on to the previously installed filter (if any).
+/// Since the process might be in an arbitrarily broken state at this point,
+/// the filter does not allocate memory or acquire locks, and it formats
+/// into static buffers rather than the stack, which might have overflowed.
+///
+/// Only the first call has an effect. Note that an unhandled exception filter
+/// is not called while a debugger is attached to the process.
+///
+/// ```
+/// windebug_logger::install_exception_logger();
+/// ```
+pub fn install_exception_logger() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| unsafe {
+        let previous = errhandlingapi::SetUnhandledExceptionFilter(Some(exception_filter));
+        if let Some(previous) = previous {
+            PREVIOUS_FILTER.store(previous as usize, Ordering::Relaxed);
+        }
+    });
+}
+
+unsafe extern "system" fn exception_filter(info: *mut EXCEPTION_POINTERS) -> LONG {
+    with_buffers(|buffers| {
+        log_exception(buffers, &*(*info).ExceptionRecord);
+        if let Some(context) = (*info).ContextRecord.as_ref() {
+            write_registers(&mut buffers.text, context, |line| {
+                debugapi::OutputDebugStringW(line.as_ptr())
+            });
+        }
+    });
+
+    match PREVIOUS_FILTER.load(Ordering::Relaxed) {
+        0 => EXCEPTION_CONTINUE_SEARCH,
+        previous => {
+            let previous: FilterFn = transmute(previous);
+            previous(info)
+        }
+    }
+}
+
+unsafe fn log_exception(buffers: &mut Buffers, record: &EXCEPTION_RECORD) {
+    let buf = &mut buffers.text;
+    buf.clear();
+
+    let code = record.ExceptionCode;
+    let address = record.ExceptionAddress as usize;
+    let _ = write!(
+        buf,
+        "FATAL [windebug_logger] Unhandled exception {:#010x}",
+        code
+    );
+    if let Some(name) = exception_name(code) {
+        let _ = write!(buf, " ({})", name);
+    }
+    let _ = write!(buf, " at {:#x}", address);
+
+    // Find the module containing the faulting address. `VirtualQuery` and
+    // `GetMappedFileNameW` don't take the loader lock, unlike
+    // `GetModuleHandleExW`.
+    let mut mbi = MaybeUninit::<MEMORY_BASIC_INFORMATION>::uninit();
+    let mbi_len = memoryapi::VirtualQuery(
+        address as _,
+        mbi.as_mut_ptr(),
+        size_of::<MEMORY_BASIC_INFORMATION>(),
+    );
+    if mbi_len != 0 {
+        let mbi = mbi.assume_init();
+        if mbi.Type == MEM_IMAGE {
+            let base = mbi.AllocationBase as usize;
+            let path = &mut buffers.path;
+            let path_len = psapi::GetMappedFileNameW(
+                processthreadsapi::GetCurrentProcess(),
+                mbi.AllocationBase,
+                path.as_mut_ptr(),
+                path.len() as u32,
+            ) as usize;
+            let path = &path[..path_len];
+            let file_name = match path.iter().rposition(|&c| c == b'\\' as u16) {
+                Some(i) => &path[i + 1..],
+                None => path,
+            };
+
+            let _ = write!(buf, " (");
+            for c in std::char::decode_utf16(file_name.iter().cloned()) {
+                let _ = buf.write_char(c.unwrap_or(std::char::REPLACEMENT_CHARACTER));
+            }
+            let _ = write!(buf, "+{:#x})", address - base);
+        }
+    }
+
+    // Access violations report the kind of access and the inaccessible address
+    if code == 0xc0000005 && record.NumberParameters >= 2 {
+        let access = match record.ExceptionInformation[0] {
+            0 => "read from",
+            1 => "write to",
+            8 => "execution of",
+            _ => "access to",
+        };
+        let _ = write!(buf, ": {} {:#x}", access, record.ExceptionInformation[1]);
+    }
+
+    debugapi::OutputDebugStringW(buf.as_c_wstr().as_ptr());
+}
+
+/// Write a string to the debugger using `OutputDebugStringW` without
+/// allocating memory or acquiring locks.
+///
+/// Unlike [`output_debug_string`] and the logger, this is safe to call from
+/// a vectored exception handler, an unhandled exception filter, or other
+/// places where the process might be in a broken state (e.g., the heap is
+/// corrupted, another thread died holding a lock, or the stack overflowed).
+/// The string is converted to UTF-16 in one of a few static buffers and
+/// written as-is; no timestamp or trailing newline is added. Strings longer
+/// than the buffer (510 UTF-16 code units) are written by multiple calls.
+/// Nothing is written if the string contains a null character, or if all
+/// buffers are in use by other threads.
+///
+/// The logger's configuration, including
+/// [`dry_run`](crate::WinDebugLoggerBuilder::dry_run), is not consulted.
+///
+/// ```
+/// windebug_logger::emit_raw_static("app: entering crash handler\n");
+/// ```
+///
+/// [`output_debug_string`]: crate::output_debug_string
+pub fn emit_raw_static(s: &'static str) {
+    if s.contains('\0') {
+        return;
+    }
+    with_buffers(|buffers| {
+        let buf = &mut buffers.text;
+        buf.clear();
+        for c in s.chars() {
+            if !buf.push(c) {
+                unsafe { debugapi::OutputDebugStringW(buf.as_raw_c_wstr().as_ptr()) };
+                buf.clear();
+                buf.push(c);
+            }
+        }
+        if !buf.is_empty() {
+            unsafe { debugapi::OutputDebugStringW(buf.as_raw_c_wstr().as_ptr()) };
+        }
+    });
+}
+
+/// Format a message into a static buffer and write it to the debugger using
+/// `OutputDebugStringW` without allocating memory or acquiring locks. See
+/// [`emit_raw_static`] for the intended use and the buffers.
+///
+/// The message is truncated to 510 UTF-16 code units, and no trailing
+/// newline is added. Formatting itself does not allocate, but the `Display`
+/// and `Debug` implementations of the arguments might; stick to integers,
+/// pointers, and string slices in a crashed state.
+///
+/// ```
+/// # let code = 0xc0000005u32;
+/// windebug_logger::emit_raw(format_args!("app: exception {:#010x}\n", code));
+/// ```
+pub fn emit_raw(args: fmt::Arguments<'_>) {
+    with_buffers(|buffers| {
+        let buf = &mut buffers.text;
+        buf.clear();
+        let _ = buf.write_fmt(args);
+        unsafe { debugapi::OutputDebugStringW(buf.as_raw_c_wstr().as_ptr()) };
+    });
+}
+
+/// Write the registers in `context` as lines of `buf`, passing each line to
+/// `output`.
+fn write_registers(buf: &mut WideString, context: &CONTEXT, mut output: impl FnMut(&[u16])) {
+    const PER_LINE: usize = 8;
+    let width = 2 + 2 * size_of::<usize>();
+    for line in registers(context).chunks(PER_LINE) {
+        buf.clear();
+        let _ = write!(buf, "FATAL [windebug_logger] Registers:");
+        for &(name, value) in line {
+            let _ = write!(buf, " {}={:#0width$x}", name, value, width = width);
+        }
+        output(buf.as_c_wstr());
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn registers(c: &CONTEXT) -> [(&'static str, u64); 18] {
+    [
+        ("rip", c.Rip),
+        ("rsp", c.Rsp),
+        ("rbp", c.Rbp),
+        ("eflags", c.EFlags.into()),
+        ("rax", c.Rax),
+        ("rbx", c.Rbx),
+        ("rcx", c.Rcx),
+        ("rdx", c.Rdx),
+        ("rsi", c.Rsi),
+        ("rdi", c.Rdi),
+        ("r8", c.R8),
+        ("r9", c.R9),
+        ("r10", c.R10),
+        ("r11", c.R11),
+        ("r12", c.R12),
+        ("r13", c.R13),
+        ("r14", c.R14),
+        ("r15", c.R15),
+    ]
+}
+
+#[cfg(target_arch = "x86")]
+fn registers(c: &CONTEXT) -> [(&'static str, u32); 10] {
+    [
+        ("eip", c.Eip),
+        ("esp", c.Esp),
+        ("ebp", c.Ebp),
+        ("eflags", c.EFlags),
+        ("eax", c.Eax),
+        ("ebx", c.Ebx),
+        ("ecx", c.Ecx),
+        ("edx", c.Edx),
+        ("esi", c.Esi),
+        ("edi", c.Edi),
+    ]
+}
+
+#[cfg(target_arch = "aarch64")]
+fn registers(c: &CONTEXT) -> [(&'static str, u64); 34] {
+    let x = unsafe { c.u.s() };
+    [
+        ("pc", c.Pc),
+        ("sp", c.Sp),
+        ("fp", x.Fp),
+        ("lr", x.Lr),
+        ("cpsr", c.Cpsr.into()),
+        ("x0", x.X0),
+        ("x1", x.X1),
+        ("x2", x.X2),
+        ("x3", x.X3),
+        ("x4", x.X4),
+        ("x5", x.X5),
+        ("x6", x.X6),
+        ("x7", x.X7),
+        ("x8", x.X8),
+        ("x9", x.X9),
+        ("x10", x.X10),
+        ("x11", x.X11),
+        ("x12", x.X12),
+        ("x13", x.X13),
+        ("x14", x.X14),
+        ("x15", x.X15),
+        ("x16", x.X16),
+        ("x17", x.X17),
+        ("x18", x.X18),
+        ("x19", x.X19),
+        ("x20", x.X20),
+        ("x21", x.X21),
+        ("x22", x.X22),
+        ("x23", x.X23),
+        ("x24", x.X24),
+        ("x25", x.X25),
+        ("x26", x.X26),
+        ("x27", x.X27),
+        ("x28", x.X28),
+    ]
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64")))]
+fn registers(_: &CONTEXT) -> [(&'static str, usize); 0] {
+    []
+}
+
+/// The buffers that the functions of this module format into. They are
+/// static because the stack might have overflowed.
+struct Buffers {
+    text: WideString,
+    path: [u16; MAX_PATH],
+}
+
+struct BufferSlot {
+    in_use: AtomicBool,
+    buffers: UnsafeCell<Buffers>,
+}
+
+// `in_use` gives one thread at a time access to `buffers`
+unsafe impl Sync for BufferSlot {}
+
+impl BufferSlot {
+    const fn new() -> Self {
+        Self {
+            in_use: AtomicBool::new(false),
+            buffers: UnsafeCell::new(Buffers {
+                text: WideString::new(),
+                path: [0; MAX_PATH],
+            }),
+        }
+    }
+}
+
+/// Enough for a few threads failing at once. A thread that finds them all
+/// in use, including one that faults again inside `with_buffers`, skips its
+/// output rather than waiting for the others.
+static BUFFER_SLOTS: [BufferSlot; 4] = [
+    BufferSlot::new(),
+    BufferSlot::new(),
+    BufferSlot::new(),
+    BufferSlot::new(),
+];
+
+/// Call `f` with a set of buffers that no other thread is using, or do
+/// nothing if there are none.
+fn with_buffers(f: impl FnOnce(&mut Buffers)) {
+    struct Release<'a>(&'a AtomicBool);
+
+    impl Drop for Release<'_> {
+        fn drop(&mut self) {
+            self.0.store(false, Ordering::Release);
+        }
+    }
+
+    for slot in &BUFFER_SLOTS {
+        if (slot.in_use)
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            // Released even if a `Display` implementation given to
+            // `emit_raw` panics
+            let _release = Release(&slot.in_use);
+            f(unsafe { &mut *slot.buffers.get() });
+            return;
+        }
+    }
+}
+
+fn exception_name(code: u32) -> Option<&'static str> {
+    Some(match code {
+        0x80000002 => "EXCEPTION_DATATYPE_MISALIGNMENT",
+        0x80000003 => "EXCEPTION_BREAKPOINT",
+        0xc0000005 => "EXCEPTION_ACCESS_VIOLATION",
+        0xc0000006 => "EXCEPTION_IN_PAGE_ERROR",
+        0xc000001d => "EXCEPTION_ILLEGAL_INSTRUCTION",
+        0xc000008c => "EXCEPTION_ARRAY_BOUNDS_EXCEEDED",
+        0xc0000094 => "EXCEPTION_INT_DIVIDE_BY_ZERO",
+        0xc0000095 => "EXCEPTION_INT_OVERFLOW",
+        0xc0000096 => "EXCEPTION_PRIV_INSTRUCTION",
+        0xc00000fd => "EXCEPTION_STACK_OVERFLOW",
+        0xc0000374 => "STATUS_HEAP_CORRUPTION",
+        0xc0000409 => "STATUS_STACK_BUFFER_OVERRUN",
+        _ => return None,
+    })
+}
+
+/// A fixed-capacity, null-terminated wide string that silently truncates
+/// its contents. Used where heap allocation is not allowed.
+struct WideString {
+    buf: [u16; 512],
+    len: usize,
+}
+
+impl WideString {
+    const fn new() -> Self {
+        Self {
+            buf: [0; 512],
+            len: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Append a character, returning `false` if it doesn't fit.
+    fn push(&mut self, c: char) -> bool {
+        // Reserve space for a line break and a null terminator
+        let capacity = self.buf.len() - 2;
+        let mut units = [0u16; 2];
+        let units = c.encode_utf16(&mut units);
+        if self.len + units.len() > capacity {
+            return false;
+        }
+        self.buf[self.len..self.len + units.len()].copy_from_slice(units);
+        self.len += units.len();
+        true
+    }
+
+    /// Get the contents with a null terminator.
+    fn as_raw_c_wstr(&mut self) -> &[u16] {
+        self.buf[self.len] = 0;
+        &self.buf[..self.len + 1]
+    }
+
+    /// Get the contents with a null terminator and a line break.
+    fn as_c_wstr(&mut self) -> &[u16] {
+        let len = self.len.min(self.buf.len() - 2);
+        self.buf[len] = b'\n' as u16;
+        self.buf[len + 1] = 0;
+        &self.buf[..len + 2]
+    }
+}
+
+impl fmt::Write for WideString {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if !self.push(c) {
+                return Err(fmt::Error);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wide_string() {
+        let mut buf = WideString::new();
+        write!(buf, "🦄 {}", 42).unwrap();
+        assert_eq!(*buf.as_c_wstr(), *wchar::wch_c!("🦄 42\n"));
+    }
+
+    #[test]
+    fn test_wide_string_truncate() {
+        let mut buf = WideString::new();
+        for _ in 0..1000 {
+            let _ = write!(buf, "x");
+        }
+        let s = buf.as_c_wstr();
+        assert_eq!(s.len(), 512);
+        assert_eq!(s[509], b'x' as u16);
+        assert_eq!(s[510..], [b'\n' as u16, 0]);
+    }
+
+    #[test]
+    fn test_wide_string_raw() {
+        let mut buf = WideString::new();
+        assert!(buf.is_empty());
+        assert!(buf.push('🦄'));
+        assert_eq!(*buf.as_raw_c_wstr(), *wchar::wch_c!("🦄"));
+
+        for _ in 0..508 {
+            assert!(buf.push('x'));
+        }
+        assert!(!buf.push('x'));
+        assert_eq!(buf.as_raw_c_wstr().len(), 511);
+        buf.clear();
+        assert_eq!(*buf.as_raw_c_wstr(), [0]);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_write_registers() {
+        let mut context: CONTEXT = unsafe { std::mem::zeroed() };
+        context.Rip = 0x7ff6_1234_5678;
+        context.R15 = 0xf;
+        let mut lines = Vec::new();
+        write_registers(&mut WideString::new(), &context, |line| {
+            lines.push(String::from_utf16(line).unwrap())
+        });
+
+        assert_eq!(lines.len(), 3, "{:?}", lines);
+        assert!(
+            lines[0].starts_with(
+                "FATAL [windebug_logger] Registers: rip=0x00007ff612345678 rsp=0x0000000000000000 "
+            ),
+            "{:?}",
+            lines[0]
+        );
+        assert!(
+            lines[2].ends_with(" r15=0x000000000000000f\n\0"),
+            "{:?}",
+            lines[2]
+        );
+    }
+
+    #[test]
+    fn test_with_buffers_nested() {
+        // A nested call, e.g., from a fault while formatting, gets other
+        // buffers
+        let mut called = false;
+        with_buffers(|outer| {
+            let outer: *const Buffers = outer;
+            with_buffers(|inner| {
+                assert_ne!(outer, inner as *const Buffers);
+                called = true;
+            });
+        });
+        assert!(called);
+    }
+}
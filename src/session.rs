@@ -0,0 +1,89 @@
+//! Session IDs, which distinguish processes across restarts. See
+//! [`WinDebugLoggerBuilder::session_id`].
+//!
+//! [`WinDebugLoggerBuilder::session_id`]: crate::WinDebugLoggerBuilder::session_id
+use std::{
+    mem::MaybeUninit,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use winapi::um::{processthreadsapi, profileapi, sysinfoapi};
+
+/// The number of characters in a session ID.
+const LEN: usize = 6;
+
+/// The number of characters in a token returned by [`token`].
+pub const TOKEN_LEN: usize = 4;
+
+/// Characters that are hard to confuse with each other.
+const ALPHABET: &[u8; 32] = b"23456789abcdefghjkmnpqrstuvwxyz_";
+
+/// Generate a random session ID.
+///
+/// This is not cryptographically secure; it only needs to differ between
+/// processes with a high probability.
+pub fn generate() -> String {
+    encode(seed(), LEN)
+}
+
+/// Generate a short random token, which only needs to differ between the
+/// records written around the same time.
+pub fn token() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    token_from(seed(), COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+fn token_from(seed: u64, count: u64) -> String {
+    encode(seed ^ count.rotate_left(41), TOKEN_LEN)
+}
+
+fn seed() -> u64 {
+    let counter = unsafe {
+        let mut counter = MaybeUninit::zeroed();
+        profileapi::QueryPerformanceCounter(counter.as_mut_ptr());
+        *counter.assume_init().QuadPart() as u64
+    };
+    let tick_count = unsafe { sysinfoapi::GetTickCount() } as u64;
+    let pid = unsafe { processthreadsapi::GetCurrentProcessId() } as u64;
+
+    counter ^ (tick_count << 32) ^ pid.rotate_left(17)
+}
+
+fn encode(mut state: u64, len: usize) -> String {
+    (0..len)
+        .map(|_| ALPHABET[(splitmix64(&mut state) % 32) as usize] as char)
+        .collect()
+}
+
+/// SplitMix64, a simple pseudorandom number generator with good statistical
+/// properties.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let id = generate();
+        assert_eq!(id.len(), LEN);
+        assert!(id.bytes().all(|c| ALPHABET.contains(&c)), "{}", id);
+
+        assert_eq!(token().len(), TOKEN_LEN);
+    }
+
+    #[test]
+    fn test_token_from() {
+        // Tokens taken in quick succession share most of the seed, so they
+        // are told apart by the counter
+        let tokens: std::collections::HashSet<_> = (0..100)
+            .map(|count| token_from(0x1234_5678, count))
+            .collect();
+        assert_eq!(tokens.len(), 100);
+    }
+}
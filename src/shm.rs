@@ -0,0 +1,275 @@
+//! A ring buffer in named shared memory. See
+//! [`WinDebugLoggerBuilder::with_shared_memory_ring`].
+//!
+//! [`WinDebugLoggerBuilder::with_shared_memory_ring`]: crate::WinDebugLoggerBuilder::with_shared_memory_ring
+use log::Level;
+use std::{
+    io,
+    ptr::{self, null_mut},
+    sync::{
+        atomic::{fence, AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use winapi::{
+    shared::{ntdef::HANDLE, winerror::ERROR_ALREADY_EXISTS},
+    um::{errhandlingapi, handleapi, memoryapi, winnt},
+};
+
+use crate::{codecvt, DebugSink};
+
+/// Identifies the layout. The bytes `WDLR` read as a little-endian integer.
+const MAGIC: u32 = u32::from_le_bytes(*b"WDLR");
+const VERSION: u32 = 1;
+/// The offsets of the header fields.
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 4;
+const CAPACITY_OFFSET: usize = 8;
+const HEAD_OFFSET: usize = 16;
+const TAIL_OFFSET: usize = 24;
+/// The size of the header, which is followed by the data area.
+const HEADER_LEN: usize = 32;
+/// The length prefix marking the rest of the data area as unused.
+const WRAP_MARKER: u32 = u32::MAX;
+/// The smallest accepted capacity.
+const MIN_CAPACITY: usize = 256;
+
+/// Writes UTF-8 records to a ring buffer in a named file mapping.
+pub(crate) struct SharedMemorySink {
+    mapping: HANDLE,
+    view: *mut u8,
+    /// The size of the data area in bytes, a multiple of 4.
+    capacity: u64,
+    /// Serializes the writers in this process.
+    lock: Mutex<()>,
+}
+
+// The mapping and the view can be used from any thread, and writes to the
+// view are serialized by `lock`
+unsafe impl Send for SharedMemorySink {}
+unsafe impl Sync for SharedMemorySink {}
+
+impl SharedMemorySink {
+    /// Create or open the file mapping named `name` with a data area of
+    /// `capacity` bytes (rounded down to a multiple of 4).
+    pub fn open(name: &str, capacity: usize) -> io::Result<Self> {
+        let capacity = capacity & !3;
+        if !(MIN_CAPACITY..=u32::MAX as usize - HEADER_LEN).contains(&capacity) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid shared memory ring size",
+            ));
+        }
+        let wide_name = codecvt::str_to_c_wstr(name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid mapping name"))?;
+        let len = HEADER_LEN + capacity;
+
+        let mapping = unsafe {
+            memoryapi::CreateFileMappingW(
+                handleapi::INVALID_HANDLE_VALUE,
+                null_mut(),
+                winnt::PAGE_READWRITE,
+                0,
+                len as u32,
+                wide_name.as_ptr(),
+            )
+        };
+        if mapping.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let existed = unsafe { errhandlingapi::GetLastError() } == ERROR_ALREADY_EXISTS;
+
+        let view =
+            unsafe { memoryapi::MapViewOfFile(mapping, memoryapi::FILE_MAP_WRITE, 0, 0, len) };
+        if view.is_null() {
+            let e = io::Error::last_os_error();
+            unsafe { handleapi::CloseHandle(mapping) };
+            return Err(e);
+        }
+
+        let sink = Self {
+            mapping,
+            view: view as *mut u8,
+            capacity: capacity as u64,
+            lock: Mutex::new(()),
+        };
+        // Keep the records in a ring created by a previous writer with the
+        // same layout, e.g., before the application was restarted
+        let reuse = existed
+            && sink.read_u32(MAGIC_OFFSET) == MAGIC
+            && sink.read_u32(VERSION_OFFSET) == VERSION
+            && sink.read_u32(CAPACITY_OFFSET) as usize == capacity;
+        if !reuse {
+            sink.write_u32(VERSION_OFFSET, VERSION);
+            sink.write_u32(CAPACITY_OFFSET, capacity as u32);
+            sink.head().store(0, Ordering::Relaxed);
+            sink.tail().store(0, Ordering::Relaxed);
+            // Readers check the magic number last
+            fence(Ordering::Release);
+            sink.write_u32(MAGIC_OFFSET, MAGIC);
+        }
+        Ok(sink)
+    }
+
+    fn head(&self) -> &AtomicU64 {
+        unsafe { &*(self.view.add(HEAD_OFFSET) as *const AtomicU64) }
+    }
+
+    fn tail(&self) -> &AtomicU64 {
+        unsafe { &*(self.view.add(TAIL_OFFSET) as *const AtomicU64) }
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        let mut bytes = [0; 4];
+        unsafe { ptr::copy_nonoverlapping(self.view.add(offset), bytes.as_mut_ptr(), 4) };
+        u32::from_le_bytes(bytes)
+    }
+
+    fn write_u32(&self, offset: usize, value: u32) {
+        self.write_bytes(offset, &value.to_le_bytes());
+    }
+
+    fn write_bytes(&self, offset: usize, bytes: &[u8]) {
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), self.view.add(offset), bytes.len()) };
+    }
+
+    /// Get the offset in the view of the position `pos` in the ring.
+    fn offset(&self, pos: u64) -> usize {
+        HEADER_LEN + (pos % self.capacity) as usize
+    }
+
+    /// Append a record. Records that do not fit in the ring are rejected.
+    fn push(&self, payload: &[u8]) -> io::Result<()> {
+        let record_len = 4 + ((payload.len() as u64 + 3) & !3);
+        if record_len > self.capacity {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "line is too long for the shared memory ring",
+            ));
+        }
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut head = self.head().load(Ordering::Relaxed);
+        let remaining = self.capacity - head % self.capacity;
+        let wrap = record_len > remaining;
+        let end = head + record_len + if wrap { remaining } else { 0 };
+
+        // Drop the oldest records to make room, and let readers know before
+        // overwriting them
+        let mut tail = self.tail().load(Ordering::Relaxed);
+        if end - tail > self.capacity {
+            while end - tail > self.capacity {
+                tail += match self.read_u32(self.offset(tail)) {
+                    WRAP_MARKER => self.capacity - tail % self.capacity,
+                    len => 4 + ((u64::from(len) + 3) & !3),
+                };
+            }
+            self.tail().store(tail, Ordering::Relaxed);
+            fence(Ordering::Release);
+        }
+
+        if wrap {
+            self.write_u32(self.offset(head), WRAP_MARKER);
+            head += remaining;
+        }
+        let offset = self.offset(head);
+        self.write_u32(offset, payload.len() as u32);
+        self.write_bytes(offset + 4, payload);
+        let padding = record_len as usize - 4 - payload.len();
+        unsafe { ptr::write_bytes(self.view.add(offset + 4 + payload.len()), 0, padding) };
+        self.head().store(head + record_len, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl DebugSink for SharedMemorySink {
+    fn write(&self, _level: Level, line: &[u16]) -> io::Result<()> {
+        // Records are delimited by the framing
+        let line = line.strip_suffix(&[b'\n' as u16]).unwrap_or(line);
+        self.push(String::from_utf16_lossy(line).as_bytes())
+    }
+}
+
+impl Drop for SharedMemorySink {
+    fn drop(&mut self) {
+        unsafe {
+            memoryapi::UnmapViewOfFile(self.view as _);
+            handleapi::CloseHandle(self.mapping);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Read the records from `pos` as described in the documentation, and
+    /// return them with the position to continue from.
+    fn read(sink: &SharedMemorySink, mut pos: u64) -> (Vec<String>, u64) {
+        assert_eq!(sink.read_u32(MAGIC_OFFSET), MAGIC);
+        let head = sink.head().load(Ordering::Acquire);
+        pos = pos.max(sink.tail().load(Ordering::Acquire));
+        let mut records = Vec::new();
+        while pos < head {
+            let offset = sink.offset(pos);
+            match sink.read_u32(offset) {
+                WRAP_MARKER => pos += sink.capacity - pos % sink.capacity,
+                len => {
+                    let mut payload = vec![0; len as usize];
+                    unsafe {
+                        ptr::copy_nonoverlapping(
+                            sink.view.add(offset + 4),
+                            payload.as_mut_ptr(),
+                            payload.len(),
+                        )
+                    };
+                    fence(Ordering::Acquire);
+                    if sink.tail().load(Ordering::Relaxed) > pos {
+                        // Overwritten while reading
+                        pos = sink.tail().load(Ordering::Relaxed);
+                        continue;
+                    }
+                    records.push(String::from_utf8(payload).unwrap());
+                    pos += 4 + ((u64::from(len) + 3) & !3);
+                }
+            }
+        }
+        (records, pos)
+    }
+
+    fn write(sink: &SharedMemorySink, line: &str) {
+        let line: Vec<u16> = line.encode_utf16().collect();
+        sink.write(Level::Info, &line).unwrap();
+    }
+
+    #[test]
+    fn test_ring() {
+        let sink = SharedMemorySink::open("windebug_logger_test_ring", 258).unwrap();
+        assert_eq!(sink.capacity, 256);
+
+        write(&sink, "hello\n");
+        write(&sink, "wörld\n");
+        let (records, pos) = read(&sink, 0);
+        assert_eq!(records, ["hello", "wörld"]);
+        assert_eq!(pos, 24);
+
+        // 100 bytes each, so the third one wraps around and the first two
+        // are dropped
+        let line = |c: char| c.to_string().repeat(96);
+        write(&sink, &line('a'));
+        write(&sink, &line('b'));
+        let (records, pos) = read(&sink, pos);
+        assert_eq!(records, [line('a'), line('b')]);
+        write(&sink, &line('c'));
+        assert_eq!(sink.tail().load(Ordering::Relaxed), 124);
+        assert_eq!(sink.head().load(Ordering::Relaxed), 356);
+        let (records, _) = read(&sink, pos);
+        assert_eq!(records, [line('c')]);
+        let (records, _) = read(&sink, 0);
+        assert_eq!(records, [line('b'), line('c')]);
+
+        let long: String = "x".repeat(253);
+        let long: Vec<u16> = long.encode_utf16().collect();
+        assert!(sink.write(Level::Info, &long).is_err());
+    }
+}
@@ -0,0 +1,538 @@
+//! Destinations of formatted lines other than `OutputDebugStringW`.
+use log::{Level, LevelFilter};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    ops,
+    path::Path,
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use winapi::{
+    shared::ntdef::HANDLE,
+    um::{consoleapi, handleapi, processenv, winbase, wincon, winnt},
+};
+
+use crate::codecvt::{self, WideDisplay};
+
+/// A destination of formatted lines.
+///
+/// Sinks are attached by [`WinDebugLoggerBuilder::sink`] and receive the
+/// lines routed to [`Sinks::CUSTOM`].
+///
+/// [`WinDebugLoggerBuilder::sink`]: crate::WinDebugLoggerBuilder::sink
+pub trait DebugSink: Send + Sync {
+    /// Write a formatted line. `line` includes the trailing newline but not a
+    /// null terminator.
+    fn write(&self, level: Level, line: &[u16]) -> io::Result<()>;
+
+    /// Write a formatted line along with the record it was formatted from,
+    /// for sinks that store the fields of records separately. Defaults to
+    /// calling [`write`](Self::write).
+    ///
+    /// The lines that have no record at hand, such as those replayed by
+    /// [`WinDebugLoggerBuilder::debug_on_error`], are passed to `write`
+    /// instead.
+    ///
+    /// [`WinDebugLoggerBuilder::debug_on_error`]: crate::WinDebugLoggerBuilder::debug_on_error
+    fn write_record(&self, record: &log::Record<'_>, line: &[u16]) -> io::Result<()> {
+        self.write(record.level(), line)
+    }
+
+    /// Flush buffered lines, if any.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Get the name shown by [`sink_status`]. Defaults to `custom`.
+    ///
+    /// [`sink_status`]: crate::sink_status
+    fn name(&self) -> String {
+        "custom".to_owned()
+    }
+}
+
+/// A [`DebugSink`] calling a function pointer with each line. See
+/// [`WinDebugLogger::add_sink_fn`].
+///
+/// [`WinDebugLogger::add_sink_fn`]: crate::WinDebugLogger::add_sink_fn
+#[derive(Clone, Copy)]
+pub struct FnSink(pub fn(&[u16]));
+
+impl fmt::Debug for FnSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FnSink")
+            .field(&(self.0 as *const ()))
+            .finish()
+    }
+}
+
+impl DebugSink for FnSink {
+    fn write(&self, _level: Level, line: &[u16]) -> io::Result<()> {
+        (self.0)(line);
+        Ok(())
+    }
+}
+
+/// A set of sink kinds, used to route records by level. See
+/// [`WinDebugLoggerBuilder::route`].
+///
+/// [`WinDebugLoggerBuilder::route`]: crate::WinDebugLoggerBuilder::route
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sinks(u16);
+
+impl Sinks {
+    /// `OutputDebugStringW`.
+    pub const ODS: Self = Self(1 << 0);
+    /// The file attached by [`WinDebugLoggerBuilder::file`] or
+    /// [`WinDebugLoggerBuilder::file_writer`].
+    ///
+    /// [`WinDebugLoggerBuilder::file`]: crate::WinDebugLoggerBuilder::file
+    /// [`WinDebugLoggerBuilder::file_writer`]: crate::WinDebugLoggerBuilder::file_writer
+    pub const FILE: Self = Self(1 << 1);
+    /// The standard error stream. See [`WinDebugLoggerBuilder::stderr`].
+    ///
+    /// [`WinDebugLoggerBuilder::stderr`]: crate::WinDebugLoggerBuilder::stderr
+    pub const STDERR: Self = Self(1 << 2);
+    /// The Windows Event Log. See [`WinDebugLoggerBuilder::event_log`].
+    ///
+    /// [`WinDebugLoggerBuilder::event_log`]: crate::WinDebugLoggerBuilder::event_log
+    pub const EVENT_LOG: Self = Self(1 << 3);
+    /// A named pipe. See [`WinDebugLoggerBuilder::pipe`].
+    ///
+    /// [`WinDebugLoggerBuilder::pipe`]: crate::WinDebugLoggerBuilder::pipe
+    pub const PIPE: Self = Self(1 << 4);
+    /// The in-memory history. See [`WinDebugLoggerBuilder::ring_buffer`].
+    ///
+    /// [`WinDebugLoggerBuilder::ring_buffer`]: crate::WinDebugLoggerBuilder::ring_buffer
+    pub const RING_BUFFER: Self = Self(1 << 5);
+    /// The sinks attached by [`WinDebugLoggerBuilder::sink`].
+    ///
+    /// [`WinDebugLoggerBuilder::sink`]: crate::WinDebugLoggerBuilder::sink
+    pub const CUSTOM: Self = Self(1 << 6);
+    /// A shared-memory ring buffer. See
+    /// `WinDebugLoggerBuilder::with_shared_memory_ring`, which requires the
+    /// `shared-memory` feature.
+    pub const SHARED_MEMORY: Self = Self(1 << 7);
+
+    /// All sink kinds.
+    pub const ALL: Self = Self((1 << 8) - 1);
+
+    const NAMES: [(Self, &'static str); 8] = [
+        (Self::ODS, "ODS"),
+        (Self::FILE, "FILE"),
+        (Self::STDERR, "STDERR"),
+        (Self::EVENT_LOG, "EVENT_LOG"),
+        (Self::PIPE, "PIPE"),
+        (Self::RING_BUFFER, "RING_BUFFER"),
+        (Self::CUSTOM, "CUSTOM"),
+        (Self::SHARED_MEMORY, "SHARED_MEMORY"),
+    ];
+
+    /// The empty set.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Check if the set is empty.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Check if `self` contains all sink kinds in `other`.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Check if `self` and `other` have any sink kinds in common.
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl Default for Sinks {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl fmt::Debug for Sinks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("(empty)");
+        }
+        let mut first = true;
+        for &(kind, name) in Self::NAMES.iter() {
+            if self.contains(kind) {
+                if !first {
+                    f.write_str(" | ")?;
+                }
+                f.write_str(name)?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ops::BitOr for Sinks {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl ops::BitOrAssign for Sinks {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl ops::BitAnd for Sinks {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl ops::BitAndAssign for Sinks {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl ops::Not for Sinks {
+    type Output = Self;
+    fn not(self) -> Self {
+        Self(!self.0) & Self::ALL
+    }
+}
+
+/// The sink kinds each level is routed to, indexed by `Level as usize - 1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Routes([Sinks; 5]);
+
+impl Routes {
+    pub const fn new() -> Self {
+        Self([Sinks::ALL; 5])
+    }
+
+    /// Route records up to `level` to `sinks`, and records above `level`
+    /// away from `sinks`.
+    pub fn set(&mut self, level: LevelFilter, sinks: Sinks) {
+        for (i, route) in self.0.iter_mut().enumerate() {
+            if i < level as usize {
+                *route |= sinks;
+            } else {
+                *route &= !sinks;
+            }
+        }
+    }
+
+    pub fn get(&self, level: Level) -> Sinks {
+        self.0[level as usize - 1]
+    }
+}
+
+/// A sink attached to the logger, along with its kind and status.
+#[derive(Clone)]
+pub(crate) struct SinkEntry {
+    pub kind: Sinks,
+    pub name: String,
+    pub sink: Arc<dyn DebugSink>,
+    /// `false` if the last write failed. Shared by clones, which share the
+    /// sink as well.
+    pub ok: Arc<AtomicBool>,
+}
+
+impl SinkEntry {
+    pub fn new(kind: Sinks, name: String, sink: Arc<dyn DebugSink>) -> Self {
+        Self {
+            kind,
+            name,
+            sink,
+            ok: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Write a line to the sink, recording whether it succeeded.
+    pub fn write(
+        &self,
+        level: Level,
+        record: Option<&log::Record<'_>>,
+        line: &[u16],
+    ) -> io::Result<()> {
+        let result = match record {
+            Some(record) => self.sink.write_record(record, line),
+            None => self.sink.write(level, line),
+        };
+        self.ok.store(result.is_ok(), Ordering::Relaxed);
+        result
+    }
+}
+
+impl fmt::Debug for SinkEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SinkEntry")
+            .field("kind", &self.kind)
+            .field("name", &self.name)
+            .field("ok", &self.ok)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Writes UTF-8 lines to a `Write` implementation, such as a file.
+pub(crate) struct WriterSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl WriterSink {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+
+    /// Open `path` for appending.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+}
+
+impl DebugSink for WriterSink {
+    fn write(&self, _level: Level, line: &[u16]) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        write!(writer, "{}", WideDisplay(line))
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writer.flush()
+    }
+}
+
+/// Writes UTF-8 lines to the standard error stream.
+pub(crate) struct StderrSink {
+    /// Color lines with ANSI escape sequences.
+    color: bool,
+}
+
+impl StderrSink {
+    /// Construct a `StderrSink`, coloring lines if `color` is `true` and
+    /// the standard error stream is a console supporting ANSI escape
+    /// sequences.
+    pub fn new(color: bool) -> Self {
+        Self {
+            color: color && enable_virtual_terminal(),
+        }
+    }
+}
+
+impl DebugSink for StderrSink {
+    fn write(&self, level: Level, line: &[u16]) -> io::Result<()> {
+        let mut stderr = io::stderr().lock();
+        if self.color {
+            write_colored(&mut stderr, level, line)
+        } else {
+            write!(stderr, "{}", WideDisplay(line))
+        }
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// Write `line` in the color of `level`, resetting it before the newline.
+fn write_colored(out: &mut impl Write, level: Level, line: &[u16]) -> io::Result<()> {
+    let (line, newline) = match line.split_last() {
+        Some((&c, rest)) if c == u16::from(b'\n') => (rest, "\n"),
+        _ => (line, ""),
+    };
+    write!(
+        out,
+        "{}{}{}{}",
+        ansi_color(level),
+        WideDisplay(line),
+        ANSI_RESET,
+        newline
+    )
+}
+
+/// Get the ANSI escape sequence setting the color of `level`.
+fn ansi_color(level: Level) -> &'static str {
+    match level {
+        Level::Error => "\x1b[31m",
+        Level::Warn => "\x1b[33m",
+        Level::Info => "\x1b[32m",
+        Level::Debug => "\x1b[36m",
+        Level::Trace => "\x1b[90m",
+    }
+}
+
+/// Enable the processing of ANSI escape sequences by the console attached to
+/// the standard error stream. Returns `false` if the stream is not a console
+/// or the console does not support them (before Windows 10).
+fn enable_virtual_terminal() -> bool {
+    const VT: u32 = wincon::ENABLE_VIRTUAL_TERMINAL_PROCESSING;
+    unsafe {
+        let handle = processenv::GetStdHandle(winbase::STD_ERROR_HANDLE);
+        let mut mode = 0;
+        if handle.is_null()
+            || handle == handleapi::INVALID_HANDLE_VALUE
+            || consoleapi::GetConsoleMode(handle, &mut mode) == 0
+        {
+            return false;
+        }
+        mode & VT != 0 || consoleapi::SetConsoleMode(handle, mode | VT) != 0
+    }
+}
+
+/// Writes UTF-8 lines to a named pipe, (re)connecting on demand.
+pub(crate) struct PipeSink {
+    path: String,
+    pipe: Mutex<Option<File>>,
+}
+
+impl PipeSink {
+    pub fn new(name: &str) -> Self {
+        Self {
+            path: format!(r"\\.\pipe\{}", name),
+            pipe: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl DebugSink for PipeSink {
+    fn write(&self, _level: Level, line: &[u16]) -> io::Result<()> {
+        let mut pipe = self.pipe.lock().unwrap_or_else(|e| e.into_inner());
+        if pipe.is_none() {
+            *pipe = Some(OpenOptions::new().write(true).open(&self.path)?);
+        }
+
+        let result = write!(pipe.as_mut().unwrap(), "{}", WideDisplay(line));
+        if result.is_err() {
+            // Reconnect on the next write
+            *pipe = None;
+        }
+        result
+    }
+}
+
+/// Reports lines to the Windows Event Log.
+pub(crate) struct EventLogSink {
+    handle: HANDLE,
+}
+
+// `HANDLE` is a raw pointer, but event log handles can be used from any thread
+unsafe impl Send for EventLogSink {}
+unsafe impl Sync for EventLogSink {}
+
+impl EventLogSink {
+    /// Register an event source named `source` on the local computer.
+    pub fn register(source: &str) -> io::Result<Self> {
+        let source = codecvt::str_to_c_wstr(source)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid source name"))?;
+        let handle = unsafe { winbase::RegisterEventSourceW(null_mut(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { handle })
+    }
+}
+
+impl DebugSink for EventLogSink {
+    fn write(&self, level: Level, line: &[u16]) -> io::Result<()> {
+        let event_type = match level {
+            Level::Error => winnt::EVENTLOG_ERROR_TYPE,
+            Level::Warn => winnt::EVENTLOG_WARNING_TYPE,
+            _ => winnt::EVENTLOG_INFORMATION_TYPE,
+        };
+
+        // The Event Log terminates lines by itself
+        let line = line.strip_suffix(&[b'\n' as u16]).unwrap_or(line);
+        let mut string = Vec::with_capacity(line.len() + 1);
+        string.extend_from_slice(line);
+        string.push(0);
+        let mut strings = [string.as_ptr()];
+
+        let ok = unsafe {
+            winbase::ReportEventW(
+                self.handle,
+                event_type,
+                0, // category
+                0, // event identifier
+                null_mut(),
+                1,
+                0,
+                strings.as_mut_ptr(),
+                null_mut(),
+            )
+        };
+        if ok == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for EventLogSink {
+    fn drop(&mut self) {
+        unsafe { winbase::DeregisterEventSource(self.handle) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sinks() {
+        let sinks = Sinks::ODS | Sinks::FILE;
+        assert!(sinks.contains(Sinks::ODS));
+        assert!(!sinks.contains(Sinks::ODS | Sinks::PIPE));
+        assert!(sinks.intersects(Sinks::ODS | Sinks::PIPE));
+        assert_eq!(!sinks & sinks, Sinks::empty());
+        assert_eq!(format!("{:?}", sinks), "ODS | FILE");
+    }
+
+    #[test]
+    fn test_routes() {
+        let mut routes = Routes::new();
+        routes.set(LevelFilter::Info, Sinks::FILE);
+        routes.set(LevelFilter::Error, Sinks::EVENT_LOG);
+        assert_eq!(routes.get(Level::Error), Sinks::ALL);
+        assert_eq!(routes.get(Level::Warn), !Sinks::EVENT_LOG);
+        assert_eq!(routes.get(Level::Debug), !(Sinks::EVENT_LOG | Sinks::FILE));
+    }
+
+    #[test]
+    fn test_write_colored() {
+        let colored = |level, line: &str| {
+            let line: Vec<u16> = line.encode_utf16().collect();
+            let mut out = Vec::new();
+            write_colored(&mut out, level, &line).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            colored(Level::Error, "ERROR [app] oops\n"),
+            "\x1b[31mERROR [app] oops\x1b[0m\n"
+        );
+        assert_eq!(
+            colored(Level::Warn, "no newline"),
+            "\x1b[33mno newline\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use log::Level;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "stats")]
+use winapi::um::profileapi;
+
+/// Counts the records processed by the logger. See [`log_stats`].
+///
+/// [`log_stats`]: crate::log_stats
+#[derive(Debug)]
+pub(crate) struct Counters {
+    /// The number of records written, indexed by `Level as usize - 1`.
+    logged: [AtomicU64; 5],
+    /// The number of records suppressed by the duplicate message filter.
+    pub suppressed_duplicates: AtomicU64,
+    /// The number of records that could not be written.
+    pub write_failures: AtomicU64,
+    /// The number of records discarded by the thread filter.
+    pub thread_filtered: AtomicU64,
+    /// The number of records dropped by sampling.
+    pub sampled_out: AtomicU64,
+}
+
+impl Counters {
+    pub const fn new() -> Self {
+        Self {
+            logged: [
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+            ],
+            suppressed_duplicates: AtomicU64::new(0),
+            write_failures: AtomicU64::new(0),
+            thread_filtered: AtomicU64::new(0),
+            sampled_out: AtomicU64::new(0),
+        }
+    }
+
+    pub fn logged(&self, level: Level) -> &AtomicU64 {
+        &self.logged[level as usize - 1]
+    }
+
+    /// Get the total numbers of the records written and dropped.
+    pub fn totals(&self) -> (u64, u64) {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let logged = self.logged.iter().map(load).sum();
+        let dropped = [
+            &self.suppressed_duplicates,
+            &self.write_failures,
+            &self.thread_filtered,
+            &self.sampled_out,
+        ]
+        .iter()
+        .map(|&counter| load(counter))
+        .sum();
+        (logged, dropped)
+    }
+}
+
+/// Increment a counter.
+pub(crate) fn bump(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The time spent in `OutputDebugStringW` (or `OutputDebugStringA`). Returned
+/// by [`stats`]. Requires the `stats` feature.
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LogStats {
+    /// The number of calls. A record may take more than one call (see
+    /// [Long messages](crate#long-messages)), and a call may carry more than
+    /// one record (see [`WinDebugLoggerBuilder::coalesce`]).
+    ///
+    /// [`WinDebugLoggerBuilder::coalesce`]: crate::WinDebugLoggerBuilder::coalesce
+    pub call_count: u64,
+    /// The total duration of the calls in nanoseconds.
+    pub total_ns: u64,
+    /// The duration of the longest call in nanoseconds.
+    pub max_ns: u64,
+}
+
+#[cfg(feature = "stats")]
+static CALL_COUNT: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "stats")]
+static TOTAL_NS: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "stats")]
+static MAX_NS: AtomicU64 = AtomicU64::new(0);
+
+/// Get the time spent in `OutputDebugStringW` by all loggers of this crate
+/// so far, e.g., to check the overhead of logging or the effect of
+/// [`WinDebugLoggerBuilder::coalesce`]. Requires the `stats` feature.
+///
+/// ```
+/// # fn main() {
+/// windebug_logger::init().unwrap();
+/// log::info!("hello");
+///
+/// let stats = windebug_logger::stats();
+/// println!(
+///     "{} calls, {} ns on average",
+///     stats.call_count,
+///     stats.total_ns / stats.call_count.max(1)
+/// );
+/// # }
+/// ```
+///
+/// [`WinDebugLoggerBuilder::coalesce`]: crate::WinDebugLoggerBuilder::coalesce
+#[cfg(feature = "stats")]
+pub fn stats() -> LogStats {
+    LogStats {
+        call_count: CALL_COUNT.load(Ordering::Relaxed),
+        total_ns: TOTAL_NS.load(Ordering::Relaxed),
+        max_ns: MAX_NS.load(Ordering::Relaxed),
+    }
+}
+
+/// Call `f`, which calls `OutputDebugStringW`, measuring its duration for
+/// [`stats`].
+#[cfg(feature = "stats")]
+pub(crate) fn time_ods(f: impl FnOnce()) {
+    let start = performance_counter();
+    f();
+    let ticks = performance_counter().saturating_sub(start);
+    let ns = (u128::from(ticks) * 1_000_000_000 / u128::from(performance_frequency())) as u64;
+
+    CALL_COUNT.fetch_add(1, Ordering::Relaxed);
+    TOTAL_NS.fetch_add(ns, Ordering::Relaxed);
+    MAX_NS.fetch_max(ns, Ordering::Relaxed);
+}
+
+#[cfg(not(feature = "stats"))]
+#[inline(always)]
+pub(crate) fn time_ods(f: impl FnOnce()) {
+    f();
+}
+
+#[cfg(feature = "stats")]
+fn performance_counter() -> u64 {
+    unsafe {
+        let mut counter = std::mem::zeroed();
+        profileapi::QueryPerformanceCounter(&mut counter);
+        *counter.QuadPart() as u64
+    }
+}
+
+/// Get the frequency of the performance counter, which is fixed at boot.
+#[cfg(feature = "stats")]
+fn performance_frequency() -> u64 {
+    static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+    match FREQUENCY.load(Ordering::Relaxed) {
+        0 => {
+            let frequency = unsafe {
+                let mut frequency = std::mem::zeroed();
+                profileapi::QueryPerformanceFrequency(&mut frequency);
+                (*frequency.QuadPart() as u64).max(1)
+            };
+            FREQUENCY.store(frequency, Ordering::Relaxed);
+            frequency
+        }
+        frequency => frequency,
+    }
+}
+
+#[cfg(all(test, feature = "stats"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_ods() {
+        let before = stats();
+        time_ods(|| std::thread::sleep(std::time::Duration::from_millis(2)));
+        let after = stats();
+        assert!(after.call_count > before.call_count);
+        assert!(after.total_ns >= before.total_ns + 2_000_000, "{:?}", after);
+        assert!(after.max_ns >= 2_000_000, "{:?}", after);
+    }
+}
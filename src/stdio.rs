@@ -0,0 +1,196 @@
+//! A sink writing to the standard output or error stream. See
+//! [`StdioSink`].
+use log::Level;
+use std::{convert::TryInto, io, ptr::null_mut, sync::Mutex};
+use winapi::{
+    shared::ntdef::HANDLE,
+    um::{consoleapi, fileapi, handleapi, processenv, winbase},
+};
+
+use crate::{codecvt::WideDisplay, DebugSink};
+
+/// The maximum number of UTF-16 code units passed to a `WriteConsoleW` call.
+/// Older versions of Windows fail calls with large buffers.
+const MAX_CONSOLE_WRITE: usize = 8192;
+
+/// A [`DebugSink`] writing lines to the standard output or error stream, in
+/// the same format as the debug output, e.g., for console programs.
+///
+/// Whether the stream is a console is determined when the sink is
+/// constructed. Lines are written to a console by `WriteConsoleW`, which
+/// shows all Unicode characters regardless of the console's code page, and
+/// to a file or a pipe by `WriteFile`, encoded in UTF-8.
+///
+/// ```no_run
+/// use windebug_logger::{stdio::StdioSink, WinDebugLoggerBuilder};
+///
+/// # fn main() {
+/// WinDebugLoggerBuilder::new()
+///     .sink(StdioSink::stdout())
+///     .init()
+///     .unwrap();
+/// # }
+/// ```
+///
+/// Unlike [`WinDebugLoggerBuilder::stderr`], this writes to the handle
+/// directly, bypassing the buffer and the lock of [`std::io::Stdout`] or
+/// [`std::io::Stderr`].
+///
+/// [`WinDebugLoggerBuilder::stderr`]: crate::WinDebugLoggerBuilder::stderr
+#[derive(Debug)]
+pub struct StdioSink {
+    handle: HANDLE,
+    name: &'static str,
+    console: bool,
+    /// Held while a line is written so that lines written in more than one
+    /// call are not interleaved.
+    lock: Mutex<()>,
+}
+
+// The standard handles can be used from any thread
+unsafe impl Send for StdioSink {}
+unsafe impl Sync for StdioSink {}
+
+impl StdioSink {
+    /// Construct a `StdioSink` writing to the standard output stream
+    /// (`STD_OUTPUT_HANDLE`).
+    pub fn stdout() -> Self {
+        Self::new(winbase::STD_OUTPUT_HANDLE, "stdout")
+    }
+
+    /// Construct a `StdioSink` writing to the standard error stream
+    /// (`STD_ERROR_HANDLE`).
+    pub fn stderr() -> Self {
+        Self::new(winbase::STD_ERROR_HANDLE, "stderr")
+    }
+
+    fn new(std_handle: u32, name: &'static str) -> Self {
+        let handle = unsafe { processenv::GetStdHandle(std_handle) };
+        let mut mode = 0;
+        let console = !handle.is_null()
+            && handle != handleapi::INVALID_HANDLE_VALUE
+            && unsafe { consoleapi::GetConsoleMode(handle, &mut mode) } != 0;
+        Self {
+            handle,
+            name,
+            console,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Check if the stream is a console.
+    pub fn is_console(&self) -> bool {
+        self.console
+    }
+
+    fn write_console(&self, line: &[u16]) -> io::Result<()> {
+        for chunk in console_chunks(line) {
+            let mut rest = chunk;
+            while !rest.is_empty() {
+                let mut written = 0;
+                let ok = unsafe {
+                    consoleapi::WriteConsoleW(
+                        self.handle,
+                        rest.as_ptr() as _,
+                        rest.len() as u32,
+                        &mut written,
+                        null_mut(),
+                    )
+                };
+                if ok == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if written == 0 {
+                    return Err(io::ErrorKind::WriteZero.into());
+                }
+                rest = &rest[(written as usize).min(rest.len())..];
+            }
+        }
+        Ok(())
+    }
+
+    fn write_file(&self, line: &[u16]) -> io::Result<()> {
+        let bytes = WideDisplay(line).to_string().into_bytes();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            let len: u32 = rest.len().try_into().unwrap_or(u32::MAX);
+            let mut written = 0;
+            let ok = unsafe {
+                fileapi::WriteFile(
+                    self.handle,
+                    rest.as_ptr() as _,
+                    len,
+                    &mut written,
+                    null_mut(),
+                )
+            };
+            if ok == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            rest = &rest[(written as usize).min(rest.len())..];
+        }
+        Ok(())
+    }
+}
+
+impl DebugSink for StdioSink {
+    fn write(&self, _level: Level, line: &[u16]) -> io::Result<()> {
+        if self.handle.is_null() || self.handle == handleapi::INVALID_HANDLE_VALUE {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the standard stream is not available",
+            ));
+        }
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if self.console {
+            self.write_console(line)
+        } else {
+            self.write_file(line)
+        }
+    }
+
+    fn name(&self) -> String {
+        self.name.to_owned()
+    }
+}
+
+/// Split `line` into chunks of at most `MAX_CONSOLE_WRITE` code units
+/// without splitting surrogate pairs.
+fn console_chunks(mut line: &[u16]) -> impl Iterator<Item = &[u16]> {
+    std::iter::from_fn(move || {
+        if line.is_empty() {
+            return None;
+        }
+        let mut len = line.len().min(MAX_CONSOLE_WRITE);
+        if len < line.len() && (0xD800..0xDC00).contains(&line[len - 1]) {
+            len -= 1;
+        }
+        let (chunk, rest) = line.split_at(len);
+        line = rest;
+        Some(chunk)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_console_chunks() {
+        let line: Vec<u16> = "a"
+            .repeat(MAX_CONSOLE_WRITE - 1)
+            .chars()
+            .chain("\u{1F984}b".chars())
+            .collect::<String>()
+            .encode_utf16()
+            .collect();
+        let chunks: Vec<_> = console_chunks(&line).collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), MAX_CONSOLE_WRITE - 1);
+        assert_eq!(chunks[1], &line[MAX_CONSOLE_WRITE - 1..]);
+        assert_eq!(console_chunks(&[]).count(), 0);
+    }
+}
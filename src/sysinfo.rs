@@ -0,0 +1,202 @@
+//! Queries about the process and the system, used to describe the
+//! environment in the log.
+use std::{
+    convert::TryInto,
+    mem::{size_of, transmute, zeroed},
+    path::Path,
+    ptr::null_mut,
+    sync::OnceLock,
+};
+use winapi::{
+    shared::{minwindef::HMODULE, ntdef::NTSTATUS},
+    um::{
+        handleapi, libloaderapi, processenv, processthreadsapi, psapi, sysinfoapi, tlhelp32, winnt,
+        winnt::OSVERSIONINFOW,
+    },
+};
+
+/// Get the current directory of the process.
+pub fn current_directory() -> Option<String> {
+    let mut buf = Vec::<u16>::new();
+    loop {
+        let capacity: u32 = buf.capacity().try_into().ok()?;
+        let len = unsafe { processenv::GetCurrentDirectoryW(capacity, buf.as_mut_ptr()) };
+        if len == 0 {
+            return None;
+        } else if len < capacity {
+            unsafe { buf.set_len(len as usize) };
+            return Some(String::from_utf16_lossy(&buf));
+        }
+        // `len` is the required buffer size including the null terminator
+        buf.reserve(len as usize);
+    }
+}
+
+/// Get the full path of the specified module. `None` (a null pointer) specifies
+/// the executable of the current process.
+pub fn module_file_name(module: HMODULE) -> Option<String> {
+    let mut buf = Vec::<u16>::with_capacity(260);
+    loop {
+        let capacity: u32 = buf.capacity().try_into().ok()?;
+        let len = unsafe { libloaderapi::GetModuleFileNameW(module, buf.as_mut_ptr(), capacity) };
+        if len == 0 {
+            return None;
+        } else if len < capacity {
+            unsafe { buf.set_len(len as usize) };
+            return Some(String::from_utf16_lossy(&buf));
+        }
+        // The path was truncated
+        buf.reserve(buf.capacity() * 2);
+    }
+}
+
+/// Get the path of the executable of the current process.
+pub fn executable_path() -> Option<String> {
+    module_file_name(null_mut())
+}
+
+/// Get the file stem of the module (DLL or executable) containing this
+/// crate, e.g., `plugin` for `C:\App\plugin.dll`. The result is cached.
+pub fn own_module_name() -> Option<&'static str> {
+    static NAME: OnceLock<Option<String>> = OnceLock::new();
+    NAME.get_or_init(|| {
+        let mut module = null_mut();
+        let ok = unsafe {
+            libloaderapi::GetModuleHandleExW(
+                libloaderapi::GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS
+                    | libloaderapi::GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+                own_module_name as *const u16,
+                &mut module,
+            )
+        };
+        if ok == 0 {
+            return None;
+        }
+        let path = module_file_name(module)?;
+        Some(Path::new(&path).file_stem()?.to_string_lossy().into_owned())
+    })
+    .as_deref()
+}
+
+/// Get the version of the operating system as `(major, minor, build)`.
+///
+/// This uses `RtlGetVersion`, which unlike `GetVersionExW` is not subject to
+/// the compatibility shims applied to applications without a manifest, and
+/// falls back to `GetVersionExW` if it is unavailable.
+pub fn os_version() -> Option<(u32, u32, u32)> {
+    type RtlGetVersionFn = unsafe extern "system" fn(*mut OSVERSIONINFOW) -> NTSTATUS;
+
+    unsafe {
+        let mut info: OSVERSIONINFOW = zeroed();
+        info.dwOSVersionInfoSize = size_of::<OSVERSIONINFOW>() as u32;
+
+        let ntdll = libloaderapi::GetModuleHandleW(wchar::wch_c!("ntdll.dll").as_ptr());
+        let rtl_get_version = if ntdll.is_null() {
+            null_mut()
+        } else {
+            libloaderapi::GetProcAddress(ntdll, b"RtlGetVersion\0".as_ptr() as _)
+        };
+
+        let ok = if rtl_get_version.is_null() {
+            sysinfoapi::GetVersionExW(&mut info) != 0
+        } else {
+            let rtl_get_version: RtlGetVersionFn = transmute(rtl_get_version);
+            rtl_get_version(&mut info) >= 0
+        };
+
+        if ok {
+            Some((info.dwMajorVersion, info.dwMinorVersion, info.dwBuildNumber))
+        } else {
+            None
+        }
+    }
+}
+
+/// Get the current time in UTC as an ISO 8601 string, e.g.,
+/// `2020-01-02T03:04:05.678Z`, regardless of the timestamp settings.
+#[cfg(all(feature = "timestamps", not(feature = "no-timestamps")))]
+pub fn utc_now() -> String {
+    let mut time = unsafe { zeroed() };
+    unsafe { sysinfoapi::GetSystemTime(&mut time) };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        time.wYear,
+        time.wMonth,
+        time.wDay,
+        time.wHour,
+        time.wMinute,
+        time.wSecond,
+        time.wMilliseconds
+    )
+}
+
+/// Get the working set size of the current process in bytes.
+pub fn working_set_size() -> Option<usize> {
+    unsafe {
+        let mut counters: psapi::PROCESS_MEMORY_COUNTERS = zeroed();
+        let size = size_of::<psapi::PROCESS_MEMORY_COUNTERS>() as u32;
+        counters.cb = size;
+        let process = processthreadsapi::GetCurrentProcess();
+        if psapi::GetProcessMemoryInfo(process, &mut counters, size) != 0 {
+            Some(counters.WorkingSetSize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Get the number of handles open in the current process.
+pub fn handle_count() -> Option<u32> {
+    let mut count = 0;
+    let process = unsafe { processthreadsapi::GetCurrentProcess() };
+    if unsafe { processthreadsapi::GetProcessHandleCount(process, &mut count) } != 0 {
+        Some(count)
+    } else {
+        None
+    }
+}
+
+/// Get the number of threads in the current process.
+///
+/// There's no direct way to get it, so this walks a snapshot of the
+/// processes in the system, which takes a while.
+pub fn thread_count() -> Option<u32> {
+    unsafe {
+        let snapshot = tlhelp32::CreateToolhelp32Snapshot(tlhelp32::TH32CS_SNAPPROCESS, 0);
+        if snapshot == handleapi::INVALID_HANDLE_VALUE {
+            return None;
+        }
+        let process_id = processthreadsapi::GetCurrentProcessId();
+        let mut entry: tlhelp32::PROCESSENTRY32W = zeroed();
+        entry.dwSize = size_of::<tlhelp32::PROCESSENTRY32W>() as u32;
+        let mut count = None;
+        let mut ok = tlhelp32::Process32FirstW(snapshot, &mut entry) != 0;
+        while ok {
+            if entry.th32ProcessID == process_id {
+                count = Some(entry.cntThreads);
+                break;
+            }
+            ok = tlhelp32::Process32NextW(snapshot, &mut entry) != 0;
+        }
+        handleapi::CloseHandle(snapshot);
+        count
+    }
+}
+
+/// Get the name of the processor architecture of the operating system, which
+/// may differ from that of the current process under WOW64.
+pub fn native_architecture() -> Option<&'static str> {
+    let arch = unsafe {
+        let mut info: sysinfoapi::SYSTEM_INFO = zeroed();
+        sysinfoapi::GetNativeSystemInfo(&mut info);
+        info.u.s().wProcessorArchitecture
+    };
+    match arch {
+        winnt::PROCESSOR_ARCHITECTURE_INTEL => Some("x86"),
+        winnt::PROCESSOR_ARCHITECTURE_AMD64 => Some("x86_64"),
+        winnt::PROCESSOR_ARCHITECTURE_ARM => Some("arm"),
+        winnt::PROCESSOR_ARCHITECTURE_ARM64 => Some("aarch64"),
+        winnt::PROCESSOR_ARCHITECTURE_IA64 => Some("ia64"),
+        _ => None,
+    }
+}
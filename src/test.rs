@@ -0,0 +1,137 @@
+//! Support for tests that exercise different configurations of the global
+//! logger. Requires the `test-util` feature.
+//!
+//! `log::set_logger` succeeds only once per process, so a logger installed
+//! by [`init`](crate::init) or [`WinDebugLoggerBuilder::init`] stays for the
+//! rest of the test binary. Instead, [`install`] installs a shim logger
+//! once and makes it forward to a new [`WinDebugLogger`] each time it's
+//! called, and [`reset`] removes it. [`global`](crate::global) and the
+//! functions built on it, such as [`enabled_for`](crate::enabled_for), see
+//! the logger installed by `install`.
+//!
+//! The logger is process-wide, so tests calling these functions must not
+//! run concurrently with each other, e.g., by holding a shared
+//! `static LOCK: Mutex<()>` or by running with `--test-threads=1`.
+//!
+//! ```
+//! # fn main() {
+//! use windebug_logger::WinDebugLoggerBuilder;
+//!
+//! windebug_logger::test::install(WinDebugLoggerBuilder::new().level(log::LevelFilter::Warn));
+//! assert!(!log::log_enabled!(log::Level::Info));
+//!
+//! windebug_logger::test::install(WinDebugLoggerBuilder::new());
+//! assert!(log::log_enabled!(log::Level::Info));
+//!
+//! windebug_logger::test::reset();
+//! assert!(!log::log_enabled!(log::Level::Error));
+//! # }
+//! ```
+use log::LevelFilter;
+use std::sync::{Once, RwLock};
+
+use crate::{WinDebugLogger, WinDebugLoggerBuilder};
+
+/// The logger installed by `install`.
+static CURRENT: RwLock<Option<&'static WinDebugLogger>> = RwLock::new(None);
+
+/// The logger installed with `log::set_logger`, which forwards to `CURRENT`.
+struct Shim;
+
+impl log::Log for Shim {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        current().is_some_and(|logger| logger.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if let Some(logger) = current() {
+            logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(logger) = current() {
+            logger.flush();
+        }
+    }
+}
+
+/// Get the logger installed by `install`, if any.
+pub(crate) fn current() -> Option<&'static WinDebugLogger> {
+    *CURRENT.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Make the global logger forward to a new logger configured by `builder`,
+/// replacing (and shutting down) the one installed by the previous call,
+/// and return the new logger.
+///
+/// The messages enabled by [`WinDebugLoggerBuilder::banner`] and the like
+/// are logged as if by [`WinDebugLoggerBuilder::init`]. Each call leaks a
+/// small amount of memory, which is acceptable in tests.
+///
+/// # Panics
+///
+/// Panics if a logger other than the shim is already installed as the
+/// global logger.
+pub fn install(builder: WinDebugLoggerBuilder) -> &'static WinDebugLogger {
+    static SHIM: Shim = Shim;
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        log::set_logger(&SHIM).expect("another logger is already installed");
+    });
+
+    let logger: &'static WinDebugLogger =
+        Box::leak(Box::new(WinDebugLogger::from_builder(&builder)));
+    let previous = CURRENT
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace(logger);
+    if let Some(previous) = previous {
+        previous.shutdown();
+    }
+    crate::activate(logger);
+    builder.announce(logger);
+    logger
+}
+
+/// Shut down and remove the logger installed by [`install`], if any. The
+/// global logger discards all records afterwards.
+pub fn reset() {
+    let previous = CURRENT.write().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(previous) = previous {
+        previous.shutdown();
+    }
+    log::set_max_level(LevelFilter::Off);
+}
+
+/// Serialize the crate's own tests that change `log`'s global state.
+#[cfg(test)]
+pub(crate) fn lock() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install() {
+        let _guard = lock();
+
+        let logger = install(WinDebugLoggerBuilder::new().level(LevelFilter::Warn));
+        assert!(std::ptr::eq(crate::global().unwrap(), logger));
+        assert!(!crate::enabled_for("app", log::Level::Info));
+        assert!(crate::enabled_for("app", log::Level::Warn));
+
+        let logger = install(WinDebugLoggerBuilder::new().ring_buffer(4));
+        log::info!(target: "app", "hello");
+        let history = logger.history.as_ref().unwrap().snapshot();
+        assert_eq!(history.len(), 1);
+        assert!(history[0].ends_with("INFO  [app] hello\n"), "{:?}", history);
+
+        reset();
+        assert!(crate::global().is_none());
+        assert!(!crate::enabled_for("app", log::Level::Error));
+    }
+}
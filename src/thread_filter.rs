@@ -0,0 +1,247 @@
+//! Restricting logging to specific threads. See
+//! [`WinDebugLoggerBuilder::only_threads`].
+//!
+//! [`WinDebugLoggerBuilder::only_threads`]: crate::WinDebugLoggerBuilder::only_threads
+use std::{
+    cell::Cell,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+};
+use winapi::um::processthreadsapi;
+
+/// Selects the threads whose records are logged. See
+/// [`WinDebugLoggerBuilder::only_threads`].
+///
+/// [`WinDebugLoggerBuilder::only_threads`]: crate::WinDebugLoggerBuilder::only_threads
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum ThreadFilter {
+    /// Only the main thread, which is the thread marked by
+    /// [`mark_main_thread`] or, if none is marked, the thread that
+    /// constructed the first logger with a thread filter (e.g., by calling
+    /// [`WinDebugLoggerBuilder::init`]).
+    ///
+    /// [`WinDebugLoggerBuilder::init`]: crate::WinDebugLoggerBuilder::init
+    MainThreadOnly,
+    /// Only the threads with the given OS thread IDs (as returned by
+    /// `GetCurrentThreadId`).
+    Ids(Vec<u32>),
+    /// Only the threads whose names (as given by [`std::thread::Builder::name`])
+    /// satisfy the predicate. See [`ThreadFilter::name`].
+    Name(Arc<ThreadNameFn>),
+}
+
+/// The signature of the predicate of [`ThreadFilter::Name`].
+pub type ThreadNameFn = dyn Fn(Option<&str>) -> bool + Send + Sync;
+
+impl ThreadFilter {
+    /// Construct a [`ThreadFilter::Name`].
+    ///
+    /// ```
+    /// use windebug_logger::ThreadFilter;
+    ///
+    /// let filter = ThreadFilter::name(|name| name.is_some_and(|name| name.starts_with("ui")));
+    /// ```
+    pub fn name(predicate: impl Fn(Option<&str>) -> bool + Send + Sync + 'static) -> Self {
+        Self::Name(Arc::new(predicate))
+    }
+
+    /// Check if the current thread is selected.
+    fn matches_current(&self) -> bool {
+        match self {
+            Self::MainThreadOnly => {
+                let main_thread = MAIN_THREAD.load(Ordering::Relaxed);
+                main_thread != 0 && main_thread == current_thread_id()
+            }
+            Self::Ids(ids) => ids.contains(&current_thread_id()),
+            Self::Name(predicate) => predicate(std::thread::current().name()),
+        }
+    }
+}
+
+impl fmt::Debug for ThreadFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MainThreadOnly => f.write_str("MainThreadOnly"),
+            Self::Ids(ids) => f.debug_tuple("Ids").field(ids).finish(),
+            Self::Name(_) => f.write_str("Name(..)"),
+        }
+    }
+}
+
+/// The ID of the main thread, or `0` if not known yet.
+static MAIN_THREAD: AtomicU32 = AtomicU32::new(0);
+
+/// Incremented whenever the result of a thread filter might change, which
+/// invalidates `CACHE` on all threads.
+static GENERATION: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    /// The address of the last `ThreadFilterState` evaluated on the current
+    /// thread, the `GENERATION` at that time, and the result.
+    static CACHE: Cell<(usize, usize, bool)> = const { Cell::new((0, 0, true)) };
+}
+
+fn current_thread_id() -> u32 {
+    unsafe { processthreadsapi::GetCurrentThreadId() }
+}
+
+fn invalidate() {
+    GENERATION.fetch_add(1, Ordering::Release);
+}
+
+/// Mark the current thread as the main thread for
+/// [`ThreadFilter::MainThreadOnly`], replacing the thread marked before.
+///
+/// ```
+/// // On the UI thread
+/// windebug_logger::mark_main_thread();
+/// ```
+pub fn mark_main_thread() {
+    MAIN_THREAD.store(current_thread_id(), Ordering::Relaxed);
+    invalidate();
+}
+
+/// The thread filter of a logger, with the result cached per thread.
+pub(crate) struct ThreadFilterState {
+    filter: RwLock<Option<ThreadFilter>>,
+    /// Whether `filter` is `Some`, to skip the cache lookup otherwise.
+    active: AtomicBool,
+}
+
+impl ThreadFilterState {
+    pub const fn empty() -> Self {
+        Self {
+            filter: RwLock::new(None),
+            active: AtomicBool::new(false),
+        }
+    }
+
+    pub fn new(filter: Option<ThreadFilter>) -> Self {
+        if filter.is_some() {
+            // Make this thread the main thread unless one is known
+            let _ = MAIN_THREAD.compare_exchange(
+                0,
+                current_thread_id(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+            // A previous `ThreadFilterState` at the same address might have
+            // left cached results
+            invalidate();
+        }
+        Self {
+            active: AtomicBool::new(filter.is_some()),
+            filter: RwLock::new(filter),
+        }
+    }
+
+    /// Replace the filter.
+    pub fn set(&self, filter: Option<ThreadFilter>) {
+        let mut guard = self.filter.write().unwrap_or_else(|e| e.into_inner());
+        self.active.store(filter.is_some(), Ordering::Relaxed);
+        *guard = filter;
+        drop(guard);
+        invalidate();
+    }
+
+    /// Get the filter.
+    pub fn get(&self) -> Option<ThreadFilter> {
+        self.filter
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Check if the current thread passes the filter.
+    #[inline]
+    pub fn allows_current(&self) -> bool {
+        !self.active.load(Ordering::Relaxed) || self.allows_current_cached()
+    }
+
+    fn allows_current_cached(&self) -> bool {
+        let key = self as *const Self as usize;
+        let generation = GENERATION.load(Ordering::Acquire);
+        CACHE
+            .try_with(|cache| {
+                let (cached_key, cached_generation, allowed) = cache.get();
+                if cached_key == key && cached_generation == generation {
+                    return allowed;
+                }
+                let allowed = self.evaluate();
+                cache.set((key, generation, allowed));
+                allowed
+            })
+            // The thread-local is being destroyed
+            .unwrap_or_else(|_| self.evaluate())
+    }
+
+    fn evaluate(&self) -> bool {
+        match &*self.filter.read().unwrap_or_else(|e| e.into_inner()) {
+            Some(filter) => filter.matches_current(),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Debug for ThreadFilterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.get(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids() {
+        let state = ThreadFilterState::new(Some(ThreadFilter::Ids(vec![current_thread_id()])));
+        assert!(state.allows_current());
+        assert!(!std::thread::scope(|s| s
+            .spawn(|| state.allows_current())
+            .join()
+            .unwrap()));
+
+        // The cached result is recomputed
+        state.set(Some(ThreadFilter::Ids(vec![])));
+        assert!(!state.allows_current());
+        state.set(None);
+        assert!(state.allows_current());
+    }
+
+    #[test]
+    fn test_name() {
+        let state = ThreadFilterState::new(Some(ThreadFilter::name(|name| name == Some("ui"))));
+        let allows_on = |name: &str| {
+            std::thread::scope(|s| {
+                std::thread::Builder::new()
+                    .name(name.to_owned())
+                    .spawn_scoped(s, || state.allows_current())
+                    .unwrap()
+                    .join()
+                    .unwrap()
+            })
+        };
+        assert!(allows_on("ui"));
+        assert!(!allows_on("worker"));
+    }
+
+    #[test]
+    fn test_main_thread_only() {
+        let state = ThreadFilterState::new(Some(ThreadFilter::MainThreadOnly));
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                mark_main_thread();
+                assert!(state.allows_current());
+            });
+        });
+        assert!(!state.allows_current());
+
+        mark_main_thread();
+        assert!(state.allows_current());
+    }
+}
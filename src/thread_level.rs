@@ -0,0 +1,110 @@
+//! Per-thread level overrides. See [`WinDebugLogger::enable_thread_override`].
+//!
+//! [`WinDebugLogger::enable_thread_override`]: crate::WinDebugLogger::enable_thread_override
+use log::{Level, LevelFilter};
+use std::{
+    cell::Cell,
+    marker::PhantomData,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{global, level_filter_from_usize};
+
+thread_local! {
+    /// The level filter overriding the logger's on the current thread.
+    static THREAD_LEVEL: Cell<Option<LevelFilter>> = const { Cell::new(None) };
+}
+
+/// The number of active overrides for each level, indexed by
+/// `Level as usize`. Used to raise `log`'s global filter while any of them is
+/// active.
+static ACTIVE: [AtomicUsize; 6] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/// Get the level filter overriding the logger's on the current thread.
+#[inline]
+pub(crate) fn current() -> Option<LevelFilter> {
+    THREAD_LEVEL.try_with(Cell::get).ok().flatten()
+}
+
+/// Get the most verbose level filter of the overrides active on any thread.
+pub(crate) fn max_active() -> LevelFilter {
+    (1..ACTIVE.len())
+        .rev()
+        .find(|&i| ACTIVE[i].load(Ordering::Relaxed) > 0)
+        .map_or(LevelFilter::Off, level_filter_from_usize)
+}
+
+/// Restores the previous per-thread level override when dropped. Returned by
+/// [`WinDebugLogger::enable_thread_override`].
+///
+/// [`WinDebugLogger::enable_thread_override`]: crate::WinDebugLogger::enable_thread_override
+#[must_use = "the override is removed when the guard is dropped"]
+#[derive(Debug)]
+pub struct ThreadLevelGuard {
+    level: Level,
+    previous: Option<LevelFilter>,
+    /// The guard must be dropped on the thread that created it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ThreadLevelGuard {
+    pub(crate) fn new(level: Level) -> Self {
+        let previous = THREAD_LEVEL.with(|cell| cell.replace(Some(level.to_level_filter())));
+        ACTIVE[level as usize].fetch_add(1, Ordering::Relaxed);
+        update_max_level();
+        Self {
+            level,
+            previous,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for ThreadLevelGuard {
+    fn drop(&mut self) {
+        // The thread-local may already be destroyed if the guard is dropped
+        // during thread exit
+        let _ = THREAD_LEVEL.try_with(|cell| cell.set(self.previous));
+        ACTIVE[self.level as usize].fetch_sub(1, Ordering::Relaxed);
+        update_max_level();
+    }
+}
+
+fn update_max_level() {
+    if let Some(logger) = global() {
+        logger.update_max_level();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_overrides() {
+        assert_eq!(current(), None);
+        {
+            let _outer = ThreadLevelGuard::new(Level::Debug);
+            assert_eq!(current(), Some(LevelFilter::Debug));
+            assert!(max_active() >= LevelFilter::Debug);
+            {
+                let _inner = ThreadLevelGuard::new(Level::Error);
+                assert_eq!(current(), Some(LevelFilter::Error));
+            }
+            assert_eq!(current(), Some(LevelFilter::Debug));
+
+            // Overrides are per thread
+            std::thread::spawn(|| assert_eq!(current(), None))
+                .join()
+                .unwrap();
+        }
+        assert_eq!(current(), None);
+    }
+}
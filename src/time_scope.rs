@@ -0,0 +1,171 @@
+//! Timing of scopes. See [`time_scope`].
+use log::Level;
+use std::{borrow::Cow, cell::Cell, fmt, marker::PhantomData, panic::Location, time::Instant};
+
+thread_local! {
+    /// The number of enabled `ScopeTimer`s alive on the current thread.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Get the number of enabled [`time_scope`]s the current thread is in. Used
+/// by [`WinDebugLoggerBuilder::span_indent`].
+///
+/// [`WinDebugLoggerBuilder::span_indent`]: crate::WinDebugLoggerBuilder::span_indent
+pub(crate) fn depth() -> usize {
+    DEPTH.with(Cell::get)
+}
+
+fn enter() {
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+}
+
+fn exit() {
+    DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+}
+
+/// Measure the time until the returned guard is dropped, and then log
+/// `{label} took {N} ms` at `level`.
+///
+/// The record is logged through the `log` crate's global logger with the
+/// target `windebug_logger` and the caller's source location. If `level` is
+/// disabled when this function is called, the guard does nothing, not even
+/// reading the clock.
+///
+/// The records logged by the same thread while the guard is alive are
+/// indented if [`WinDebugLoggerBuilder::span_indent`] is set. The guard
+/// should therefore be dropped by the thread that created it.
+///
+/// ```
+/// # fn main() {
+/// windebug_logger::init().unwrap();
+///
+/// {
+///     let _timer = windebug_logger::time_scope(log::Level::Debug, "loading");
+///     // ...
+/// } // Logs "loading took 0.012 ms"
+/// # }
+/// ```
+///
+/// [`WinDebugLoggerBuilder::span_indent`]: crate::WinDebugLoggerBuilder::span_indent
+#[track_caller]
+pub fn time_scope(level: Level, label: impl Into<Cow<'static, str>>) -> ScopeTimer {
+    let inner = if log::log_enabled!(target: "windebug_logger", level) {
+        enter();
+        Some(Inner {
+            start: Instant::now(),
+            level,
+            label: label.into(),
+            location: Location::caller(),
+        })
+    } else {
+        None
+    };
+    ScopeTimer {
+        inner,
+        _not_send: PhantomData,
+    }
+}
+
+/// Logs the time elapsed since its creation when dropped. Returned by
+/// [`time_scope`].
+#[must_use = "the time is logged when the guard is dropped"]
+pub struct ScopeTimer {
+    inner: Option<Inner>,
+    /// The timer must be dropped on the thread whose nesting depth it
+    /// incremented.
+    _not_send: PhantomData<*const ()>,
+}
+
+struct Inner {
+    start: Instant,
+    level: Level,
+    label: Cow<'static, str>,
+    location: &'static Location<'static>,
+}
+
+impl fmt::Debug for ScopeTimer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("ScopeTimer");
+        if let Some(inner) = &self.inner {
+            f.field("level", &inner.level)
+                .field("label", &inner.label)
+                .field("elapsed", &inner.start.elapsed());
+        }
+        f.finish_non_exhaustive()
+    }
+}
+
+impl Drop for ScopeTimer {
+    fn drop(&mut self) {
+        let inner = match &self.inner {
+            Some(inner) => inner,
+            None => return,
+        };
+        // The summary line belongs to the enclosing scope
+        exit();
+        let millis = inner.start.elapsed().as_secs_f64() * 1000.0;
+        log::logger().log(
+            &log::Record::builder()
+                .args(format_args!("{} took {:.3} ms", inner.label, millis))
+                .level(inner.level)
+                .target("windebug_logger")
+                .file(Some(inner.location.file()))
+                .line(Some(inner.location.line()))
+                .build(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled() {
+        let _guard = crate::test::lock();
+        crate::test::reset();
+
+        let timer = time_scope(Level::Error, "disabled");
+        assert!(timer.inner.is_none());
+        assert_eq!(format!("{:?}", timer), "ScopeTimer { .. }");
+        drop(timer);
+        assert_eq!(depth(), 0);
+    }
+
+    #[test]
+    fn test_span_indent() {
+        let _guard = crate::test::lock();
+        let logger = crate::test::install(
+            crate::WinDebugLoggerBuilder::new()
+                .level(log::LevelFilter::Info)
+                .timestamp_fn(|_| {})
+                .ring_buffer(8)
+                .span_indent(2),
+        );
+
+        {
+            let _outer = time_scope(Level::Info, "outer");
+            log::info!(target: "app", "one");
+            {
+                let _inner = time_scope(Level::Info, "inner");
+                log::info!(target: "app", "two");
+            }
+        }
+        // Disabled scopes don't count
+        {
+            let _disabled = time_scope(Level::Trace, "disabled");
+            log::info!(target: "app", "three");
+        }
+        crate::test::reset();
+
+        let history = logger.history.as_ref().unwrap().snapshot();
+        let history: Vec<&str> = history.iter().map(|line| line.trim_end()).collect();
+        assert_eq!(history.len(), 5, "{:?}", history);
+        assert_eq!(history[0], "INFO  [app]   one");
+        assert_eq!(history[1], "INFO  [app]     two");
+        assert!(history[2].starts_with("INFO  [windebug_logger]   inner took "));
+        assert!(history[3].starts_with("INFO  [windebug_logger] outer took "));
+        assert_eq!(history[4], "INFO  [app] three");
+        assert_eq!(depth(), 0);
+    }
+}
@@ -0,0 +1,93 @@
+//! Timestamp formatting using the `time` crate. See
+//! [`WinDebugLoggerBuilder::timestamp_format_description`].
+//!
+//! [`WinDebugLoggerBuilder::timestamp_format_description`]: crate::WinDebugLoggerBuilder::timestamp_format_description
+use std::mem::MaybeUninit;
+use time::{format_description::OwnedFormatItem, OffsetDateTime, UtcOffset};
+use winapi::{
+    shared::minwindef::FILETIME,
+    um::{fileapi, sysinfoapi},
+};
+
+use crate::format::UNIX_EPOCH_FILETIME;
+
+/// Render the time `at` (in nanoseconds since the Unix epoch), or the current
+/// time if `None`, with `format`.
+///
+/// The local time zone's current offset is applied to `at` as well.
+pub fn format_at(format: &OwnedFormatItem, utc: bool, at: Option<i128>) -> Option<String> {
+    let utc_ft = system_time_as_file_time();
+
+    let nanos = at.unwrap_or((filetime_to_i64(&utc_ft) - UNIX_EPOCH_FILETIME) as i128 * 100);
+
+    let time = if utc {
+        OffsetDateTime::from_unix_timestamp_nanos(nanos).ok()?
+    } else {
+        let local_ft = unsafe {
+            let mut ft = MaybeUninit::uninit();
+            if fileapi::FileTimeToLocalFileTime(&utc_ft, ft.as_mut_ptr()) == 0 {
+                return None;
+            }
+            ft.assume_init()
+        };
+        let offset_secs = (filetime_to_i64(&local_ft) - filetime_to_i64(&utc_ft)) / 10_000_000;
+        let offset = UtcOffset::from_whole_seconds(offset_secs as i32).ok()?;
+        // Unlike `to_offset`, this fails instead of panicking if the local
+        // time is out of range
+        OffsetDateTime::from_unix_timestamp_nanos(nanos + offset_secs as i128 * 1_000_000_000)
+            .ok()?
+            .replace_offset(offset)
+    };
+
+    time.format(format).ok()
+}
+
+/// Get the current time in UTC, using `GetSystemTimePreciseAsFileTime`
+/// (Windows 8 and later) if available.
+#[cfg(not(feature = "compat-xp"))]
+fn system_time_as_file_time() -> FILETIME {
+    use std::{mem::transmute, sync::OnceLock};
+    use winapi::um::libloaderapi;
+
+    type GetSystemTimePreciseAsFileTimeFn = unsafe extern "system" fn(*mut FILETIME);
+    static PRECISE: OnceLock<Option<GetSystemTimePreciseAsFileTimeFn>> = OnceLock::new();
+
+    let precise = PRECISE.get_or_init(|| unsafe {
+        let kernel32 = libloaderapi::GetModuleHandleW(wchar::wch_c!("kernel32.dll").as_ptr());
+        if kernel32.is_null() {
+            return None;
+        }
+        let proc = libloaderapi::GetProcAddress(
+            kernel32,
+            b"GetSystemTimePreciseAsFileTime\0".as_ptr() as _,
+        );
+        if proc.is_null() {
+            return None;
+        }
+        let precise: GetSystemTimePreciseAsFileTimeFn = transmute(proc);
+        Some(precise)
+    });
+
+    unsafe {
+        let mut ft = MaybeUninit::uninit();
+        match *precise {
+            Some(precise) => precise(ft.as_mut_ptr()),
+            None => sysinfoapi::GetSystemTimeAsFileTime(ft.as_mut_ptr()),
+        }
+        ft.assume_init()
+    }
+}
+
+/// Get the current time in UTC.
+#[cfg(feature = "compat-xp")]
+fn system_time_as_file_time() -> FILETIME {
+    unsafe {
+        let mut ft = MaybeUninit::uninit();
+        sysinfoapi::GetSystemTimeAsFileTime(ft.as_mut_ptr());
+        ft.assume_init()
+    }
+}
+
+fn filetime_to_i64(ft: &FILETIME) -> i64 {
+    ((ft.dwHighDateTime as i64) << 32) | ft.dwLowDateTime as i64
+}
@@ -0,0 +1,65 @@
+//! Forwards the debug output of a child process, which is this test binary
+//! run again with `CHILD_ENV` set. This is an integration test because both
+//! processes install the global logger. It fails if another debug output
+//! monitor, such as DebugView, is running.
+use log::Level;
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    process::{Command, Stdio},
+    thread,
+};
+use winapi::um::winnls::CP_UTF8;
+use windebug_logger::{capture::CaptureSink, WinDebugLoggerBuilder};
+
+const CHILD_ENV: &str = "WINDEBUG_LOGGER_FORWARD_CHILD";
+
+#[test]
+fn test_forward_from_pid() {
+    if env::var_os(CHILD_ENV).is_some() {
+        run_child();
+        return;
+    }
+
+    let (sink, captured) = CaptureSink::new();
+    WinDebugLoggerBuilder::new()
+        .timestamp_fn(|_| {})
+        .sink(sink)
+        .init()
+        .unwrap();
+
+    let mut child = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "test_forward_from_pid"])
+        .env(CHILD_ENV, "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    let forwarder = windebug_logger::forward_from_pid(child.id(), Level::Info, CP_UTF8).unwrap();
+
+    // Let the child log now that its output is received
+    child.stdin.take().unwrap().write_all(b"\n").unwrap();
+    assert!(child.wait().unwrap().success());
+    // The strings written before the exit are forwarded before the
+    // forwarder notices the exit and finishes
+    while !forwarder.is_finished() {
+        thread::yield_now();
+    }
+
+    let expected = format!(
+        "INFO  [windebug_logger::child] [child {}] WARN  [child] h\u{e9}llo \u{2713}\n",
+        child.id()
+    );
+    let lines = captured.lines();
+    assert!(lines.contains(&expected), "{:?}", lines);
+}
+
+fn run_child() {
+    WinDebugLoggerBuilder::new()
+        .timestamp_fn(|_| {})
+        .code_page(CP_UTF8)
+        .init()
+        .unwrap();
+    io::stdin().lock().read_line(&mut String::new()).unwrap();
+    log::warn!(target: "child", "h\u{e9}llo \u{2713}");
+}
@@ -0,0 +1,33 @@
+//! Initializes the global logger from many threads at once. This is an
+//! integration test because the global logger can be installed only once
+//! per process.
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+};
+
+#[test]
+fn test_concurrent_init_with_level() {
+    const THREADS: usize = 10;
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let results: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                windebug_logger::init_with_level(log::Level::Info).is_ok()
+            })
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect();
+
+    assert_eq!(results.iter().filter(|&&ok| ok).count(), 1, "{:?}", results);
+
+    // The logger that won is the one `global` returns
+    let logger = windebug_logger::global().expect("`global` returned `None`");
+    let global = log::logger() as *const dyn log::Log as *const ();
+    assert_eq!(global, logger as *const _ as *const ());
+    assert_eq!(log::max_level(), log::LevelFilter::Info);
+}
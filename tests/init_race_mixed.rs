@@ -0,0 +1,66 @@
+//! Initializes the global logger from many threads at once through the
+//! various initialization functions. See `init_race.rs`.
+use log::{Level, LevelFilter};
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+};
+use windebug_logger::{WinDebugLogger, WinDebugLoggerBuilder};
+
+/// Call the `i`-th initialization function, returning the level filter it
+/// would install and whether it succeeded.
+fn init(i: usize) -> (LevelFilter, bool) {
+    match i % 6 {
+        0 => (LevelFilter::Trace, windebug_logger::init().is_ok()),
+        1 => (
+            LevelFilter::Debug,
+            windebug_logger::init_with_level(Level::Debug).is_ok(),
+        ),
+        2 => (
+            LevelFilter::Info,
+            windebug_logger::init_with_level_static!(Level::Info).is_ok(),
+        ),
+        3 => (
+            LevelFilter::Warn,
+            WinDebugLogger::new(Level::Warn).install().is_ok(),
+        ),
+        4 => (
+            LevelFilter::Error,
+            WinDebugLoggerBuilder::new()
+                .level(LevelFilter::Error)
+                .init()
+                .is_ok(),
+        ),
+        _ => (
+            LevelFilter::Off,
+            windebug_logger::init_with_level_str("off").is_ok(),
+        ),
+    }
+}
+
+#[test]
+fn test_concurrent_init_mixed() {
+    const THREADS: usize = 24;
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let results: Vec<_> = (0..THREADS)
+        .map(|i| {
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                init(i)
+            })
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect();
+
+    let winners: Vec<_> = results.iter().filter(|(_, ok)| *ok).collect();
+    assert_eq!(winners.len(), 1, "{:?}", results);
+
+    // The losers left the level filter of the winner in place
+    let (level, _) = winners[0];
+    assert_eq!(log::max_level(), *level, "{:?}", results);
+    #[cfg(not(feature = "build-script"))]
+    assert_eq!(windebug_logger::global().unwrap().level(), *level);
+}
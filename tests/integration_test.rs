@@ -0,0 +1,30 @@
+//! Tests of the public API that need the `test-util` feature, which the
+//! dev-dependency on this crate enables for `cargo test`.
+#![cfg(target_os = "windows")]
+use windebug_logger::{capture::CaptureSink, WinDebugLogger, WinDebugLoggerBuilder};
+
+/// `%` sequences, which `FormatMessageW` would expand as inserts, are written
+/// verbatim.
+#[test]
+fn test_percent_signs_are_verbatim() {
+    const MESSAGE: &str = "test %1 %2 %3 %% %%1 100% done";
+
+    let (sink, lines) = CaptureSink::new();
+    let logger = WinDebugLogger::from_builder(&WinDebugLoggerBuilder::new().sink(sink));
+    log::Log::log(
+        &logger,
+        &log::Record::builder()
+            .args(format_args!("{}", MESSAGE))
+            .level(log::Level::Error)
+            .target("app")
+            .build(),
+    );
+
+    let lines = lines.lines();
+    assert_eq!(lines.len(), 1, "{:?}", lines);
+    assert!(
+        lines[0].ends_with(&format!(" [app] {}\n", MESSAGE)),
+        "{:?}",
+        lines
+    );
+}
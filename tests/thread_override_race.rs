@@ -0,0 +1,36 @@
+//! Creates and drops per-thread level overrides from many threads at once
+//! while another override stays active. This is an integration test because
+//! the overrides adjust `log`'s global filter, which is per process.
+use log::{Level, LevelFilter};
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+};
+use windebug_logger::WinDebugLogger;
+
+#[test]
+fn test_concurrent_thread_overrides() {
+    const THREADS: usize = 8;
+    windebug_logger::init_with_level(Level::Error).unwrap();
+
+    let _guard = WinDebugLogger::enable_thread_override(Level::Trace);
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..1000 {
+                    let _guard = WinDebugLogger::enable_thread_override(Level::Debug);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // A stale value computed by one of the threads must not have lowered the
+    // global filter below the override that is still active
+    assert_eq!(log::max_level(), LevelFilter::Trace);
+}